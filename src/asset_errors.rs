@@ -0,0 +1,67 @@
+use std::collections::BTreeMap;
+
+use bevy::{asset::AssetLoadFailedEvent, prelude::*};
+
+// 资源文件加载失败的错误，按文件路径记录，文件修改后重新加载成功时清除
+#[derive(Debug, Default, Resource)]
+pub struct AssetErrors(pub BTreeMap<String, String>);
+
+// 显示资源错误的文字，显示在所有界面之上
+#[derive(Debug, Component)]
+pub struct AssetErrorText;
+
+pub fn track_asset_errors<A: Asset>(
+    asset_server: Res<AssetServer>,
+    mut failed_events: EventReader<AssetLoadFailedEvent<A>>,
+    mut asset_events: EventReader<AssetEvent<A>>,
+    mut errors: ResMut<AssetErrors>,
+) {
+    for event in failed_events.read() {
+        error!("{}", event.error);
+        errors.0.insert(event.path.to_string(), event.error.to_string());
+    }
+    for event in asset_events.read() {
+        let (AssetEvent::LoadedWithDependencies { id } | AssetEvent::Modified { id }) = event else {
+            continue;
+        };
+        let Some(path) = asset_server.get_path(*id).map(|path| path.to_string()) else {
+            continue;
+        };
+        if errors.0.contains_key(&path) {
+            errors.0.remove(&path);
+        }
+    }
+}
+
+pub fn setup_asset_error_text(mut commands: Commands) {
+    commands.spawn((
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font_size: 18.0,
+                color: Color::srgb(1.0, 0.3, 0.3),
+                ..default()
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            top: Val::Px(10.0),
+            left: Val::Px(10.0),
+            ..default()
+        }),
+        ZIndex::Global(100),
+        AssetErrorText,
+    ));
+}
+
+pub fn update_asset_error_text(
+    errors: Res<AssetErrors>,
+    mut query: Query<&mut Text, With<AssetErrorText>>,
+) {
+    if !errors.is_changed() {
+        return;
+    }
+    for mut text in &mut query {
+        text.sections[0].value = errors.0.values().cloned().collect::<Vec<_>>().join("\n");
+    }
+}
@@ -0,0 +1,20 @@
+use std::process::ExitCode;
+
+use tetris_game::headless::{run, SimulationOptions, USAGE};
+
+// 无界面批量模拟：由机器人或回放文件操作，每局结束后输出一行 JSON
+fn main() -> ExitCode {
+    let options = match SimulationOptions::from_args(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("{err}");
+            eprintln!("{USAGE}");
+            return ExitCode::FAILURE;
+        }
+    };
+    if let Err(err) = run(&options) {
+        eprintln!("{err}");
+        return ExitCode::FAILURE;
+    }
+    ExitCode::SUCCESS
+}
@@ -1,7 +1,22 @@
 use bevy::prelude::*;
+use rand::Rng;
 
 use crate::{
-    common::AppState, piece::{piece_shape, PieceQueue, PieceType}, state::{base_offset, spawn_next_piece_board}
+    common::{AppState, GameEntity, RestartGame},
+    layout::ui_root_size,
+    mode::{GameConfig, GameMode},
+    network::{NetSession, RemotePlayer},
+    menu::{spawn_menu_button, spawn_menu_panel, spawn_menu_text, spawn_menu_title, MenuButton},
+    piece::{ClearingLines, PieceQueue, PieceType},
+    piece_set::PieceSet,
+    rule_set::RuleSet,
+    player::{GameSeed, Player, PlayerBundle},
+    state::{
+        BASE_OFFSET, format_duration, spawn_next_piece_board, Level, Lines, Score, SessionStats,
+        STATS_BOARD_LENGTH,
+    },
+    theme::{BlockSkin, BoardBackground, BoardFrame, GridLine},
+    versus::spawn_garbage_meter,
 };
 
 // game board宽高
@@ -14,12 +29,15 @@ pub const BLOCK_LENGTH: f32 = 30.0;
 // 正方形方块贴纸边长
 pub const BLOCK_STICKER_LENGTH: f32 = 28.0;
 
-// game board 边界厚度
-pub const BORDER_THICKNESS: f32 = 10.0;
-pub const BORDER_COLOR: Color = Color::srgb(0.8, 0.8, 0.8);
+// 面板中各层的 z 值，面板本身在 z = 0
+pub const BOARD_BACKGROUND_Z: f32 = 0.0;
+pub const GRID_Z: f32 = 0.1;
+pub const HINT_Z: f32 = 0.5;
+pub const BLOCK_Z: f32 = 1.0;
+pub const PARTICLE_Z: f32 = 2.0;
 
 // 方块
-#[derive(Component, Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub struct Block {
     pub x: i32,
     pub y: i32,
@@ -28,66 +46,74 @@ pub struct Block {
 impl Block {
     pub fn transform_to(&self) -> Vec2 {
         Vec2 {
-            x: (self.x + base_offset.0) as f32 * BLOCK_LENGTH,
-            y: (self.y + base_offset.1) as f32 * BLOCK_LENGTH,
+            x: (self.x + BASE_OFFSET.0) as f32 * BLOCK_LENGTH,
+            y: (self.y + BASE_OFFSET.1) as f32 * BLOCK_LENGTH,
         }
     }
 
     pub fn transform_to_real_pos(&self) -> (i32, i32) {
-        (self.x + base_offset.0, self.y + base_offset.1)
+        (self.x + BASE_OFFSET.0, self.y + BASE_OFFSET.1)
     }
 }
 
+// 面板中的位置（像素，以面板左上角为原点，y 向下）转换为面板实体下的局部坐标（y 向上），取方块中心
+pub fn board_translation(position: Vec2, z: f32) -> Vec3 {
+    Vec3::new(
+        position.x + BLOCK_LENGTH / 2.0,
+        -(position.y + BLOCK_LENGTH / 2.0),
+        z,
+    )
+}
+
 impl From<[i32; 2]> for Block {
     fn from([x, y]: [i32; 2]) -> Self {
         Block { x, y }
     }
 }
 
-// 装方块的容器
+// 装方块的容器，每个玩家一个，玩家的游戏状态（骨牌队列、分数、计时器等）也挂在该实体上
 #[derive(Component)]
 pub struct MainBoard;
 
 #[derive(Debug, Component)]
 pub struct NextPieceBoard;
 
+// 游戏面板、下一个骨牌面板和编辑器面板用精灵绘制在世界坐标中，界面中只保留一个占位节点参与布局，
+// 面板实体每帧对齐到占位节点的左上角
+#[derive(Debug, Component)]
+pub struct FollowNode(pub Entity);
+
+// 游戏面板旁边的一列，放置下一个骨牌和对战时的玩家信息
+#[derive(Debug, Component)]
+pub struct PlayerPanel;
+
+// 单人游戏时面板左边的一列，放置分数、行数和模式信息
+#[derive(Debug, Component)]
+pub struct StatsPanel;
+
+// 方块溢出的面板
+#[derive(Debug, Component)]
+pub struct ToppedOut;
+
 // 展示下一个骨牌
-#[derive(Resource)]
+#[derive(Component)]
 pub struct HasNextPiece(pub bool);
 
-pub fn setup_game_board(mut commands: Commands) {
-    let border_size: f32 = 1.0;
-    let main_board = commands
-        .spawn(NodeBundle {
-            style: Style {
-                width: Val::Px(BLOCK_LENGTH * COL_COUNT as f32 + border_size),
-                height: Val::Px(BLOCK_LENGTH * ROW_COUNT as f32 + border_size),
-                position_type: PositionType::Absolute,
-                border: UiRect::all(Val::Px(border_size)),
-                overflow:Overflow::clip(),
-                ..Default::default()
-            },
-            border_color: BorderColor(Color::srgb(0.5, 0.5, 1.0)),
-            ..default()
-        })
-        .insert(MainBoard)
-        .id();
-
-    let next_piece_board = commands
-        .spawn(NodeBundle {
-            style: Style {
-                width: Val::Px(BLOCK_LENGTH * 4 as f32),
-                height: Val::Px(BLOCK_LENGTH * 4 as f32),
-                position_type: PositionType::Absolute,
-                top: Val::Px(80 as f32),
-                right: Val::Px(150 as f32),
-                ..Default::default()
-            },
-            ..default()
-        })
-        .insert(NextPieceBoard)
-        .id();
+pub fn setup_game_board(
+    mut commands: Commands,
+    config: Res<GameConfig>,
+    piece_set: Res<PieceSet>,
+    rules: Res<RuleSet>,
+) {
+    // 所有玩家共用一个种子，拿到相同的骨牌序列
+    let seed = GameSeed(config.seed.unwrap_or_else(|| rand::thread_rng().gen()));
+    commands.insert_resource(seed);
+    let player_count = config.mode.player_count();
+    let board_width = BLOCK_LENGTH * COL_COUNT as f32;
+    let board_height = BLOCK_LENGTH * ROW_COUNT as f32;
 
+    // 界面中的占位节点：(玩家, 游戏面板, 下一个骨牌面板)
+    let mut slots: Vec<(Player, Entity, Entity)> = Vec::new();
     commands
         .spawn(NodeBundle {
             style: Style {
@@ -95,35 +121,212 @@ pub fn setup_game_board(mut commands: Commands) {
                 height: Val::Percent(100.),
                 justify_content: JustifyContent::Center, // 在主轴方向上居中
                 align_items: AlignItems::Center,
+                column_gap: Val::Px(40.),
                 ..Default::default()
             },
             ..default()
         })
-        .add_child(main_board)
-        .add_child(next_piece_board);
+        .insert(GameEntity)
+        .with_children(|parent| {
+            for index in 0..player_count {
+                let player = Player(index);
+                parent
+                    .spawn(NodeBundle {
+                        style: Style {
+                            align_items: AlignItems::FlexStart,
+                            column_gap: Val::Px(10.),
+                            ..default()
+                        },
+                        ..default()
+                    })
+                    .with_children(|parent| {
+                        if player_count > 1 {
+                            spawn_garbage_meter(parent, player, board_height);
+                        } else {
+                            // 单人游戏的分数等信息在面板左边，同样不参与布局
+                            parent.spawn((
+                                NodeBundle {
+                                    style: Style {
+                                        position_type: PositionType::Absolute,
+                                        right: Val::Px(board_width + 30.),
+                                        width: Val::Px(STATS_BOARD_LENGTH - 30.),
+                                        flex_direction: FlexDirection::Column,
+                                        ..default()
+                                    },
+                                    ..default()
+                                },
+                                StatsPanel,
+                            ));
+                        }
+                        // 边框画在节点外面，节点的大小正好是面板的大小
+                        let board_slot = parent
+                            .spawn(NodeBundle {
+                                style: Style {
+                                    width: Val::Px(board_width),
+                                    height: Val::Px(board_height),
+                                    ..Default::default()
+                                },
+                                ..default()
+                            })
+                            .insert((BoardFrame, Outline::new(Val::Px(1.0), Val::ZERO, Color::NONE)))
+                            .id();
+
+                        // 单人游戏时面板不参与布局，游戏面板保持在窗口中间
+                        let panel_style = if player_count > 1 {
+                            Style {
+                                flex_direction: FlexDirection::Column,
+                                row_gap: Val::Px(10.),
+                                ..default()
+                            }
+                        } else {
+                            Style {
+                                position_type: PositionType::Absolute,
+                                left: Val::Px(board_width + 30.),
+                                ..default()
+                            }
+                        };
+                        let mut next_slot = Entity::PLACEHOLDER;
+                        parent
+                            .spawn(NodeBundle {
+                                style: panel_style,
+                                ..default()
+                            })
+                            .insert((PlayerPanel, player))
+                            .with_children(|parent| {
+                                next_slot = parent
+                                    .spawn(NodeBundle {
+                                        style: Style {
+                                            width: Val::Px(BLOCK_LENGTH * 4.0),
+                                            height: Val::Px(BLOCK_LENGTH * 4.0),
+                                            ..Default::default()
+                                        },
+                                        ..default()
+                                    })
+                                    .id();
+                            });
+                        slots.push((player, board_slot, next_slot));
+                    });
+            }
+        });
+
+    // 面板和方块在世界坐标中，对齐到上面的占位节点
+    for (player, board_slot, next_slot) in slots {
+        commands
+            .spawn((SpatialBundle::default(), MainBoard, GameEntity, FollowNode(board_slot)))
+            .insert(PlayerBundle::new(player, player_count, &config, &piece_set, &rules, seed))
+            .with_children(spawn_board_background);
+        commands.spawn((
+            SpatialBundle::default(),
+            NextPieceBoard,
+            player,
+            GameEntity,
+            FollowNode(next_slot),
+        ));
+    }
+}
+
+// 面板实体对齐到占位节点的左上角，窗口大小改变或布局变化后也能跟上。
+// 在布局之后、变换传播之前运行，节点的 GlobalTransform 还没有更新，沿父节点累加 Transform 得到节点的位置
+pub fn follow_ui_nodes(
+    ui_scale: Res<UiScale>,
+    cameras: Query<&Camera, With<Camera2d>>,
+    nodes: Query<(&Node, &Transform, Option<&Parent>), Without<FollowNode>>,
+    mut boards: Query<(&FollowNode, &mut Transform)>,
+) {
+    let Some(root_size) = cameras
+        .get_single()
+        .ok()
+        .and_then(|camera| ui_root_size(camera, &ui_scale))
+    else {
+        return;
+    };
+    // 界面坐标以左上角为原点、y 向下，世界坐标以中心为原点、y 向上，两者单位相同
+    for (follow_node, mut transform) in &mut boards {
+        let Ok((node, node_transform, mut parent)) = nodes.get(follow_node.0) else {
+            continue;
+        };
+        let mut center = node_transform.translation.truncate();
+        while let Some((_, parent_transform, grandparent)) =
+            parent.and_then(|parent| nodes.get(parent.get()).ok())
+        {
+            center += parent_transform.translation.truncate();
+            parent = grandparent;
+        }
+        let top_left = center - node.size() / 2.0;
+        let translation = Vec3::new(
+            top_left.x - root_size.x / 2.0,
+            root_size.y / 2.0 - top_left.y,
+            transform.translation.z,
+        );
+        if transform.translation != translation {
+            transform.translation = translation;
+        }
+    }
+}
+
+// 面板的背景和网格线，颜色由主题决定，没有网格的主题中网格线为透明
+pub fn spawn_board_background(parent: &mut ChildBuilder) {
+    let width = BLOCK_LENGTH * COL_COUNT as f32;
+    let height = BLOCK_LENGTH * ROW_COUNT as f32;
+    // 以面板左上角为原点、y 向下的矩形
+    let rect = |left: f32, top: f32, width: f32, height: f32, z: f32| SpriteBundle {
+        sprite: Sprite {
+            custom_size: Some(Vec2::new(width, height)),
+            ..default()
+        },
+        transform: Transform::from_xyz(left + width / 2.0, -(top + height / 2.0), z),
+        ..default()
+    };
+    parent.spawn((rect(0.0, 0.0, width, height, BOARD_BACKGROUND_Z), BoardBackground));
+    for x in 1..COL_COUNT {
+        parent.spawn((rect(x as f32 * BLOCK_LENGTH, 0.0, 1.0, height, GRID_Z), GridLine));
+    }
+    for y in 1..ROW_COUNT {
+        parent.spawn((rect(0.0, y as f32 * BLOCK_LENGTH, width, 1.0, GRID_Z), GridLine));
+    }
 }
 
 pub fn update_next_piece_board(
     mut commands: Commands,
-    piece_queue: Res<PieceQueue>,
-    nest_piece_board_query: Query<Entity, With<NextPieceBoard>>,
+    piece_set: Res<PieceSet>,
+    mut main_board: Query<(&Player, &PieceQueue, &mut HasNextPiece), With<MainBoard>>,
+    nest_piece_board_query: Query<(Entity, &Player), With<NextPieceBoard>>,
     //  Bevy 中，实体和子实体之间的关系是通过 Children 组件来维护的。如果你想遍历某个实体的所有子实体，你可以使用 Query 来获取 Children 组件
     children_query: Query<&Children>,
-    mut has_next_piece: ResMut<HasNextPiece>,
 ) {
-    if !has_next_piece.0 {
-        let next_piece = piece_queue.0.front().unwrap();
-        let piece_type = next_piece.piece_type;
-        let color = next_piece.color;
-        let blocks = piece_shape(piece_type);
-        let next_piece_board = nest_piece_board_query.single();
+    for (player, piece_queue, mut has_next_piece) in &mut main_board {
+        if has_next_piece.0 {
+            continue;
+        }
+        let Some((next_piece_board, _)) = nest_piece_board_query
+            .iter()
+            .find(|(_, board_player)| *board_player == player)
+        else {
+            continue;
+        };
         if let Ok(children) = children_query.get(next_piece_board) {
             for child in children.iter() {
                 //despawn_recursive 方法用于递归地销毁一个实体及其所有子实体
                 commands.entity(*child).despawn_recursive();
             }
         }
-        spawn_next_piece_board(&mut commands, next_piece_board, blocks, color);
+        // 谜题模式的骨牌用完后不再显示
+        let next_piece = piece_queue
+            .0
+            .front()
+            .and_then(|next_piece| {
+                let piece = piece_set.get(next_piece.piece_type)?;
+                Some((piece, next_piece.piece_type, next_piece.color))
+            });
+        if let Some((piece, piece_type, color)) = next_piece {
+            spawn_next_piece_board(
+                &mut commands,
+                next_piece_board,
+                &piece.preview(),
+                color,
+                BlockSkin::Piece(piece_type),
+            );
+        }
         has_next_piece.0 = true;
     }
 }
@@ -132,93 +335,167 @@ pub fn update_next_piece_board(
 
 // 检测容器是否溢出 游戏结束
 pub fn check_game_overs(
+    mut commands: Commands,
     mut app_state: ResMut<NextState<AppState>>,
-    board_query: Query<&Block, Without<PieceType>>,
+    // 消行动画结束前消除的行还没有下移，等下移后再检测
+    main_board: Query<
+        Entity,
+        (With<MainBoard>, Without<ToppedOut>, Without<RemotePlayer>, Without<ClearingLines>),
+    >,
+    board_query: Query<(&Parent, &Block), Without<PieceType>>,
 ) {
-    // 检查是否碰撞边界
-    let mut max_block_y = ROW_COUNT as i32;
-    for board_block in board_query.iter() {
-        let cur = board_block.transform_to_real_pos();
-        if cur.1<max_block_y {
-            max_block_y = cur.1;
+    for main_board_entity in &main_board {
+        // 检查是否碰撞边界
+        let mut max_block_y = ROW_COUNT as i32;
+        for (parent, board_block) in board_query.iter() {
+            if parent.get() != main_board_entity {
+                continue;
+            }
+            let cur = board_block.transform_to_real_pos();
+            if cur.1<max_block_y {
+                max_block_y = cur.1;
+            }
+        }
+
+
+        if max_block_y < 0 {
+            commands.entity(main_board_entity).insert(ToppedOut);
+            app_state.set(AppState::GameOver);
         }
     }
+}
 
 
-    if max_block_y < 0 {
-        app_state.set(AppState::GameOver);
+// 暂停时隐藏游戏面板 防止玩家暂停后研究棋盘
+pub fn hide_game_board(
+    mut query: Query<&mut Visibility, Or<(With<MainBoard>, With<NextPieceBoard>)>>,
+) {
+    for mut visibility in &mut query {
+        *visibility = Visibility::Hidden;
     }
 }
 
+// 恢复游戏时重新显示游戏面板
+pub fn show_game_board(
+    mut query: Query<&mut Visibility, Or<(With<MainBoard>, With<NextPieceBoard>)>>,
+) {
+    for mut visibility in &mut query {
+        *visibility = Visibility::Inherited;
+    }
+}
 
-#[derive(Component,Debug)]
-pub struct QuitButton;
+// 重新开始游戏：销毁当前这局的所有实体，进入 InGame 时会重新生成面板、队列和计时器
+pub fn restart_game(
+    mut commands: Commands,
+    mut restart_events: EventReader<RestartGame>,
+    game_entity_query: Query<Entity, With<GameEntity>>,
+    mut app_state: ResMut<NextState<AppState>>,
+) {
+    if restart_events.read().last().is_none() {
+        return;
+    }
+    for entity in &game_entity_query {
+        commands.entity(entity).despawn_recursive();
+    }
+    app_state.set(AppState::InGame);
+}
 
+// 销毁一局游戏中生成的实体
+pub fn despawn_game_entities(
+    mut commands: Commands,
+    game_entity_query: Query<Entity, With<GameEntity>>,
+) {
+    for entity in &game_entity_query {
+        commands.entity(entity).despawn_recursive();
+    }
+}
 
+// 结算界面（游戏失败或完成模式目标），展示本局统计数据
 pub fn show_game_over_menu(
     mut commands: Commands,
+    app_state: Res<State<AppState>>,
+    config: Res<GameConfig>,
+    session: Option<Res<NetSession>>,
+    main_board: Query<
+        (&Player, &Score, &Lines, &Level, &SessionStats, Has<ToppedOut>),
+        With<MainBoard>,
+    >,
 ) {
+    // 谜题模式有单独的成功/失败界面
+    if config.mode == GameMode::Puzzle {
+        return;
+    }
+    let state = app_state.get().clone();
+    let mut players: Vec<_> = main_board.iter().collect();
+    players.sort_by_key(|(player, ..)| player.0);
 
-    commands
-    .spawn((
-        NodeBundle {
-            style: Style {
-                width: Val::Percent(100.),
-                height: Val::Percent(100.),
-                justify_content: JustifyContent::Center,
-                align_items: AlignItems::Center,
-                flex_direction: FlexDirection::Column,
-                row_gap: Val::Px(10.),
-                position_type: PositionType::Absolute,
-                ..default()
-            },
-            ..default()
-        },
-    ))
-    .with_children(|parent| {
-        parent
-            .spawn((
-                NodeBundle {
-                    style: Style {
-                        width: Val::Px(400.),
-                        height: Val::Px(400.),
-                        justify_content: JustifyContent::Center,
-                        align_items: AlignItems::Center,
-                        ..default()
-                    },
-                    background_color: BackgroundColor::from(Color::srgb(0.1, 0.1, 0.1)),
-                    ..default()
-                },
-            ))
-            .with_children(|parent| {
-                parent
-                        .spawn((
-                            ButtonBundle {
-                                style: Style {
-                                    width: Val::Px(200.0),
-                                    height: Val::Px(50.0),
-                                    margin: UiRect::all(Val::Px(10.0)),
-                                    justify_content: JustifyContent::Center,
-                                    align_items: AlignItems::Center,
-                                    ..default()
-                                },
-                                image: UiImage::default()
-                                    .with_color(Color::srgb(0.15, 0.15, 0.15).into()),
-                                ..default()
-                            },
-                            QuitButton
-                        ))
-                        .with_children(|parent| {
-                            parent.spawn(TextBundle::from_section(
-                                "GAME OVER",
-                                TextStyle {
-                                    font_size: 40.0,
-                                    color: Color::srgb(0.9, 0.9, 0.9),
-                                    ..default()
-                                },
-                            ));
-                        });
-               
-            });
+    if config.mode.player_count() > 1 {
+        // 对战：没有溢出的一方获胜，同时溢出为平局
+        let survivors: Vec<Player> = players
+            .iter()
+            .filter(|(.., topped_out)| !topped_out)
+            .map(|(player, ..)| **player)
+            .collect();
+        let title = match survivors.as_slice() {
+            [winner] => format!("{} WINS", winner.name().to_uppercase()),
+            _ => "DRAW".to_string(),
+        };
+        spawn_menu_panel(&mut commands, state, |parent| {
+            spawn_menu_title(parent, &title);
+            if let Some(session) = session.as_ref() {
+                if session.disconnected {
+                    spawn_menu_text(parent, "OPPONENT DISCONNECTED");
+                }
+                spawn_menu_text(parent, &format!("You are {}", session.local_player.name()));
+            }
+            for (player, score, lines, _, stats, _) in players.iter() {
+                spawn_menu_text(
+                    parent,
+                    &format!(
+                        "{}: Score {}  Lines {}  Sent {}  PPS {:.2}  Faults {}",
+                        player.name(),
+                        score.0,
+                        lines.0,
+                        stats.garbage_sent,
+                        stats.pieces_per_second(),
+                        stats.finesse_faults
+                    ),
+                );
+            }
+            // 网络对战需要双方重新连接才能再来一局
+            if session.is_none() {
+                spawn_menu_button(parent, "Play Again", MenuButton::Restart);
+            }
+            spawn_menu_button(parent, "Main Menu", MenuButton::MainMenu);
+            spawn_menu_button(parent, "Quit", MenuButton::Quit);
+        });
+        return;
+    }
+
+    let Some((_, score, lines, level, stats, _)) = players.first() else {
+        return;
+    };
+    let title = match state {
+        AppState::Victory => format!("{} COMPLETE", config.mode.name().to_uppercase()),
+        AppState::TimeUp => "TIME'S UP".to_string(),
+        _ => "GAME OVER".to_string(),
+    };
+    spawn_menu_panel(&mut commands, state, |parent| {
+        spawn_menu_title(parent, &title);
+        if stats.new_personal_best {
+            spawn_menu_text(parent, "NEW PERSONAL BEST!");
+        }
+        spawn_menu_text(parent, &format!("Score: {}", score.0));
+        spawn_menu_text(parent, &format!("Lines: {}", lines.0));
+        spawn_menu_text(parent, &format!("Level: {}", level.0));
+        spawn_menu_text(parent, &format!("Time: {}", format_duration(stats.play_time)));
+        spawn_menu_text(parent, &format!("Pieces: {}", stats.pieces_placed));
+        spawn_menu_text(parent, &format!("PPS: {:.2}", stats.pieces_per_second()));
+        spawn_menu_text(parent, &format!("Tetrises: {}", stats.tetrises));
+        spawn_menu_text(parent, &format!("T-spins: {}", stats.t_spins));
+        spawn_menu_text(parent, &format!("Finesse faults: {}", stats.finesse_faults));
+        spawn_menu_button(parent, "Play Again", MenuButton::Restart);
+        spawn_menu_button(parent, "Main Menu", MenuButton::MainMenu);
+        spawn_menu_button(parent, "Quit", MenuButton::Quit);
     });
-}
\ No newline at end of file
+}
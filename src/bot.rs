@@ -0,0 +1,545 @@
+use std::collections::{HashMap, VecDeque};
+use std::time::Duration;
+
+use bevy::prelude::*;
+
+use crate::{
+    board::{setup_game_board, Block, MainBoard, COL_COUNT, HINT_Z, ROW_COUNT},
+    common::{AppState, GameSettings},
+    mode::GameConfig,
+    network::{NetSession, RemotePlayer},
+    piece::{movable_flags, move_piece, rotate_blocks, rotate_piece, PieceRotation, PieceType},
+    piece_set::PieceSet,
+    player::{read_keyboard_input, InputMap, Player, PlayerInput},
+    rules::NewGameSetup,
+    state::new_block_sprite,
+};
+
+// 机器人每一步操作的间隔，让玩家能看清它的操作
+const BOT_STEP_INTERVAL: Duration = Duration::from_millis(80);
+
+// 提示方块颜色
+const HINT_COLOR: Color = Color::srgba(1.0, 1.0, 1.0, 0.25);
+
+// 骨牌生成时最高可能在面板上方的行
+const SPAWN_MIN_ROW: i32 = -4;
+
+// 方块溢出时的评分，比任何正常局面都低
+const TOP_OUT_PENALTY: f32 = -1.0e6;
+
+// 机器人：每个骨牌枚举所有能到达的落点，按局面特征加权评分，选择最好的落点并模拟按键操作
+pub struct BotPlugin;
+
+impl Plugin for BotPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<BotWeights>()
+            .add_systems(
+                OnEnter(AppState::InGame),
+                setup_bots.after(setup_game_board).in_set(NewGameSetup),
+            )
+            .add_systems(
+                Update,
+                (
+                    drive_bots
+                        .after(read_keyboard_input)
+                        .before(move_piece)
+                        .before(rotate_piece),
+                    update_hints.run_if(|settings: Res<GameSettings>| settings.show_hints),
+                )
+                    .run_if(in_state(AppState::InGame)),
+            )
+            .add_systems(
+                Update,
+                clear_hints.run_if(|settings: Res<GameSettings>| !settings.show_hints),
+            );
+    }
+}
+
+// 评分权重，正数表示越大越好。新游戏的机器人使用此资源中的权重
+#[derive(Resource, Debug, Clone, Copy, PartialEq)]
+pub struct BotWeights {
+    pub aggregate_height: f32,
+    pub lines_cleared: f32,
+    pub holes: f32,
+    pub bumpiness: f32,
+}
+
+impl Default for BotWeights {
+    fn default() -> Self {
+        Self {
+            aggregate_height: -0.510066,
+            lines_cleared: 0.760666,
+            holes: -0.35663,
+            bumpiness: -0.184483,
+        }
+    }
+}
+
+// 机器人的一步操作
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BotAction {
+    Left,
+    Right,
+    Down,
+    Rotate,
+}
+
+// 一个落点及到达该落点的操作
+#[derive(Debug, Clone)]
+pub struct Placement {
+    pub blocks: Vec<Block>,
+    pub path: Vec<BotAction>,
+    pub score: f32,
+}
+
+// 为当前骨牌选定的落点和到达落点的操作
+#[derive(Debug, Clone)]
+pub struct BotPlan {
+    // states[i] 为执行 path[i] 之前骨牌的位置（排序后），最后一个是落点
+    pub states: Vec<Vec<Block>>,
+    pub path: Vec<BotAction>,
+    // 骨牌当前位于路线的第几步
+    pub step: usize,
+}
+
+impl BotPlan {
+    // 从骨牌当前的位置按落点的操作依次执行，记录每一步之后的位置
+    pub fn new(
+        piece_set: &PieceSet,
+        piece_type: PieceType,
+        blocks: &[Block],
+        rotation: usize,
+        board_blocks: &[Block],
+        placement: Placement,
+    ) -> Option<Self> {
+        let mut states = vec![sorted(blocks)];
+        let mut cur = (blocks.to_vec(), rotation);
+        for action in placement.path.iter() {
+            cur = apply_action(piece_set, piece_type, &cur.0, cur.1, board_blocks, *action)?;
+            states.push(sorted(&cur.0));
+        }
+        Some(Self {
+            states,
+            path: placement.path,
+            step: 0,
+        })
+    }
+
+    // 骨牌在路线上时返回下一步操作，已经到达落点时为 Some(None)；骨牌离开了路线时返回 None
+    pub fn advance(&mut self, blocks: &[Block]) -> Option<Option<BotAction>> {
+        let blocks = sorted(blocks);
+        let offset = self.states[self.step..]
+            .iter()
+            .position(|state| *state == blocks)?;
+        self.step += offset;
+        Some(self.path.get(self.step).copied())
+    }
+}
+
+// 由机器人控制的面板
+#[derive(Component, Debug)]
+pub struct Bot {
+    pub weights: BotWeights,
+    // 当前骨牌的路线，骨牌生成时选定，之后每帧按路线操作
+    pub plan: Option<BotPlan>,
+    pub step_timer: Timer,
+}
+
+impl Default for Bot {
+    fn default() -> Self {
+        Self {
+            weights: BotWeights::default(),
+            plan: None,
+            step_timer: Timer::new(BOT_STEP_INTERVAL, TimerMode::Repeating),
+        }
+    }
+}
+
+// 面板上的提示方块，显示机器人推荐的落点
+#[derive(Component, Debug)]
+pub struct HintBlock;
+
+// 排序后的方块位置，用于比较两个骨牌状态是否相同
+pub fn sorted(blocks: &[Block]) -> Vec<Block> {
+    let mut blocks = blocks.to_vec();
+    blocks.sort_by_key(|block| (block.y, block.x));
+    blocks
+}
+
+// 对骨牌执行一步操作，返回操作后的方块和旋转状态，无法执行时返回 None
+pub fn apply_action(
+    piece_set: &PieceSet,
+    piece_type: PieceType,
+    blocks: &[Block],
+    rotation: usize,
+    board_blocks: &[Block],
+    action: BotAction,
+) -> Option<(Vec<Block>, usize)> {
+    let movable = movable_flags(blocks, board_blocks);
+    let (dx, dy) = match action {
+        BotAction::Left if movable.can_left => (-1, 0),
+        BotAction::Right if movable.can_right => (1, 0),
+        BotAction::Down if movable.can_down => (0, 1),
+        BotAction::Rotate => {
+            return rotate_blocks(piece_set, piece_type, blocks, rotation, board_blocks);
+        }
+        _ => return None,
+    };
+    let moved = blocks
+        .iter()
+        .map(|block| Block {
+            x: block.x + dx,
+            y: block.y + dy,
+        })
+        .collect();
+    Some((moved, rotation))
+}
+
+// 旋转的踢墙可能把骨牌推出面板或不断上移，搜索时只保留面板范围内的状态
+pub fn in_search_bounds(blocks: &[Block]) -> bool {
+    blocks.iter().all(|block| {
+        let (x, y) = block.transform_to_real_pos();
+        (0..COL_COUNT as i32).contains(&x) && (SPAWN_MIN_ROW..ROW_COUNT as i32).contains(&y)
+    })
+}
+
+// 搜索中的骨牌状态：排序后的方块位置和旋转状态
+type SearchState = (Vec<Block>, usize);
+
+// 从当前位置出发搜索所有能到达的状态，返回每个状态的方块位置及到达它的操作序列
+//
+// 下移次数少的路径优先：先在同一高度左右移动和旋转，最后再下落，避免骨牌在贴着底部平移时被锁定
+fn reachable_states(
+    piece_set: &PieceSet,
+    piece_type: PieceType,
+    blocks: &[Block],
+    rotation: usize,
+    board_blocks: &[Block],
+) -> Vec<(Vec<Block>, Vec<BotAction>)> {
+    let start = (sorted(blocks), rotation);
+    let mut previous: HashMap<SearchState, Option<(SearchState, BotAction)>> = HashMap::new();
+    previous.insert(start.clone(), None);
+    let mut order = vec![start.clone()];
+    let mut frontier = vec![start];
+    while !frontier.is_empty() {
+        // 同一高度内广度优先，下移得到的状态留到下一轮
+        let mut queue = VecDeque::from(std::mem::take(&mut frontier));
+        while let Some(state) = queue.pop_front() {
+            for action in [BotAction::Left, BotAction::Right, BotAction::Rotate, BotAction::Down] {
+                let Some((next, next_rotation)) =
+                    apply_action(piece_set, piece_type, &state.0, state.1, board_blocks, action)
+                else {
+                    continue;
+                };
+                let next = (sorted(&next), next_rotation);
+                if !in_search_bounds(&next.0) || previous.contains_key(&next) {
+                    continue;
+                }
+                previous.insert(next.clone(), Some((state.clone(), action)));
+                order.push(next.clone());
+                if action == BotAction::Down {
+                    frontier.push(next);
+                } else {
+                    queue.push_back(next);
+                }
+            }
+        }
+    }
+
+    order
+        .into_iter()
+        .map(|state| {
+            let mut path = Vec::new();
+            let mut cur = state.clone();
+            while let Some(Some((prev, action))) = previous.get(&cur) {
+                path.push(*action);
+                cur = prev.clone();
+            }
+            path.reverse();
+            (state.0, path)
+        })
+        .collect()
+}
+
+// 放置骨牌后的局面评分
+pub fn evaluate_board(board_blocks: &[Block], placed: &[Block], weights: &BotWeights) -> f32 {
+    let mut cells: Vec<(i32, i32)> = board_blocks
+        .iter()
+        .chain(placed.iter())
+        .map(|block| block.transform_to_real_pos())
+        .collect();
+
+    // 消除满行
+    let full_rows: Vec<i32> = (0..ROW_COUNT as i32)
+        .filter(|row| cells.iter().filter(|(_, y)| y == row).count() == COL_COUNT as usize)
+        .collect();
+    cells.retain(|(_, y)| !full_rows.contains(y));
+    for (_, y) in cells.iter_mut() {
+        *y += full_rows.iter().filter(|row| *row > y).count() as i32;
+    }
+    if cells.iter().any(|(_, y)| *y < 0) {
+        return TOP_OUT_PENALTY;
+    }
+
+    let mut heights = [0i32; COL_COUNT as usize];
+    let mut holes = 0;
+    for column in 0..COL_COUNT as i32 {
+        let top = cells
+            .iter()
+            .filter(|(x, _)| *x == column)
+            .map(|(_, y)| *y)
+            .min();
+        if let Some(top) = top {
+            heights[column as usize] = ROW_COUNT as i32 - top;
+            holes += (top..ROW_COUNT as i32)
+                .filter(|row| !cells.contains(&(column, *row)))
+                .count() as i32;
+        }
+    }
+    let aggregate_height: i32 = heights.iter().sum();
+    let bumpiness: i32 = heights.windows(2).map(|pair| (pair[0] - pair[1]).abs()).sum();
+
+    weights.aggregate_height * aggregate_height as f32
+        + weights.lines_cleared * full_rows.len() as f32
+        + weights.holes * holes as f32
+        + weights.bumpiness * bumpiness as f32
+}
+
+// 枚举所有落点（旋转 × 列，以及软降后平移的落点），返回评分最高的
+pub fn best_placement(
+    piece_set: &PieceSet,
+    piece_type: PieceType,
+    blocks: &[Block],
+    rotation: usize,
+    board_blocks: &[Block],
+    weights: &BotWeights,
+) -> Option<Placement> {
+    reachable_states(piece_set, piece_type, blocks, rotation, board_blocks)
+        .into_iter()
+        .filter(|(state, _)| !movable_flags(state, board_blocks).can_down)
+        .map(|(state, path)| Placement {
+            score: evaluate_board(board_blocks, &state, weights),
+            blocks: state,
+            path,
+        })
+        .max_by(|a, b| {
+            a.score
+                .total_cmp(&b.score)
+                // 同分时选择操作更少的落点
+                .then(b.path.len().cmp(&a.path.len()))
+        })
+}
+
+// 开始游戏时把机器人控制的面板交给机器人：演示模式的玩家，或对战模式中与机器人对战时的 Player 2
+pub fn setup_bots(
+    mut commands: Commands,
+    config: Res<GameConfig>,
+    weights: Res<BotWeights>,
+    session: Option<Res<NetSession>>,
+    main_board: Query<(Entity, &Player), With<MainBoard>>,
+) {
+    if session.is_some() {
+        return;
+    }
+    for (entity, player) in &main_board {
+        let is_bot = config.demo
+            || (config.mode.player_count() > 1 && config.versus_bot && player.0 == 1);
+        if is_bot {
+            commands
+                .entity(entity)
+                .insert(Bot {
+                    weights: *weights,
+                    ..default()
+                })
+                .remove::<InputMap>();
+        } else if config.versus_bot {
+            // 与机器人对战时玩家使用方向键
+            commands.entity(entity).insert(InputMap::arrows());
+        }
+    }
+}
+
+// 为新骨牌选择落点和路线，之后按路线模拟按键；只有骨牌离开路线（例如自动下落）时才重新选择
+pub fn drive_bots(
+    time: Res<Time>,
+    piece_set: Res<PieceSet>,
+    mut main_board: Query<(Entity, &mut Bot, &mut PlayerInput, &PieceRotation), With<MainBoard>>,
+    piece_blocks: Query<(&Parent, &Block, &PieceType)>,
+    board_blocks: Query<(&Parent, &Block), Without<PieceType>>,
+) {
+    for (main_board_entity, mut bot, mut input, rotation) in &mut main_board {
+        bot.step_timer.tick(time.delta());
+        let piece: Vec<(Block, PieceType)> = piece_blocks
+            .iter()
+            .filter(|(parent, _, _)| parent.get() == main_board_entity)
+            .map(|(_, block, piece_type)| (*block, *piece_type))
+            .collect();
+        *input = PlayerInput::default();
+        let Some((_, piece_type)) = piece.first().copied() else {
+            // 骨牌已固定，下一个骨牌重新选择落点
+            bot.plan = None;
+            continue;
+        };
+        let blocks: Vec<Block> = piece.iter().map(|(block, _)| *block).collect();
+
+        let action = match bot.plan.as_mut().and_then(|plan| plan.advance(&blocks)) {
+            Some(action) => action,
+            None => {
+                // 新骨牌，或者骨牌离开了路线，从当前位置重新选择
+                let board: Vec<Block> = board_blocks
+                    .iter()
+                    .filter(|(parent, _)| parent.get() == main_board_entity)
+                    .map(|(_, block)| *block)
+                    .collect();
+                bot.plan = best_placement(&piece_set, piece_type, &blocks, rotation.0, &board, &bot.weights)
+                    .and_then(|placement| {
+                        BotPlan::new(&piece_set, piece_type, &blocks, rotation.0, &board, placement)
+                    });
+                match bot.plan.as_mut() {
+                    Some(plan) => plan.advance(&blocks).flatten(),
+                    None => continue,
+                }
+            }
+        };
+        match action {
+            Some(BotAction::Left) => input.left = true,
+            Some(BotAction::Right) => input.right = true,
+            Some(BotAction::Down) => input.down = true,
+            Some(BotAction::Rotate) => input.rotate = bot.step_timer.just_finished(),
+            // 到达落点，按下键立即固定
+            None => input.down = true,
+        }
+    }
+}
+
+// 为玩家的面板显示推荐落点
+pub fn update_hints(
+    mut commands: Commands,
+    piece_set: Res<PieceSet>,
+    weights: Res<BotWeights>,
+    main_board: Query<(Entity, &PieceRotation), (With<MainBoard>, Without<Bot>, Without<RemotePlayer>)>,
+    piece_blocks: Query<(&Parent, &Block, &PieceType)>,
+    board_blocks: Query<(&Parent, &Block), Without<PieceType>>,
+    hint_blocks: Query<(Entity, &Parent), With<HintBlock>>,
+    mut last_piece: Local<HashMap<Entity, Vec<Block>>>,
+) {
+    for (main_board_entity, rotation) in &main_board {
+        let piece: Vec<(Block, PieceType)> = piece_blocks
+            .iter()
+            .filter(|(parent, _, _)| parent.get() == main_board_entity)
+            .map(|(_, block, piece_type)| (*block, *piece_type))
+            .collect();
+        let blocks: Vec<Block> = sorted(&piece.iter().map(|(block, _)| *block).collect::<Vec<_>>());
+        // 骨牌位置不变时不需要重新计算
+        if last_piece.get(&main_board_entity) == Some(&blocks) {
+            continue;
+        }
+        last_piece.insert(main_board_entity, blocks.clone());
+
+        for (entity, parent) in &hint_blocks {
+            if parent.get() == main_board_entity {
+                commands.entity(entity).despawn();
+            }
+        }
+        let Some((_, piece_type)) = piece.first().copied() else {
+            continue;
+        };
+        let board: Vec<Block> = board_blocks
+            .iter()
+            .filter(|(parent, _)| parent.get() == main_board_entity)
+            .map(|(_, block)| *block)
+            .collect();
+        let Some(placement) = best_placement(&piece_set, piece_type, &blocks, rotation.0, &board, &weights)
+        else {
+            continue;
+        };
+        commands.entity(main_board_entity).with_children(|parent| {
+            for block in placement.blocks.iter() {
+                // 提示方块显示在已有方块和当前骨牌下面
+                let mut sprite = new_block_sprite(HINT_COLOR, block.transform_to());
+                sprite.transform.translation.z = HINT_Z;
+                parent.spawn((sprite, HintBlock));
+            }
+        });
+    }
+}
+
+// 关闭提示后移除提示方块
+pub fn clear_hints(mut commands: Commands, hint_blocks: Query<Entity, With<HintBlock>>) {
+    for entity in &hint_blocks {
+        commands.entity(entity).despawn();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::BASE_OFFSET;
+
+    // 按真实坐标（面板左上角为原点，y 向下）列出方块
+    fn real_blocks(cells: &[(i32, i32)]) -> Vec<Block> {
+        cells
+            .iter()
+            .map(|(x, y)| Block {
+                x: x - BASE_OFFSET.0,
+                y: y - BASE_OFFSET.1,
+            })
+            .collect()
+    }
+
+    // 面板底部的几行，# 为方块，最后一行是面板底部
+    fn board(rows: &[&str]) -> Vec<Block> {
+        let top = ROW_COUNT as usize - rows.len();
+        let cells: Vec<(i32, i32)> = rows
+            .iter()
+            .enumerate()
+            .flat_map(|(index, row)| {
+                row.chars()
+                    .enumerate()
+                    .filter(|(_, cell)| *cell == '#')
+                    .map(move |(x, _)| (x as i32, (top + index) as i32))
+            })
+            .collect();
+        real_blocks(&cells)
+    }
+
+    // 从生成位置出发到达 target 的路径
+    fn path_to(piece_type: PieceType, board_blocks: &[Block], target: &[(i32, i32)]) -> Option<Vec<BotAction>> {
+        let piece_set = PieceSet::default();
+        let spawn = piece_set.config(piece_type).unwrap().blocks;
+        let target = sorted(&real_blocks(target));
+        reachable_states(&piece_set, piece_type, &spawn, 0, board_blocks)
+            .into_iter()
+            .find(|(blocks, _)| sorted(blocks) == target)
+            .map(|(_, path)| path)
+    }
+
+    #[test]
+    fn reaches_tuck_under_overhang() {
+        let board_blocks = board(&["...#######", "..........", ".........."]);
+        let target = [(4, 18), (5, 18), (4, 19), (5, 19)];
+        let path = path_to(PieceType('O'), &board_blocks, &target).unwrap();
+        // 先从左边的空列落到底，再向右移到悬空方块下面
+        let last_down = path.iter().rposition(|action| *action == BotAction::Down).unwrap();
+        assert!(path[last_down..].contains(&BotAction::Right), "{:?}", path);
+        assert_eq!(path.last(), Some(&BotAction::Right));
+    }
+
+    #[test]
+    fn reaches_t_spin_slot() {
+        let board_blocks = board(&["...#......", "###...####", "####.#####"]);
+        // T 朝下放入槽中，只能通过最后一步旋转进入
+        let target = [(3, 18), (4, 18), (5, 18), (4, 19)];
+        let path = path_to(PieceType('T'), &board_blocks, &target).unwrap();
+        assert_eq!(path.last(), Some(&BotAction::Rotate), "{:?}", path);
+    }
+
+    #[test]
+    fn sealed_cells_are_unreachable() {
+        // 被完全封住的空洞不能到达
+        let board_blocks = board(&["##########", "#.......##", "##########"]);
+        assert!(path_to(PieceType('I'), &board_blocks, &[(1, 18), (2, 18), (3, 18), (4, 18)]).is_none());
+        let path = path_to(PieceType('I'), &board_blocks, &[(0, 16), (1, 16), (2, 16), (3, 16)]).unwrap();
+        assert!(path.contains(&BotAction::Down));
+    }
+}
@@ -3,26 +3,166 @@ use bevy::prelude::*;
 #[derive(Debug, Clone, Eq, PartialEq, Hash, States, Default)]
 /// 定义应用程序状态
 pub enum AppState {
+    // 主菜单 选择模式和起始等级
     #[default]
+    MainMenu,
     InGame,
+    // 暂停 冻结所有计时器并隐藏游戏面板
+    Paused,
+    // 设置界面 从主菜单或暂停菜单进入
+    Settings,
     GameOver,
+    // 完成当前模式的目标
+    Victory,
+    // 限时模式时间用完
+    TimeUp,
+    // 棋盘编辑器 编辑面板和骨牌队列，保存为谜题或直接试玩
+    Editor,
 }
 
+// 游戏设置
 #[derive(Debug, Resource)]
-pub struct GameAudios {
-    pub drop: Handle<AudioSource>,
-    pub game_over: Handle<AudioSource>,
-    pub line_clear: Handle<AudioSource>,
+pub struct GameSettings {
+    // 窗口失去焦点时自动暂停
+    pub pause_on_focus_lost: bool,
+    // 显示机器人推荐的落点
+    pub show_hints: bool,
+    // 按键练习：按键失误后显示最优按键
+    pub finesse_practice: bool,
+    // 当前主题的名称
+    pub theme: String,
+    // 粒子效果的强度
+    pub effects: EffectsLevel,
+    // 方块平滑移动到新的位置，关闭时逐格跳动
+    pub smooth_movement: bool,
+    // 全屏显示，也可以按 F11 切换
+    pub fullscreen: bool,
+    // 音量，0 到 1，音乐和音效的实际音量再乘以总音量
+    pub master_volume: f32,
+    pub music_volume: f32,
+    pub sfx_volume: f32,
 }
+
+impl GameSettings {
+    pub fn volume(&self, channel: VolumeChannel) -> f32 {
+        match channel {
+            VolumeChannel::Master => self.master_volume,
+            VolumeChannel::Music => self.music_volume,
+            VolumeChannel::Sfx => self.sfx_volume,
+        }
+    }
+
+    pub fn volume_mut(&mut self, channel: VolumeChannel) -> &mut f32 {
+        match channel {
+            VolumeChannel::Master => &mut self.master_volume,
+            VolumeChannel::Music => &mut self.music_volume,
+            VolumeChannel::Sfx => &mut self.sfx_volume,
+        }
+    }
+}
+
+// 音量通道
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VolumeChannel {
+    Master,
+    Music,
+    Sfx,
+}
+
+impl VolumeChannel {
+    pub fn name(&self) -> &'static str {
+        match self {
+            VolumeChannel::Master => "Master volume",
+            VolumeChannel::Music => "Music volume",
+            VolumeChannel::Sfx => "Effects volume",
+        }
+    }
+}
+
+// 粒子效果的强度
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EffectsLevel {
+    #[default]
+    Full,
+    Reduced,
+    Off,
+}
+
+impl EffectsLevel {
+    pub fn name(&self) -> &'static str {
+        match self {
+            EffectsLevel::Full => "Full",
+            EffectsLevel::Reduced => "Reduced",
+            EffectsLevel::Off => "Off",
+        }
+    }
+
+    pub fn next(&self) -> Self {
+        match self {
+            EffectsLevel::Full => EffectsLevel::Reduced,
+            EffectsLevel::Reduced => EffectsLevel::Off,
+            EffectsLevel::Off => EffectsLevel::Full,
+        }
+    }
+
+    // 粒子数量的倍数
+    pub fn scale(&self) -> f32 {
+        match self {
+            EffectsLevel::Full => 1.0,
+            EffectsLevel::Reduced => 0.35,
+            EffectsLevel::Off => 0.0,
+        }
+    }
+}
+
+impl Default for GameSettings {
+    fn default() -> Self {
+        Self {
+            pause_on_focus_lost: true,
+            show_hints: false,
+            finesse_practice: false,
+            theme: "Classic".to_string(),
+            effects: EffectsLevel::Full,
+            smooth_movement: true,
+            fullscreen: false,
+            master_volume: 1.0,
+            music_volume: 0.5,
+            sfx_volume: 1.0,
+        }
+    }
+}
+
+// 主菜单中的子界面
+#[derive(Debug, Clone, Eq, PartialEq, Hash, SubStates, Default)]
+#[source(AppState = AppState::MainMenu)]
+pub enum MenuScreen {
+    #[default]
+    Main,
+    // 选择模式和起始等级
+    ModeSelect,
+    HighScores,
+    // 网络对战的主机/加入
+    Network,
+    // 选择谜题
+    PuzzleSelect,
+}
+
+// 一局游戏中生成的实体（游戏面板、计分板等），重新开始或回到主菜单时统一销毁
+#[derive(Component, Debug)]
+pub struct GameEntity;
+
+// 重新开始游戏
+#[derive(Event, Debug)]
+pub struct RestartGame;
+
 #[derive(Debug, Resource)]
 pub struct FontTff {
     pub sim_hei: Handle<Font>,
-    pub fira_sans_bold: Handle<Font>,
 }
 
 
 
-// 屏幕尺寸
+// 屏幕尺寸，也是界面布局的设计尺寸，窗口大小不同时整体缩放
 #[derive(Resource)]
 pub struct WindowSize {
     pub width: f32,
@@ -38,29 +178,9 @@ impl Default for WindowSize {
     }
 }
 
-/// 加载游戏音频资源
-///
-/// 该函数负责加载游戏过程中使用的音频资源，并将它们插入到游戏资源池中。
-///
-/// # 参数
-/// - `mut command: Commands`: 命令缓冲区，用于插入资源命令。
-/// - `asset_server: Res<AssetServer>`: 资源服务器，用于加载音频资源。
-pub fn setup_game_audios_resource(mut command: Commands, asset_server: Res<AssetServer>) {
-    // 定义并加载游戏音频资源
-    let game_audios: GameAudios = GameAudios {
-        drop: asset_server.load("sounds/Drop.wav"),
-        game_over: asset_server.load("sounds/Gameover.wav"),
-        line_clear: asset_server.load("sounds/Lineclear.wav"),
-    };
-
-    // 将加载的音频资源插入到命令缓冲区中
-    command.insert_resource(game_audios);
-}
-
 pub fn setup_font_assets_resource(mut command: Commands, asset_server: Res<AssetServer>) {
     let font_tff: FontTff = FontTff {
         sim_hei: asset_server.load("fonts/SimHei.ttf"),
-        fira_sans_bold: asset_server.load("fonts/Fira-Sans-Bold.ttf"),
     };
 
     command.insert_resource(font_tff);
@@ -0,0 +1,337 @@
+use bevy::{prelude::*, window::PrimaryWindow};
+
+use crate::{
+    board::{spawn_board_background, FollowNode, BLOCK_LENGTH, COL_COUNT, ROW_COUNT},
+    common::{AppState, RestartGame},
+    layout::window_to_world,
+    menu::{
+        spawn_menu_button, spawn_menu_text, spawn_menu_title, spawn_option_row,
+        spawn_small_menu_button, MenuButton, MenuLabel,
+    },
+    mode::{GameConfig, GameMode},
+    piece::PieceType,
+    piece_set::PieceSet,
+    puzzle::{cell_color, save_puzzle, Puzzle, PuzzleGoal, PuzzleHandles},
+    state::new_block_sprite,
+    theme::{BlockSkin, BoardFrame},
+};
+
+// 编辑器中的操作，由编辑器界面的按钮发出
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EditorAction {
+    // 选择绘制的颜色，None 为灰色的垃圾方块
+    Brush(Option<PieceType>),
+    // 在骨牌队列末尾添加当前颜色的骨牌
+    PushPiece,
+    // 删除骨牌队列的最后一个骨牌
+    PopPiece,
+    // 把当前颜色的骨牌设为暂存的骨牌
+    SetHold,
+    ClearHold,
+    PreviousGoal,
+    NextGoal,
+    // 清空面板上的方块
+    ClearBoard,
+    // 保存为谜题文件
+    Save,
+    // 从编辑的局面开始试玩
+    Playtest,
+}
+
+// 编辑中的局面，离开编辑器后保留，试玩结束回到编辑器时继续编辑
+#[derive(Debug, Resource)]
+pub struct EditorState {
+    pub puzzle: Puzzle,
+    // 鼠标左键绘制的颜色，None 为灰色的垃圾方块
+    pub brush: Option<PieceType>,
+    // 保存结果等提示
+    pub status: String,
+}
+
+impl Default for EditorState {
+    fn default() -> Self {
+        Self {
+            puzzle: Puzzle {
+                name: "Custom".to_string(),
+                goal: PuzzleGoal::Lines(1),
+                pieces: Vec::new(),
+                hold: None,
+                cells: Vec::new(),
+            },
+            brush: None,
+            status: String::new(),
+        }
+    }
+}
+
+// 正在试玩的局面，试玩期间代替谜题选择界面中选择的谜题
+#[derive(Debug, Resource)]
+pub struct PlaytestPuzzle(pub Puzzle);
+
+// 编辑器中的面板，与游戏面板一样用精灵绘制，对齐到界面中的占位节点
+#[derive(Debug, Component)]
+pub struct EditorBoard;
+
+// 编辑器面板上的方块
+#[derive(Debug, Component)]
+pub struct EditorBlock;
+
+pub fn show_editor(mut commands: Commands, piece_set: Res<PieceSet>, mut editor: ResMut<EditorState>) {
+    let mut board_slot = Entity::PLACEHOLDER;
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    width: Val::Percent(100.),
+                    height: Val::Percent(100.),
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    column_gap: Val::Px(40.),
+                    ..default()
+                },
+                ..default()
+            },
+            StateScoped(AppState::Editor),
+        ))
+        .with_children(|parent| {
+            // 边框画在节点外面，节点的大小正好是面板的大小
+            board_slot = parent
+                .spawn((
+                    NodeBundle {
+                        style: Style {
+                            width: Val::Px(BLOCK_LENGTH * COL_COUNT as f32),
+                            height: Val::Px(BLOCK_LENGTH * ROW_COUNT as f32),
+                            ..default()
+                        },
+                        ..default()
+                    },
+                    BoardFrame,
+                    Outline::new(Val::Px(1.0), Val::ZERO, Color::NONE),
+                ))
+                .id();
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::Column,
+                        align_items: AlignItems::Center,
+                        ..default()
+                    },
+                    ..default()
+                })
+                .with_children(|parent| {
+                    spawn_menu_title(parent, "EDITOR");
+                    spawn_menu_text(parent, "Left click: paint  Right click: erase  Enter: playtest");
+                    spawn_menu_text(parent, "").insert(MenuLabel::EditorBrush);
+                    // 选择颜色
+                    parent
+                        .spawn(NodeBundle {
+                            style: Style {
+                                margin: UiRect::vertical(Val::Px(5.0)),
+                                ..default()
+                            },
+                            ..default()
+                        })
+                        .with_children(|parent| {
+                            for piece_type in piece_set.piece_types() {
+                                spawn_small_menu_button(
+                                    parent,
+                                    &piece_type.letter().to_string(),
+                                    MenuButton::EditorAction(EditorAction::Brush(Some(piece_type))),
+                                );
+                            }
+                            spawn_small_menu_button(parent, "#", MenuButton::EditorAction(EditorAction::Brush(None)));
+                        });
+                    spawn_option_row(
+                        parent,
+                        MenuLabel::EditorQueue,
+                        MenuButton::EditorAction(EditorAction::PopPiece),
+                        MenuButton::EditorAction(EditorAction::PushPiece),
+                    );
+                    spawn_option_row(
+                        parent,
+                        MenuLabel::EditorHold,
+                        MenuButton::EditorAction(EditorAction::ClearHold),
+                        MenuButton::EditorAction(EditorAction::SetHold),
+                    );
+                    spawn_option_row(
+                        parent,
+                        MenuLabel::EditorGoal,
+                        MenuButton::EditorAction(EditorAction::PreviousGoal),
+                        MenuButton::EditorAction(EditorAction::NextGoal),
+                    );
+                    spawn_menu_button(parent, "Playtest", MenuButton::EditorAction(EditorAction::Playtest));
+                    spawn_menu_button(parent, "Save", MenuButton::EditorAction(EditorAction::Save));
+                    spawn_menu_button(parent, "Clear", MenuButton::EditorAction(EditorAction::ClearBoard));
+                    spawn_menu_button(parent, "Back", MenuButton::MainMenu);
+                    spawn_menu_text(parent, "").insert(MenuLabel::EditorStatus);
+                });
+        });
+    commands
+        .spawn((
+            SpatialBundle::default(),
+            EditorBoard,
+            FollowNode(board_slot),
+            StateScoped(AppState::Editor),
+        ))
+        .with_children(spawn_board_background);
+    // 重新绘制面板上的方块
+    editor.set_changed();
+}
+
+// 回到编辑器或主菜单时结束试玩
+pub fn stop_playtest(mut commands: Commands) {
+    commands.remove_resource::<PlaytestPuzzle>();
+}
+
+// 鼠标左键绘制当前颜色，右键擦除
+pub fn paint_editor_board(
+    mouse_input: Res<ButtonInput<MouseButton>>,
+    window_query: Query<&Window, With<PrimaryWindow>>,
+    camera_query: Query<(&Camera, &GlobalTransform), With<Camera2d>>,
+    board_query: Query<&GlobalTransform, With<EditorBoard>>,
+    mut editor: ResMut<EditorState>,
+) {
+    let paint = mouse_input.pressed(MouseButton::Left);
+    let erase = mouse_input.pressed(MouseButton::Right);
+    if !paint && !erase {
+        return;
+    }
+    let Some(cursor) = window_query.get_single().ok().and_then(Window::cursor_position) else {
+        return;
+    };
+    let Ok((camera, camera_transform)) = camera_query.get_single() else {
+        return;
+    };
+    let Ok(board_transform) = board_query.get_single() else {
+        return;
+    };
+    let Some(cursor) = window_to_world(camera, camera_transform, cursor) else {
+        return;
+    };
+    // 面板实体位于面板左上角，面板中的 y 向下
+    let pos = cursor - board_transform.translation().truncate();
+    let x = (pos.x / BLOCK_LENGTH).floor() as i32;
+    let y = (-pos.y / BLOCK_LENGTH).floor() as i32;
+    if !(0..COL_COUNT as i32).contains(&x) || !(0..ROW_COUNT as i32).contains(&y) {
+        return;
+    }
+    let cell = if paint { Some((x, y, editor.brush)) } else { None };
+    let current = editor
+        .puzzle
+        .cells
+        .iter()
+        .position(|(cell_x, cell_y, _)| *cell_x == x && *cell_y == y);
+    // 按住鼠标拖动时，只有格子变化才修改，避免每帧重新绘制面板
+    if current.map(|index| editor.puzzle.cells[index]) == cell {
+        return;
+    }
+    if let Some(index) = current {
+        editor.puzzle.cells.remove(index);
+    }
+    editor.puzzle.cells.extend(cell);
+    editor.status.clear();
+}
+
+// 局面变化后重新生成编辑器面板上的方块
+pub fn update_editor_board(
+    mut commands: Commands,
+    piece_set: Res<PieceSet>,
+    editor: Res<EditorState>,
+    board_query: Query<Entity, With<EditorBoard>>,
+    block_query: Query<Entity, With<EditorBlock>>,
+) {
+    if !editor.is_changed() && !piece_set.is_changed() {
+        return;
+    }
+    for block in &block_query {
+        commands.entity(block).despawn();
+    }
+    for board in &board_query {
+        commands.entity(board).with_children(|parent| {
+            for (x, y, piece_type) in editor.puzzle.cells.iter() {
+                let position = Vec2::new(*x as f32, *y as f32) * BLOCK_LENGTH;
+                parent.spawn((
+                    new_block_sprite(cell_color(&piece_set, *piece_type), position),
+                    BlockSkin::cell(&piece_set, *piece_type),
+                    EditorBlock,
+                ));
+            }
+        });
+    }
+}
+
+pub fn handle_editor_actions(
+    mut commands: Commands,
+    mut editor_actions: EventReader<EditorAction>,
+    mut editor: ResMut<EditorState>,
+    mut config: ResMut<GameConfig>,
+    asset_server: Res<AssetServer>,
+    mut puzzle_handles: ResMut<PuzzleHandles>,
+    mut restart_events: EventWriter<RestartGame>,
+) {
+    for action in editor_actions.read() {
+        editor.status.clear();
+        match action {
+            EditorAction::Brush(brush) => editor.brush = *brush,
+            EditorAction::PushPiece => match editor.brush {
+                Some(piece_type) => editor.puzzle.pieces.push(piece_type),
+                None => editor.status = "Choose a piece color first".to_string(),
+            },
+            EditorAction::PopPiece => {
+                editor.puzzle.pieces.pop();
+            }
+            EditorAction::SetHold => editor.puzzle.hold = editor.brush,
+            EditorAction::ClearHold => editor.puzzle.hold = None,
+            EditorAction::PreviousGoal => editor.puzzle.goal = editor.puzzle.goal.cycle(-1),
+            EditorAction::NextGoal => editor.puzzle.goal = editor.puzzle.goal.cycle(1),
+            EditorAction::ClearBoard => editor.puzzle.cells.clear(),
+            EditorAction::Save => {
+                if let Err(err) = editor.puzzle.validate() {
+                    editor.status = err;
+                    continue;
+                }
+                // 保存后像其他谜题文件一样加载，出现在谜题列表中
+                let mut puzzle = editor.puzzle.clone();
+                editor.status = match save_puzzle(&mut puzzle) {
+                    Ok(path) => {
+                        puzzle_handles.0.push(asset_server.load(path.clone()));
+                        format!("Saved to assets/{}", path)
+                    }
+                    Err(err) => format!("Save failed: {}", err),
+                };
+            }
+            EditorAction::Playtest => {
+                if let Err(err) = editor.puzzle.validate() {
+                    editor.status = err;
+                    continue;
+                }
+                commands.insert_resource(PlaytestPuzzle(editor.puzzle.clone()));
+                config.demo = false;
+                config.mode = GameMode::Puzzle;
+                restart_events.send(RestartGame);
+            }
+        }
+    }
+}
+
+// 按 Enter 在编辑器和试玩之间切换
+pub fn toggle_playtest(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    app_state: Res<State<AppState>>,
+    playtest: Option<Res<PlaytestPuzzle>>,
+    mut next_state: ResMut<NextState<AppState>>,
+    mut editor_actions: EventWriter<EditorAction>,
+) {
+    if !keyboard_input.just_pressed(KeyCode::Enter) {
+        return;
+    }
+    match app_state.get() {
+        AppState::Editor => {
+            editor_actions.send(EditorAction::Playtest);
+        }
+        AppState::InGame | AppState::Paused | AppState::GameOver | AppState::Victory if playtest.is_some() => {
+            next_state.set(AppState::Editor);
+        }
+        _ => {}
+    }
+}
@@ -0,0 +1,193 @@
+use std::time::Duration;
+
+use bevy::prelude::*;
+use rand::Rng;
+
+use crate::{
+    board::{Block, MainBoard, BLOCK_LENGTH, PARTICLE_Z},
+    common::{AppState, GameSettings},
+    mode::check_mode_goal,
+    piece::{check_full_line, collapse_cleared_lines, ClearingBlock, LinesCleared, PieceLocked},
+    piece_set::PieceSet,
+    theme::{BlockSkin, Theme},
+};
+
+// 粒子边长
+const PARTICLE_SIZE: f32 = 6.0;
+// 粒子存在的时间
+const PARTICLE_LIFETIME: Duration = Duration::from_millis(600);
+// 粒子下落的加速度（像素/秒²），y 向下增大
+const PARTICLE_GRAVITY: f32 = 900.0;
+
+// 骨牌固定时每个方块的粒子数
+const LOCK_PARTICLES: f32 = 1.0;
+// 直接落下后固定时每个方块的粒子数
+const DROP_PARTICLES: f32 = 4.0;
+// 消行时每个方块的粒子数，再乘以消行强度
+const CLEAR_PARTICLES: f32 = 1.5;
+
+// 粒子效果：消行、骨牌固定和直接落下时在方块位置喷出骨牌颜色的粒子，只在界面中使用
+pub struct EffectsPlugin;
+
+impl Plugin for EffectsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            PostUpdate,
+            (
+                // 消除的行被标记后、下移之前读取方块的位置和颜色
+                (spawn_lock_particles, spawn_clear_particles)
+                    .after(check_full_line)
+                    .before(collapse_cleared_lines),
+                update_particles.after(check_mode_goal),
+            )
+                .run_if(in_state(AppState::InGame)),
+        );
+    }
+}
+
+// 粒子，位置和速度以游戏面板左上角为原点
+#[derive(Debug, Component)]
+pub struct Particle {
+    pub position: Vec2,
+    pub velocity: Vec2,
+    pub color: Color,
+    pub lifetime: Timer,
+}
+
+// 消行的粒子强度：消除行数越多越强，T-spin 和全消额外加强
+fn clear_intensity(event: &LinesCleared) -> f32 {
+    let mut intensity = event.lines as f32;
+    if event.t_spin {
+        intensity += 2.0;
+    }
+    if event.perfect_clear {
+        intensity += 4.0;
+    }
+    intensity
+}
+
+// 在方块中心喷出粒子，count 的小数部分按概率多生成一个
+fn spawn_burst(
+    commands: &mut Commands,
+    board: Entity,
+    block: &Block,
+    color: Color,
+    count: f32,
+    speed: f32,
+) {
+    let mut rng = rand::thread_rng();
+    let mut count_int = count.floor() as u32;
+    if rng.gen::<f32>() < count.fract() {
+        count_int += 1;
+    }
+    let center = block.transform_to() + Vec2::splat(BLOCK_LENGTH / 2.0);
+    commands.entity(board).with_children(|parent| {
+        for _ in 0..count_int {
+            let angle = rng.gen_range(0.0..std::f32::consts::TAU);
+            let velocity = Vec2::from_angle(angle) * speed * rng.gen_range(0.5..1.0)
+                - Vec2::new(0.0, speed * 0.5);
+            let particle = Particle {
+                position: center,
+                velocity,
+                color,
+                lifetime: Timer::new(PARTICLE_LIFETIME, TimerMode::Once),
+            };
+            parent.spawn((particle_sprite(center, color), particle));
+        }
+    });
+}
+
+fn particle_sprite(position: Vec2, color: Color) -> SpriteBundle {
+    SpriteBundle {
+        sprite: Sprite {
+            color,
+            custom_size: Some(Vec2::splat(PARTICLE_SIZE)),
+            ..default()
+        },
+        transform: Transform::from_translation(particle_translation(position)),
+        ..default()
+    }
+}
+
+// 面板坐标 y 向下，面板实体下的局部坐标 y 向上
+fn particle_translation(position: Vec2) -> Vec3 {
+    Vec3::new(position.x, -position.y, PARTICLE_Z)
+}
+
+// 骨牌固定时的粒子，按向下键直接固定时更多更快
+pub fn spawn_lock_particles(
+    mut commands: Commands,
+    settings: Res<GameSettings>,
+    theme: Res<Theme>,
+    piece_set: Res<PieceSet>,
+    mut piece_locked_events: EventReader<PieceLocked>,
+    main_board: Query<(), With<MainBoard>>,
+) {
+    let scale = settings.effects.scale();
+    for event in piece_locked_events.read() {
+        if scale == 0.0 || !main_board.contains(event.board) {
+            continue;
+        }
+        let color = theme.block_color(&piece_set, BlockSkin::Piece(event.piece_type));
+        let (count, speed) = if event.hard_drop {
+            (DROP_PARTICLES, 160.0)
+        } else {
+            (LOCK_PARTICLES, 80.0)
+        };
+        for block in event.blocks.iter() {
+            spawn_burst(&mut commands, event.board, block, color, count * scale, speed);
+        }
+    }
+}
+
+// 消行时在消除的行喷出粒子，一次消除的行数越多粒子越多越快
+pub fn spawn_clear_particles(
+    mut commands: Commands,
+    settings: Res<GameSettings>,
+    mut lines_cleared_events: EventReader<LinesCleared>,
+    clearing_blocks: Query<(&Parent, &Block, &Sprite), Added<ClearingBlock>>,
+) {
+    let scale = settings.effects.scale();
+    for event in lines_cleared_events.read() {
+        if scale == 0.0 || event.lines == 0 {
+            continue;
+        }
+        let intensity = clear_intensity(event);
+        for (parent, block, sprite) in &clearing_blocks {
+            if parent.get() != event.board {
+                continue;
+            }
+            spawn_burst(
+                &mut commands,
+                event.board,
+                block,
+                sprite.color,
+                CLEAR_PARTICLES * intensity * scale,
+                120.0 + 40.0 * intensity,
+            );
+        }
+    }
+}
+
+// 粒子受重力下落并逐渐消失
+pub fn update_particles(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut particles: Query<(Entity, &mut Particle, &mut Transform, &mut Sprite)>,
+) {
+    let delta = time.delta_seconds();
+    for (entity, mut particle, mut transform, mut sprite) in &mut particles {
+        particle.lifetime.tick(time.delta());
+        if particle.lifetime.finished() {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        }
+        particle.velocity.y += PARTICLE_GRAVITY * delta;
+        let velocity = particle.velocity;
+        particle.position += velocity * delta;
+        transform.translation = particle_translation(particle.position);
+        sprite.color = particle
+            .color
+            .with_alpha(particle.lifetime.fraction_remaining());
+    }
+}
@@ -0,0 +1,352 @@
+use std::collections::{HashMap, VecDeque};
+
+use bevy::prelude::*;
+
+use crate::{
+    board::{Block, MainBoard},
+    bot::{apply_action, in_search_bounds, sorted, Bot, BotAction},
+    common::GameSettings,
+    network::RemotePlayer,
+    piece::{drop_distance, PieceLocked, PieceType},
+    piece_set::PieceSet,
+    player::{Player, PlayerInput},
+    state::SessionStats,
+};
+
+// 一次按键。按住左右键会一直移动到墙边，只算一次按键
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FinesseInput {
+    Left,
+    Right,
+    HoldLeft,
+    HoldRight,
+    Rotate,
+}
+
+impl FinesseInput {
+    const ALL: [FinesseInput; 5] = [
+        FinesseInput::Left,
+        FinesseInput::Right,
+        FinesseInput::HoldLeft,
+        FinesseInput::HoldRight,
+        FinesseInput::Rotate,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            FinesseInput::Left => "←",
+            FinesseInput::Right => "→",
+            FinesseInput::HoldLeft => "长按←",
+            FinesseInput::HoldRight => "长按→",
+            FinesseInput::Rotate => "旋转",
+        }
+    }
+}
+
+// 记录当前骨牌生成时的位置和玩家的按键次数，骨牌固定时与最少按键比较
+#[derive(Component, Debug, Default)]
+pub struct FinesseTracker {
+    pub spawn: Option<(PieceType, Vec<Block>)>,
+    pub presses: u32,
+    pub last_input: PlayerInput,
+    // 上一个骨牌失误时的最优按键，练习模式中显示
+    pub last_fault: Option<Vec<FinesseInput>>,
+}
+
+// 练习模式中显示最优按键的文字
+#[derive(Component, Debug)]
+pub struct FinesseHintText;
+
+// 把骨牌平移到指定高度，只保留列和朝向
+fn at_height(blocks: &[Block], top: i32) -> Vec<Block> {
+    let min_y = blocks.iter().map(|block| block.y).min().unwrap_or(top);
+    sorted(
+        &blocks
+            .iter()
+            .map(|block| Block {
+                x: block.x,
+                y: block.y - min_y + top,
+            })
+            .collect::<Vec<_>>(),
+    )
+}
+
+// 在空面板上执行一次按键，返回按键后的方块和旋转状态
+fn apply_input(
+    piece_set: &PieceSet,
+    piece_type: PieceType,
+    blocks: &[Block],
+    rotation: usize,
+    input: FinesseInput,
+) -> Option<(Vec<Block>, usize)> {
+    let action = match input {
+        FinesseInput::Left | FinesseInput::HoldLeft => BotAction::Left,
+        FinesseInput::Right | FinesseInput::HoldRight => BotAction::Right,
+        FinesseInput::Rotate => BotAction::Rotate,
+    };
+    let mut cur = apply_action(piece_set, piece_type, blocks, rotation, &[], action)?;
+    if matches!(input, FinesseInput::HoldLeft | FinesseInput::HoldRight) {
+        while let Some(next) = apply_action(piece_set, piece_type, &cur.0, cur.1, &[], action) {
+            cur = next;
+        }
+    }
+    Some(cur)
+}
+
+// 从生成位置到达固定位置的列和朝向所需的最少按键，不考虑下移，按键在空面板上计算
+//
+// board_blocks 为固定前面板上的方块。从目标的列和朝向直接落下到不了固定位置时，
+// 需要软降后再平移或旋转（塞入、T-spin 等），返回 None，不计失误
+pub fn minimum_inputs(
+    piece_set: &PieceSet,
+    piece_type: PieceType,
+    spawn: &[Block],
+    locked: &[Block],
+    board_blocks: &[Block],
+) -> Option<Vec<FinesseInput>> {
+    let top = spawn.iter().map(|block| block.y).min()?;
+    let target = at_height(locked, top);
+    let distance = drop_distance(&target, board_blocks);
+    let landed = target.iter().map(|block| Block {
+        x: block.x,
+        y: block.y + distance,
+    });
+    if sorted(&landed.collect::<Vec<_>>()) != sorted(locked) {
+        return None;
+    }
+    // 骨牌生成时的旋转状态为 0
+    let start = (at_height(spawn, top), 0);
+    let mut previous: HashMap<(Vec<Block>, usize), Option<((Vec<Block>, usize), FinesseInput)>> =
+        HashMap::new();
+    previous.insert(start.clone(), None);
+    let mut queue = VecDeque::from([start]);
+    while let Some(state) = queue.pop_front() {
+        if state.0 == target {
+            let mut path = Vec::new();
+            let mut cur = state;
+            while let Some(Some((prev, input))) = previous.get(&cur) {
+                path.push(*input);
+                cur = prev.clone();
+            }
+            path.reverse();
+            return Some(path);
+        }
+        for input in FinesseInput::ALL {
+            let Some((next, rotation)) = apply_input(piece_set, piece_type, &state.0, state.1, input)
+            else {
+                continue;
+            };
+            let next = (at_height(&next, top), rotation);
+            if !in_search_bounds(&next.0) || previous.contains_key(&next) {
+                continue;
+            }
+            previous.insert(next.clone(), Some((state.clone(), input)));
+            queue.push_back(next);
+        }
+    }
+    None
+}
+
+// 统计玩家按下左右键和旋转键的次数，按住不放只算一次
+pub fn count_finesse_inputs(
+    mut main_board: Query<
+        (Entity, &PlayerInput, &mut FinesseTracker),
+        (With<MainBoard>, Without<Bot>, Without<RemotePlayer>),
+    >,
+    piece_blocks: Query<(&Parent, &Block, &PieceType)>,
+) {
+    for (main_board_entity, input, mut tracker) in &mut main_board {
+        let last_input = std::mem::replace(&mut tracker.last_input, *input);
+        let piece: Vec<(Block, PieceType)> = piece_blocks
+            .iter()
+            .filter(|(parent, _, _)| parent.get() == main_board_entity)
+            .map(|(_, block, piece_type)| (*block, *piece_type))
+            .collect();
+        let Some((_, piece_type)) = piece.first().copied() else {
+            continue;
+        };
+        // 新骨牌还没有移动过，记录生成位置
+        if tracker.spawn.is_none() {
+            tracker.spawn = Some((piece_type, piece.iter().map(|(block, _)| *block).collect()));
+            tracker.presses = 0;
+        }
+        let presses = [
+            input.left && !last_input.left,
+            input.right && !last_input.right,
+            input.rotate,
+        ];
+        tracker.presses += presses.iter().filter(|pressed| **pressed).count() as u32;
+    }
+}
+
+// 骨牌固定时与最少按键比较，多出的按键计为失误
+pub fn check_finesse(
+    piece_set: Res<PieceSet>,
+    mut piece_locked_events: EventReader<PieceLocked>,
+    mut main_board: Query<
+        (&mut FinesseTracker, &mut SessionStats),
+        (With<MainBoard>, Without<Bot>, Without<RemotePlayer>),
+    >,
+    board_blocks: Query<(&Parent, &Block), Without<PieceType>>,
+) {
+    for event in piece_locked_events.read() {
+        let Ok((mut tracker, mut stats)) = main_board.get_mut(event.board) else {
+            continue;
+        };
+        let presses = std::mem::take(&mut tracker.presses);
+        let Some((piece_type, spawn)) = tracker.spawn.take() else {
+            continue;
+        };
+        // 固定的骨牌已经成为面板方块，去掉后得到固定前的面板
+        let board: Vec<Block> = board_blocks
+            .iter()
+            .filter(|(parent, block)| parent.get() == event.board && !event.blocks.contains(block))
+            .map(|(_, block)| *block)
+            .collect();
+        let Some(optimal) = minimum_inputs(&piece_set, piece_type, &spawn, &event.blocks, &board)
+        else {
+            continue;
+        };
+        let faults = presses.saturating_sub(optimal.len() as u32);
+        stats.finesse_faults += faults;
+        tracker.last_fault = (faults > 0).then_some(optimal);
+    }
+}
+
+// 练习模式中，失误后显示该骨牌的最优按键
+pub fn update_finesse_hint(
+    settings: Res<GameSettings>,
+    main_board: Query<(&Player, &FinesseTracker), With<MainBoard>>,
+    mut query: Query<(&Player, &mut Text), With<FinesseHintText>>,
+) {
+    for (hint_player, mut text) in &mut query {
+        for (player, tracker) in &main_board {
+            if player != hint_player {
+                continue;
+            }
+            let value = match &tracker.last_fault {
+                Some(optimal) if settings.finesse_practice => {
+                    let inputs: Vec<&str> = optimal.iter().map(FinesseInput::label).collect();
+                    if inputs.is_empty() {
+                        "最优按键: 不需要按键".to_string()
+                    } else {
+                        format!("最优按键: {}", inputs.join(" "))
+                    }
+                }
+                _ => String::new(),
+            };
+            if text.sections[0].value != value {
+                text.sections[0].value = value;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+
+    use super::*;
+    use crate::state::BASE_OFFSET;
+
+    // 按真实坐标（面板左上角为原点，y 向下）列出方块
+    fn real_blocks(cells: &[(i32, i32)]) -> Vec<Block> {
+        cells
+            .iter()
+            .map(|(x, y)| Block {
+                x: x - BASE_OFFSET.0,
+                y: y - BASE_OFFSET.1,
+            })
+            .collect()
+    }
+
+    fn spawn_blocks(piece_type: PieceType) -> Vec<Block> {
+        PieceSet::default().config(piece_type).unwrap().blocks
+    }
+
+    fn inputs(piece_type: PieceType, locked: &[(i32, i32)], board: &[(i32, i32)]) -> Option<Vec<FinesseInput>> {
+        let piece_set = PieceSet::default();
+        minimum_inputs(
+            &piece_set,
+            piece_type,
+            &spawn_blocks(piece_type),
+            &real_blocks(locked),
+            &real_blocks(board),
+        )
+    }
+
+    #[test]
+    fn known_placements() {
+        let o = PieceType('O');
+        let t = PieceType('T');
+        // O 靠左墙：长按左一次
+        assert_eq!(
+            inputs(o, &[(0, 18), (1, 18), (0, 19), (1, 19)], &[]),
+            Some(vec![FinesseInput::HoldLeft])
+        );
+        assert_eq!(
+            inputs(o, &[(3, 18), (4, 18), (3, 19), (4, 19)], &[]),
+            Some(vec![FinesseInput::Left])
+        );
+        // T 在生成的列和朝向直接落下不需要按键
+        assert_eq!(inputs(t, &[(4, 18), (3, 19), (4, 19), (5, 19)], &[]), Some(Vec::new()));
+        assert_eq!(
+            inputs(t, &[(8, 18), (7, 19), (8, 19), (9, 19)], &[]),
+            Some(vec![FinesseInput::HoldRight])
+        );
+        // 落在面板方块上
+        let stack = [(3, 19), (4, 19), (5, 19)];
+        assert_eq!(inputs(t, &[(4, 17), (3, 18), (4, 18), (5, 18)], &stack), Some(Vec::new()));
+    }
+
+    #[test]
+    fn tuck_is_not_counted() {
+        // 塞入悬空方块下面，直接落下会停在悬空方块上
+        let overhang = [(0, 17), (1, 17), (2, 17)];
+        assert_eq!(
+            inputs(PieceType('T'), &[(1, 18), (0, 19), (1, 19), (2, 19)], &overhang),
+            None
+        );
+    }
+
+    #[test]
+    fn counts_extra_presses_as_faults() {
+        let piece_type = PieceType('T');
+        let locked = real_blocks(&[(8, 18), (7, 19), (8, 19), (9, 19)]);
+        let mut world = World::new();
+        world.insert_resource(PieceSet::default());
+        world.init_resource::<Events<PieceLocked>>();
+        let board = world
+            .spawn((MainBoard, FinesseTracker::default(), SessionStats::default()))
+            .id();
+        // 固定后的骨牌方块已经是面板的子实体
+        for block in &locked {
+            world.spawn(*block).set_parent(board);
+        }
+
+        let lock = |world: &mut World, presses: u32| {
+            let mut tracker = world.get_mut::<FinesseTracker>(board).unwrap();
+            tracker.spawn = Some((piece_type, spawn_blocks(piece_type)));
+            tracker.presses = presses;
+            world.send_event(PieceLocked {
+                board,
+                piece_type,
+                blocks: locked.clone(),
+                t_spin: false,
+                hard_drop: true,
+            });
+            world.run_system_once(check_finesse);
+        };
+
+        // 按了三次右键，最优为一次长按右
+        lock(&mut world, 3);
+        assert_eq!(world.get::<SessionStats>(board).unwrap().finesse_faults, 2);
+        assert_eq!(
+            world.get::<FinesseTracker>(board).unwrap().last_fault,
+            Some(vec![FinesseInput::HoldRight])
+        );
+        lock(&mut world, 1);
+        assert_eq!(world.get::<SessionStats>(board).unwrap().finesse_faults, 2);
+        assert_eq!(world.get::<FinesseTracker>(board).unwrap().last_fault, None);
+    }
+}
@@ -0,0 +1,248 @@
+use bevy::{prelude::*, sprite::Anchor};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{
+    board::{Block, MainBoard, BLOCK_LENGTH, BLOCK_Z, COL_COUNT, ROW_COUNT},
+    mode::{GameConfig, GameMode, SURVIVAL_INTERVAL_STEP, SURVIVAL_MIN_INTERVAL},
+    piece::PieceType,
+    state::{BASE_OFFSET, spawn_board_block},
+    theme::BlockSkin,
+};
+
+// 垃圾行方块颜色
+pub const GARBAGE_COLOR: Color = Color::srgb(0.5, 0.5, 0.5);
+
+// 垃圾行上升警告条颜色
+const WARNING_COLOR: Color = Color::srgba(1.0, 0.6, 0.0, 0.6);
+const DANGER_COLOR: Color = Color::srgba(1.0, 0.1, 0.1, 0.8);
+
+// 剩余时间少于该值时警告条变红
+const DANGER_SECONDS: f32 = 2.0;
+
+// 垃圾行中的方块
+#[derive(Component, Debug)]
+pub struct GarbageBlock;
+
+// 生存模式面板底部的警告条，长度表示距离下一次垃圾行上升的进度
+#[derive(Component, Debug)]
+pub struct GarbageWarningBar;
+
+// 生存模式垃圾行上升计时器
+#[derive(Debug, Component)]
+pub struct GarbageRiseTimer(pub Timer);
+
+// 生成垃圾行的缺口位置
+#[derive(Debug, Component)]
+pub struct GarbageGenerator {
+    // 上一行缺口所在的列
+    pub hole_column: i32,
+    // 每生成一行时缺口换到其他列的概率，0 表示缺口始终在同一列，1 表示每行都换
+    pub messiness: f32,
+    pub rng: StdRng,
+}
+
+impl GarbageGenerator {
+    pub fn new(messiness: f32, seed: u64) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+        Self {
+            hole_column: rng.gen_range(0..COL_COUNT as i32),
+            messiness,
+            rng,
+        }
+    }
+
+    // 下一行垃圾的缺口所在列（面板上的真实列号）
+    pub fn next_hole(&mut self) -> i32 {
+        if self.rng.gen::<f32>() < self.messiness {
+            // 换到不同的列
+            let offset = self.rng.gen_range(1..COL_COUNT as i32);
+            self.hole_column = (self.hole_column + offset) % COL_COUNT as i32;
+        }
+        self.hole_column
+    }
+}
+
+// 在面板底部插入垃圾行，已有的方块整体上移；如果当前骨牌与上移后的方块重叠，骨牌也随之上移
+//
+// holes 为每行缺口所在的真实列号，第一个元素是最底下的一行
+pub fn insert_garbage_rows(
+    commands: &mut Commands,
+    main_board: Entity,
+    board_blocks: &mut Query<(&Parent, &mut Block), Without<PieceType>>,
+    piece_blocks: &mut Query<(&Parent, &mut Block), With<PieceType>>,
+    holes: &[i32],
+) {
+    let row_count = holes.len() as i32;
+    if row_count == 0 {
+        return;
+    }
+
+    // 已有方块整体上移
+    for (parent, mut block) in board_blocks.iter_mut() {
+        if parent.get() != main_board {
+            continue;
+        }
+        block.y -= row_count;
+    }
+
+    // 生成新的垃圾行
+    let mut new_blocks = Vec::new();
+    for (index, hole) in holes.iter().enumerate() {
+        let real_y = ROW_COUNT as i32 - 1 - index as i32;
+        for real_x in 0..COL_COUNT as i32 {
+            if real_x == *hole {
+                continue;
+            }
+            new_blocks.push(Block {
+                x: real_x - BASE_OFFSET.0,
+                y: real_y - BASE_OFFSET.1,
+            });
+        }
+    }
+    commands.entity(main_board).with_children(|parent| {
+        for block in new_blocks.iter() {
+            spawn_board_block(parent, *block, GARBAGE_COLOR, BlockSkin::Garbage);
+        }
+    });
+
+    // 当前骨牌如果与方块重叠，则向上推
+    let mut occupied: Vec<Block> = board_blocks
+        .iter()
+        .filter(|(parent, _)| parent.get() == main_board)
+        .map(|(_, block)| *block)
+        .collect();
+    occupied.extend(new_blocks);
+    for _ in 0..row_count {
+        let overlapping = piece_blocks
+            .iter()
+            .any(|(parent, block)| parent.get() == main_board && occupied.contains(block));
+        if !overlapping {
+            break;
+        }
+        for (parent, mut block) in piece_blocks.iter_mut() {
+            if parent.get() != main_board {
+                continue;
+            }
+            block.y -= 1;
+        }
+    }
+}
+
+// 新游戏开始时按模式生成初始垃圾行，生存模式还会生成上升警告条
+pub fn spawn_initial_garbage(
+    mut commands: Commands,
+    config: Res<GameConfig>,
+    mut main_board: Query<(Entity, &mut GarbageGenerator), With<MainBoard>>,
+    mut board_blocks: Query<(&Parent, &mut Block), Without<PieceType>>,
+    mut piece_blocks: Query<(&Parent, &mut Block), With<PieceType>>,
+) {
+    for (main_board_entity, mut generator) in &mut main_board {
+        match config.mode {
+            GameMode::Cheese => {
+                let holes: Vec<i32> =
+                    (0..config.cheese_rows).map(|_| generator.next_hole()).collect();
+                insert_garbage_rows(
+                    &mut commands,
+                    main_board_entity,
+                    &mut board_blocks,
+                    &mut piece_blocks,
+                    &holes,
+                );
+            }
+            GameMode::Survival => {
+                commands.entity(main_board_entity).with_children(|parent| {
+                    // 从面板左下角向右伸长，显示在方块上面
+                    parent.spawn((
+                        SpriteBundle {
+                            sprite: Sprite {
+                                color: WARNING_COLOR,
+                                custom_size: Some(Vec2::new(0.0, BLOCK_LENGTH / 5.0)),
+                                anchor: Anchor::BottomLeft,
+                                ..default()
+                            },
+                            transform: Transform::from_xyz(
+                                0.0,
+                                -(BLOCK_LENGTH * ROW_COUNT as f32),
+                                BLOCK_Z + 0.5,
+                            ),
+                            ..default()
+                        },
+                        GarbageWarningBar,
+                    ));
+                });
+            }
+            _ => {}
+        }
+    }
+}
+
+// 生存模式：计时结束时从底部升起一行垃圾，并缩短下一次上升的间隔
+pub fn rise_garbage(
+    mut commands: Commands,
+    config: Res<GameConfig>,
+    time: Res<Time>,
+    mut main_board: Query<(Entity, &mut GarbageRiseTimer, &mut GarbageGenerator), With<MainBoard>>,
+    mut board_blocks: Query<(&Parent, &mut Block), Without<PieceType>>,
+    mut piece_blocks: Query<(&Parent, &mut Block), With<PieceType>>,
+) {
+    if config.mode != GameMode::Survival {
+        return;
+    }
+    for (main_board_entity, mut timer, mut generator) in &mut main_board {
+        timer.0.tick(time.delta());
+        if !timer.0.just_finished() {
+            continue;
+        }
+        let hole = generator.next_hole();
+        insert_garbage_rows(
+            &mut commands,
+            main_board_entity,
+            &mut board_blocks,
+            &mut piece_blocks,
+            &[hole],
+        );
+        let next_interval = timer
+            .0
+            .duration()
+            .saturating_sub(SURVIVAL_INTERVAL_STEP)
+            .max(SURVIVAL_MIN_INTERVAL);
+        timer.0.set_duration(next_interval);
+        timer.0.reset();
+    }
+}
+
+// 更新警告条长度，快要上升时变红
+pub fn update_garbage_warning(
+    main_board: Query<&GarbageRiseTimer, With<MainBoard>>,
+    mut query: Query<(&Parent, &mut Sprite), With<GarbageWarningBar>>,
+) {
+    for (parent, mut sprite) in &mut query {
+        let Ok(timer) = main_board.get(parent.get()) else {
+            continue;
+        };
+        sprite.custom_size = Some(Vec2::new(
+            timer.0.fraction() * BLOCK_LENGTH * COL_COUNT as f32,
+            BLOCK_LENGTH / 5.0,
+        ));
+        sprite.color = if timer.0.remaining_secs() < DANGER_SECONDS {
+            DANGER_COLOR
+        } else {
+            WARNING_COLOR
+        };
+    }
+}
+
+// 指定面板上剩余的垃圾行数
+pub fn remaining_garbage_rows(
+    garbage_blocks: &Query<(&Parent, &Block), With<GarbageBlock>>,
+    main_board: Entity,
+) -> u32 {
+    let mut rows: Vec<i32> = garbage_blocks
+        .iter()
+        .filter(|(parent, _)| parent.get() == main_board)
+        .map(|(_, block)| block.y)
+        .collect();
+    rows.sort();
+    rows.dedup();
+    rows.len() as u32
+}
@@ -0,0 +1,355 @@
+use std::{fs, time::Duration};
+
+use bevy::{
+    hierarchy::HierarchyPlugin, input::InputPlugin, prelude::*, state::app::StatesPlugin,
+    time::TimeUpdateStrategy,
+};
+
+use crate::{
+    board::{MainBoard, ToppedOut},
+    bot::{BotPlugin, BotWeights},
+    common::{AppState, RestartGame},
+    mode::{GameConfig, GameMode},
+    player::{GameSeed, InputMap, Player, PlayerInput},
+    rules::GameRulesPlugin,
+    state::{Level, Lines, Score, SessionStats},
+};
+
+pub const USAGE: &str = "usage: simulate [--games N] [--mode KEY] [--seed N] [--max-pieces N] \
+[--max-idle-frames N] [--step-ms N] [--weights HEIGHT,LINES,HOLES,BUMPINESS] [--replay FILE]";
+
+// 无界面模拟的参数
+#[derive(Debug, Clone)]
+pub struct SimulationOptions {
+    pub games: u32,
+    pub mode: GameMode,
+    // 第 N 局使用 seed + N 作为随机种子
+    pub seed: u64,
+    // 放置的骨牌数达到上限时结束这一局，防止无尽模式一直进行下去
+    pub max_pieces: u32,
+    // 连续这么多帧没有放置新的骨牌（包括游戏一直没有开始）时结束这一局，防止卡住的对局一直运行
+    pub max_idle_frames: u32,
+    // 每帧推进的游戏时间
+    pub step: Duration,
+    pub weights: BotWeights,
+    // 回放文件，为 None 时由机器人操作
+    pub replay: Option<String>,
+}
+
+impl Default for SimulationOptions {
+    fn default() -> Self {
+        Self {
+            games: 1,
+            mode: GameMode::Marathon,
+            seed: 0,
+            max_pieces: 1000,
+            max_idle_frames: 10_000,
+            step: Duration::from_millis(16),
+            weights: BotWeights::default(),
+            replay: None,
+        }
+    }
+}
+
+impl SimulationOptions {
+    // 解析命令行参数
+    pub fn from_args(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut options = Self::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or_else(|| format!("missing value for {arg}"));
+            match arg.as_str() {
+                "--games" => options.games = parse_number(&value()?)?,
+                "--mode" => {
+                    let key = value()?;
+                    options.mode =
+                        GameMode::from_key(&key).ok_or_else(|| format!("unknown mode: {key}"))?;
+                }
+                "--seed" => options.seed = parse_number(&value()?)?,
+                "--max-pieces" => options.max_pieces = parse_number(&value()?)?,
+                "--max-idle-frames" => options.max_idle_frames = parse_number(&value()?)?,
+                "--step-ms" => options.step = Duration::from_millis(parse_number(&value()?)?),
+                "--weights" => options.weights = parse_weights(&value()?)?,
+                "--replay" => options.replay = Some(value()?),
+                _ => return Err(format!("unknown argument: {arg}")),
+            }
+        }
+        if options.step.is_zero() {
+            return Err("--step-ms must be greater than 0".to_string());
+        }
+        if options.max_idle_frames == 0 {
+            return Err("--max-idle-frames must be greater than 0".to_string());
+        }
+        Ok(options)
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(value: &str) -> Result<T, String> {
+    value.parse().map_err(|_| format!("invalid number: {value}"))
+}
+
+fn parse_weights(value: &str) -> Result<BotWeights, String> {
+    let weights = value
+        .split(',')
+        .map(|weight| weight.trim().parse::<f32>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| format!("invalid weights: {value}"))?;
+    let [aggregate_height, lines_cleared, holes, bumpiness] = weights[..] else {
+        return Err(format!("expected 4 weights: {value}"));
+    };
+    Ok(BotWeights {
+        aggregate_height,
+        lines_cleared,
+        holes,
+        bumpiness,
+    })
+}
+
+// 回放文件：可选的 `seed N`、`mode KEY` 头部，之后每行是一帧 Player 1 的操作
+//
+// 操作由 L（左）R（右）D（下）U（旋转）H（直接落下）组成，`.` 表示这一帧没有操作，# 开头的行是注释
+#[derive(Debug, Default)]
+pub struct Replay {
+    pub seed: Option<u64>,
+    pub mode: Option<GameMode>,
+    pub frames: Vec<PlayerInput>,
+}
+
+impl Replay {
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|err| format!("{path}: {err}"))?;
+        Self::parse(&text).map_err(|err| format!("{path}: {err}"))
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut replay = Self::default();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some(seed) = line.strip_prefix("seed ") {
+                replay.seed = Some(parse_number(seed.trim())?);
+                continue;
+            }
+            if let Some(key) = line.strip_prefix("mode ") {
+                let key = key.trim();
+                replay.mode =
+                    Some(GameMode::from_key(key).ok_or_else(|| format!("unknown mode: {key}"))?);
+                continue;
+            }
+            let mut input = PlayerInput::default();
+            for action in line.chars() {
+                match action {
+                    'L' => input.left = true,
+                    'R' => input.right = true,
+                    'D' => input.down = true,
+                    'U' => input.rotate = true,
+                    'H' => input.hard_drop = true,
+                    '.' => {}
+                    _ => return Err(format!("line {}: unknown action {action}", index + 1)),
+                }
+            }
+            replay.frames.push(input);
+        }
+        Ok(replay)
+    }
+}
+
+// 一局结束的原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GameEnd {
+    TopOut,
+    // 完成模式目标
+    Goal,
+    TimeUp,
+    PieceLimit,
+    // 回放的操作已经用完
+    ReplayEnd,
+    // 长时间没有放置新的骨牌
+    Stalled,
+}
+
+impl GameEnd {
+    pub fn key(&self) -> &'static str {
+        match self {
+            GameEnd::TopOut => "top_out",
+            GameEnd::Goal => "goal",
+            GameEnd::TimeUp => "time_up",
+            GameEnd::PieceLimit => "piece_limit",
+            GameEnd::ReplayEnd => "replay_end",
+            GameEnd::Stalled => "stalled",
+        }
+    }
+}
+
+// 创建不带窗口、渲染和音频的 App，只运行游戏规则和机器人
+pub fn build_app(options: &SimulationOptions) -> App {
+    let mut app = App::new();
+    app.add_plugins((
+        MinimalPlugins,
+        StatesPlugin,
+        InputPlugin,
+        HierarchyPlugin,
+        GameRulesPlugin,
+        BotPlugin,
+    ))
+    .insert_resource(TimeUpdateStrategy::ManualDuration(options.step))
+    .insert_resource(options.weights);
+    app.finish();
+    app.cleanup();
+    app
+}
+
+// 按参数运行所有对局，每局结束后向标准输出打印一行 JSON
+pub fn run(options: &SimulationOptions) -> Result<(), String> {
+    let replay = options.replay.as_deref().map(Replay::load).transpose()?;
+    let mut app = build_app(options);
+    // 回放只有一局
+    let games = if replay.is_some() { 1 } else { options.games };
+    for game in 0..games {
+        let mode = replay
+            .as_ref()
+            .and_then(|replay| replay.mode)
+            .unwrap_or(options.mode);
+        let seed = replay
+            .as_ref()
+            .and_then(|replay| replay.seed)
+            .unwrap_or(options.seed.wrapping_add(game as u64));
+        {
+            let mut config = app.world_mut().resource_mut::<GameConfig>();
+            config.mode = mode;
+            config.seed = Some(seed);
+            config.demo = replay.is_none();
+            // 回放对战时由机器人扮演对手
+            config.versus_bot = replay.is_some() && mode.player_count() > 1;
+        }
+        app.world_mut().send_event(RestartGame);
+        let end = play_game(&mut app, replay.as_ref(), options);
+        println!("{}", game_report(app.world_mut(), game, mode, end));
+    }
+    Ok(())
+}
+
+// 推进一局直到结束
+fn play_game(app: &mut App, replay: Option<&Replay>, options: &SimulationOptions) -> GameEnd {
+    let mut started = false;
+    let mut frame = 0;
+    let mut last_pieces = 0;
+    let mut idle_frames = 0;
+    loop {
+        // 面板生成后才开始消耗回放的操作
+        if started {
+            if let Some(replay) = replay {
+                let Some(input) = replay.frames.get(frame) else {
+                    finish_game(app);
+                    return GameEnd::ReplayEnd;
+                };
+                apply_replay_input(app.world_mut(), *input);
+                frame += 1;
+            }
+        }
+        app.update();
+
+        let state = app.world().resource::<State<AppState>>().get().clone();
+        match state {
+            AppState::InGame => started = true,
+            AppState::GameOver if started => return GameEnd::TopOut,
+            AppState::Victory if started => return GameEnd::Goal,
+            AppState::TimeUp if started => return GameEnd::TimeUp,
+            _ => {}
+        }
+        let pieces = if started { pieces_placed(app.world_mut()) } else { 0 };
+        if started && pieces >= options.max_pieces {
+            finish_game(app);
+            return GameEnd::PieceLimit;
+        }
+        if pieces != last_pieces {
+            last_pieces = pieces;
+            idle_frames = 0;
+        } else {
+            idle_frames += 1;
+        }
+        if idle_frames >= options.max_idle_frames {
+            finish_game(app);
+            return GameEnd::Stalled;
+        }
+    }
+}
+
+// 提前结束这一局，之后的 RestartGame 会从 GameOver 重新进入 InGame
+fn finish_game(app: &mut App) {
+    app.world_mut()
+        .resource_mut::<NextState<AppState>>()
+        .set(AppState::GameOver);
+    app.update();
+}
+
+// 回放操作 Player 1 的面板，键盘输入不再生效
+fn apply_replay_input(world: &mut World, input: PlayerInput) {
+    let mut query = world.query_filtered::<(Entity, &Player), With<MainBoard>>();
+    let Some(entity) = query
+        .iter(world)
+        .find(|(_, player)| player.0 == 0)
+        .map(|(entity, _)| entity)
+    else {
+        return;
+    };
+    world
+        .entity_mut(entity)
+        .remove::<InputMap>()
+        .insert(input);
+}
+
+fn pieces_placed(world: &mut World) -> u32 {
+    let mut query = world.query_filtered::<&SessionStats, With<MainBoard>>();
+    query
+        .iter(world)
+        .map(|stats| stats.pieces_placed)
+        .max()
+        .unwrap_or(0)
+}
+
+// 一局的结果，Player 1 的数据放在顶层，对战时再附上胜者
+fn game_report(world: &mut World, game: u32, mode: GameMode, end: GameEnd) -> String {
+    let seed = world.get_resource::<GameSeed>().map_or(0, |seed| seed.0);
+    let mut query = world.query_filtered::<
+        (&Player, &Score, &Lines, &Level, &SessionStats, Has<ToppedOut>),
+        With<MainBoard>,
+    >();
+    let mut players: Vec<_> = query.iter(world).collect();
+    players.sort_by_key(|(player, ..)| player.0);
+
+    let mut report = format!(
+        "{{\"game\":{game},\"seed\":{seed},\"mode\":\"{}\",\"end\":\"{}\"",
+        mode.key(),
+        end.key()
+    );
+    if let Some((_, score, lines, level, stats, _)) = players.first() {
+        report.push_str(&format!(
+            ",\"score\":{},\"lines\":{},\"level\":{},\"pieces\":{},\"tetrises\":{},\"t_spins\":{},\"finesse_faults\":{},\"time_ms\":{}",
+            score.0,
+            lines.0,
+            level.0,
+            stats.pieces_placed,
+            stats.tetrises,
+            stats.t_spins,
+            stats.finesse_faults,
+            stats.play_time.as_millis()
+        ));
+    }
+    if mode.player_count() > 1 {
+        let survivors: Vec<usize> = players
+            .iter()
+            .filter(|(.., topped_out)| !topped_out)
+            .map(|(player, ..)| player.0 + 1)
+            .collect();
+        let winner = match survivors[..] {
+            [winner] => winner.to_string(),
+            _ => "null".to_string(),
+        };
+        report.push_str(&format!(",\"winner\":{winner}"));
+    }
+    report.push('}');
+    report
+}
@@ -0,0 +1,151 @@
+use std::{cmp::Ordering, time::Duration};
+
+use bevy::prelude::*;
+
+use crate::{
+    board::MainBoard,
+    common::AppState,
+    mode::{GameConfig, GameMode, Ranking},
+    state::{format_duration, Score, SessionStats},
+};
+
+// 每个模式保留的最高分条数
+pub const HIGH_SCORE_COUNT: usize = 10;
+
+// 最高分保存文件
+const HIGH_SCORE_FILE: &str = "highscores.txt";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HighScoreEntry {
+    pub mode: GameMode,
+    pub score: u32,
+    // 本局用时，竞速模式按用时排名
+    pub time: Duration,
+}
+
+impl HighScoreEntry {
+    // 排名比较，排在前面的成绩更好
+    fn rank_cmp(&self, other: &Self) -> Ordering {
+        match self.mode.ranking() {
+            Ranking::HighestScore => other.score.cmp(&self.score),
+            Ranking::ShortestTime => self.time.cmp(&other.time),
+            Ranking::LongestTime => other.time.cmp(&self.time),
+        }
+    }
+
+    // 排行榜中展示的成绩
+    pub fn display(&self) -> String {
+        match self.mode.ranking() {
+            Ranking::HighestScore => self.score.to_string(),
+            Ranking::ShortestTime | Ranking::LongestTime => format_duration(self.time),
+        }
+    }
+}
+
+// 最高分排行榜
+#[derive(Debug, Default, Resource)]
+pub struct HighScores(pub Vec<HighScoreEntry>);
+
+impl HighScores {
+    // 某个模式的排行榜，最好的成绩排在最前
+    pub fn for_mode(&self, mode: GameMode) -> Vec<&HighScoreEntry> {
+        let mut entries: Vec<&HighScoreEntry> =
+            self.0.iter().filter(|entry| entry.mode == mode).collect();
+        entries.sort_by(|a, b| a.rank_cmp(b));
+        entries.truncate(HIGH_SCORE_COUNT);
+        entries
+    }
+
+    pub fn insert(&mut self, entry: HighScoreEntry) {
+        let mode = entry.mode;
+        self.0.push(entry);
+        // 只保留每个模式的前 HIGH_SCORE_COUNT 名
+        let kept: Vec<HighScoreEntry> = self.for_mode(mode).into_iter().cloned().collect();
+        self.0.retain(|entry| entry.mode != mode);
+        self.0.extend(kept);
+    }
+
+    // 每行格式：<模式> <分数> <用时毫秒>
+    fn parse(content: &str) -> Self {
+        let entries = content
+            .lines()
+            .filter_map(|line| {
+                let mut parts = line.split_whitespace();
+                let mode = GameMode::from_key(parts.next()?)?;
+                let score = parts.next()?.parse().ok()?;
+                let millis = parts.next().and_then(|part| part.parse().ok()).unwrap_or(0);
+                Some(HighScoreEntry {
+                    mode,
+                    score,
+                    time: Duration::from_millis(millis),
+                })
+            })
+            .collect();
+        HighScores(entries)
+    }
+
+    fn serialize(&self) -> String {
+        self.0
+            .iter()
+            .map(|entry| {
+                format!(
+                    "{} {} {}\n",
+                    entry.mode.key(),
+                    entry.score,
+                    entry.time.as_millis()
+                )
+            })
+            .collect()
+    }
+}
+
+// 从文件加载最高分，文件不存在时使用空排行榜
+pub fn setup_high_scores_resource(mut commands: Commands) {
+    #[cfg(not(target_arch = "wasm32"))]
+    let high_scores = std::fs::read_to_string(HIGH_SCORE_FILE)
+        .map(|content| HighScores::parse(&content))
+        .unwrap_or_default();
+    #[cfg(target_arch = "wasm32")]
+    let high_scores = HighScores::default();
+
+    commands.insert_resource(high_scores);
+}
+
+fn save_high_scores(high_scores: &HighScores) {
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Err(err) = std::fs::write(HIGH_SCORE_FILE, high_scores.serialize()) {
+        warn!("failed to save high scores: {}", err);
+    }
+    #[cfg(target_arch = "wasm32")]
+    let _ = high_scores;
+}
+
+// 游戏结束时记录本局成绩，并判断是否刷新了最好成绩
+pub fn record_high_score(
+    app_state: Res<State<AppState>>,
+    config: Res<GameConfig>,
+    mut main_board: Query<(&Score, &mut SessionStats), With<MainBoard>>,
+    mut high_scores: ResMut<HighScores>,
+) {
+    if !config.mode.has_high_scores() || config.demo {
+        return;
+    }
+    let Ok((score, mut stats)) = main_board.get_single_mut() else {
+        return;
+    };
+    if *app_state.get() == AppState::GameOver && !config.mode.records_on_top_out() {
+        stats.new_personal_best = false;
+        return;
+    }
+    let entry = HighScoreEntry {
+        mode: config.mode,
+        score: score.0,
+        time: stats.play_time,
+    };
+    stats.new_personal_best = match high_scores.for_mode(config.mode).first() {
+        Some(best) => entry.rank_cmp(best) == Ordering::Less,
+        None => config.mode.ranking() != Ranking::HighestScore || score.0 > 0,
+    };
+    high_scores.insert(entry);
+    save_high_scores(&high_scores);
+}
@@ -0,0 +1,120 @@
+use bevy::{prelude::*, transform::TransformSystem};
+
+use crate::{
+    board::{board_translation, Block, BLOCK_LENGTH},
+    common::{AppState, GameSettings},
+    mode::check_mode_goal,
+    piece::PieceType,
+};
+
+// 显示位置与逻辑位置的差距减半所需的时间（秒）
+const HALF_LIFE: f32 = 0.025;
+// 差距小于该值（像素）时直接对齐
+const SNAP_DISTANCE: f32 = 0.5;
+// 当前骨牌显示时最多落后的距离，重力很快时也不会明显滞后
+const MAX_PIECE_LAG: f32 = BLOCK_LENGTH;
+
+// 方块的显示位置：规则只修改方块的逻辑位置，这里把它同步到精灵的位置。
+// 平滑移动时逻辑位置仍然逐格变化，显示位置逐渐追上逻辑位置，不影响游戏规则
+pub struct InterpolationPlugin;
+
+impl Plugin for InterpolationPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            PostUpdate,
+            smooth_block_movement
+                .after(check_mode_goal)
+                .before(TransformSystem::TransformPropagate)
+                .run_if(in_state(AppState::InGame)),
+        );
+    }
+}
+
+// 方块的显示位置，offset 为显示位置相对逻辑位置的偏移
+#[derive(Debug, Component)]
+pub struct SmoothBlock {
+    // 上一帧的逻辑位置
+    pub last: Block,
+    pub offset: Vec2,
+}
+
+// 下落、左右移动、消行下移和垃圾行上升时平滑移动；旋转时形状改变，直接对齐。
+// 面板上方（还没进入面板）的方块不显示
+pub fn smooth_block_movement(
+    mut commands: Commands,
+    settings: Res<GameSettings>,
+    time: Res<Time>,
+    mut new_blocks: Query<
+        (Entity, &Block, &mut Transform, &mut Visibility),
+        (With<Sprite>, Without<SmoothBlock>),
+    >,
+    mut blocks: Query<(
+        &Parent,
+        &Block,
+        Has<PieceType>,
+        &mut SmoothBlock,
+        &mut Transform,
+        &mut Visibility,
+    )>,
+) {
+    // 新生成的方块直接显示在逻辑位置
+    for (entity, block, mut transform, mut visibility) in &mut new_blocks {
+        commands.entity(entity).insert(SmoothBlock {
+            last: *block,
+            offset: Vec2::ZERO,
+        });
+        transform.translation = board_translation(block.transform_to(), transform.translation.z);
+        *visibility = block_visibility(block);
+    }
+
+    // 当前骨牌的各个方块移动的距离不同时是旋转
+    let mut piece_moves: Vec<(Entity, IVec2, bool)> = Vec::new();
+    for (parent, block, is_piece, smooth, ..) in &blocks {
+        if !is_piece {
+            continue;
+        }
+        let delta = IVec2::new(block.x - smooth.last.x, block.y - smooth.last.y);
+        match piece_moves.iter_mut().find(|(board, ..)| *board == parent.get()) {
+            Some((_, first_delta, rotated)) => *rotated |= *first_delta != delta,
+            None => piece_moves.push((parent.get(), delta, false)),
+        }
+    }
+
+    let decay = 0.5_f32.powf(time.delta_seconds() / HALF_LIFE);
+    for (parent, block, is_piece, mut smooth, mut transform, mut visibility) in &mut blocks {
+        let moved = smooth.last != *block;
+        if !moved && smooth.offset == Vec2::ZERO {
+            continue;
+        }
+        let target = block.transform_to();
+        let rotated = is_piece
+            && piece_moves
+                .iter()
+                .any(|(board, _, rotated)| *board == parent.get() && *rotated);
+        if !settings.smooth_movement || rotated {
+            smooth.offset = Vec2::ZERO;
+        } else {
+            let last = smooth.last.transform_to();
+            smooth.offset += last - target;
+            if is_piece {
+                smooth.offset = smooth.offset.clamp_length_max(MAX_PIECE_LAG);
+            }
+            smooth.offset *= decay;
+            if smooth.offset.length() < SNAP_DISTANCE {
+                smooth.offset = Vec2::ZERO;
+            }
+        }
+        smooth.last = *block;
+        transform.translation =
+            board_translation(target + smooth.offset, transform.translation.z);
+        visibility.set_if_neq(block_visibility(block));
+    }
+}
+
+fn block_visibility(block: &Block) -> Visibility {
+    if block.transform_to_real_pos().1 < 0 {
+        Visibility::Hidden
+    } else {
+        Visibility::Inherited
+    }
+}
@@ -0,0 +1,115 @@
+use bevy::{
+    prelude::*,
+    render::camera::{ScalingMode, Viewport},
+    ui::UiSystem,
+    window::{PrimaryWindow, WindowMode},
+};
+
+use crate::common::{GameSettings, WindowSize};
+
+// 自适应布局：界面和面板按固定的设计尺寸布局，窗口大小或 DPI 改变时整体缩放到能完整显示的最大尺寸，
+// 宽高比不同时两侧或上下留出窗口背景色的边
+pub struct LayoutPlugin;
+
+impl Plugin for LayoutPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (
+                toggle_fullscreen,
+                apply_window_mode.run_if(resource_changed::<GameSettings>),
+            )
+                .chain(),
+        )
+        .add_systems(PostUpdate, fit_camera_to_window.before(UiSystem::Layout));
+    }
+}
+
+// 窗口坐标（逻辑像素，以窗口左上角为原点）转换为世界坐标，窗口两侧留出的背景不在视口中
+pub fn window_to_world(
+    camera: &Camera,
+    camera_transform: &GlobalTransform,
+    position: Vec2,
+) -> Option<Vec2> {
+    let viewport_min = camera
+        .logical_viewport_rect()
+        .map_or(Vec2::ZERO, |rect| rect.min);
+    camera.viewport_to_world_2d(camera_transform, position - viewport_min)
+}
+
+// 界面根节点的大小，也是相机看到的世界坐标范围
+pub fn ui_root_size(camera: &Camera, ui_scale: &UiScale) -> Option<Vec2> {
+    camera
+        .logical_viewport_size()
+        .map(|size| size / ui_scale.0)
+}
+
+// 按 F11 切换全屏
+pub fn toggle_fullscreen(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    mut settings: ResMut<GameSettings>,
+) {
+    if keyboard_input.just_pressed(KeyCode::F11) {
+        settings.fullscreen = !settings.fullscreen;
+    }
+}
+
+pub fn apply_window_mode(
+    settings: Res<GameSettings>,
+    mut windows: Query<&mut Window, With<PrimaryWindow>>,
+) {
+    let Ok(mut window) = windows.get_single_mut() else {
+        return;
+    };
+    let mode = if settings.fullscreen {
+        WindowMode::BorderlessFullscreen
+    } else {
+        WindowMode::Windowed
+    };
+    if window.mode != mode {
+        window.mode = mode;
+    }
+}
+
+// 按窗口大小计算缩放比例，相机只渲染窗口中间保持设计宽高比的区域，界面和精灵使用同一个缩放比例
+pub fn fit_camera_to_window(
+    windows: Query<&Window, With<PrimaryWindow>>,
+    mut ui_scale: ResMut<UiScale>,
+    mut cameras: Query<(&mut Camera, &mut OrthographicProjection), With<Camera2d>>,
+) {
+    let Ok(window) = windows.get_single() else {
+        return;
+    };
+    let design = WindowSize::default();
+    let scale = (window.width() / design.width).min(window.height() / design.height);
+    // 窗口最小化时大小为 0
+    if scale <= 0.0 {
+        return;
+    }
+    let window_size = UVec2::new(window.physical_width(), window.physical_height());
+    let viewport_size = (Vec2::new(design.width, design.height) * scale * window.scale_factor())
+        .round()
+        .as_uvec2()
+        .min(window_size);
+    let viewport = Viewport {
+        physical_position: (window_size - viewport_size) / 2,
+        physical_size: viewport_size,
+        ..default()
+    };
+    // 只在变化时修改，避免每帧触发重新布局
+    if ui_scale.0 != scale {
+        ui_scale.0 = scale;
+    }
+    for (mut camera, mut projection) in &mut cameras {
+        let unchanged = camera.viewport.as_ref().is_some_and(|current| {
+            current.physical_position == viewport.physical_position
+                && current.physical_size == viewport.physical_size
+        });
+        if !unchanged {
+            camera.viewport = Some(viewport.clone());
+        }
+        if !matches!(projection.scaling_mode, ScalingMode::WindowSize(current) if current == scale) {
+            projection.scaling_mode = ScalingMode::WindowSize(scale);
+        }
+    }
+}
@@ -1,43 +1,75 @@
-use std::time::Duration;
+// Bevy 的系统参数和查询类型本身就长，这两个 lint 对系统函数没有意义
+#![allow(clippy::type_complexity, clippy::too_many_arguments)]
 
-use bevy::{prelude::*, window::WindowResolution};
-use board::{check_game_overs, setup_game_board, show_game_over_menu, update_next_piece_board, HasNextPiece, MainBoard, BLOCK_LENGTH};
-use common::{setup_font_assets_resource, setup_game_audios_resource, AppState, GameAudios, WindowSize};
-use menu::click_button;
-use piece::{
-    auto_generate_new_piece, check_collision, check_full_line, move_piece, remove_piece_component, rotate_piece, setup_piece_queue, AutoMovePieceDownTimer, ManuallyMoveTimer, RemovePieceComponentTimer
+use bevy::{prelude::*, transform::TransformSystem, ui::UiSystem, window::WindowResolution};
+use asset_errors::{setup_asset_error_text, update_asset_error_text};
+use bot::BotPlugin;
+use board::{despawn_game_entities, follow_ui_nodes, hide_game_board, setup_game_board, show_game_board, show_game_over_menu, update_next_piece_board};
+use editor::{handle_editor_actions, paint_editor_board, show_editor, stop_playtest, toggle_playtest, update_editor_board, EditorAction, EditorState};
+use common::{setup_font_assets_resource, AppState, MenuScreen, WindowSize};
+use effects::EffectsPlugin;
+use finesse::update_finesse_hint;
+use interpolation::InterpolationPlugin;
+use layout::LayoutPlugin;
+use sound::SoundPlugin;
+use garbage::{spawn_initial_garbage, update_garbage_warning};
+use highscore::{record_high_score, setup_high_scores_resource};
+use menu::{click_button, edit_net_address, pause_on_focus_lost, show_high_scores_menu, show_main_menu, show_mode_select_menu, show_network_menu, show_pause_menu, toggle_pause, update_button_color, update_menu_labels};
+use mode::check_mode_goal;
+use piece::{animate_line_clear, move_piece, remove_piece_component, rotate_piece};
+use piece_set::PieceSetPlugin;
+use rule_set::RuleSetPlugin;
+use network::{
+    accept_connection, close_network_session, flush_network_session, handle_network_actions, receive_network_messages, send_local_input, send_local_locks, send_top_out, setup_network_boards, wait_for_opponent, NetConfig, NetListener, NetSession, NetStatus, NetworkAction
 };
-use state::{setup_post_states_boards, update_scoreboard, Score};
+use player::read_keyboard_input;
+use puzzle::{show_puzzle_result_menu, show_puzzle_select_menu, PuzzlePlugin};
+use rules::{GameRulesPlugin, NewGameSetup};
+use settings::{show_settings_menu, SettingsReturnState};
+use theme::ThemePlugin;
+use state::{count_locked_pieces, setup_post_states_boards, update_linesboard, update_modeboard, update_scoreboard};
+use versus::update_garbage_meter;
 
+mod asset_errors;
 mod board;
+mod bot;
 mod common;
+mod editor;
+mod effects;
+mod finesse;
+mod garbage;
+pub mod headless;
+mod highscore;
+mod interpolation;
+mod layout;
 mod piece;
+mod piece_set;
+mod puzzle;
+mod player;
+mod rule_set;
+mod rules;
+mod scoring;
 mod state;
 mod menu;
+mod mode;
+mod network;
+mod settings;
+mod sound;
+mod theme;
+mod versus;
 pub fn start() {
     App::new()
-        .insert_resource(Score(0))
-        .insert_resource(HasNextPiece(false))
-        .insert_resource(AutoMovePieceDownTimer(Timer::new(
-            Duration::from_millis(500),
-            TimerMode::Repeating,
-        )))
-        .insert_resource(ManuallyMoveTimer(Timer::new(
-            Duration::from_millis(100),
-            TimerMode::Once,
-        )))
-        .insert_resource(RemovePieceComponentTimer(Timer::new(
-            Duration::from_millis(300),
-            TimerMode::Once,
-        )))
+        .init_resource::<SettingsReturnState>()
+        .insert_resource(NetConfig::from_env())
+        .init_resource::<NetStatus>()
+        .init_resource::<EditorState>()
         .add_systems(
             Startup,
             (
                 setup_camera,
                 setup_font_assets_resource,
-                setup_game_audios_resource,
-                setup_game_board,
-                setup_piece_queue,
+                setup_high_scores_resource,
+                setup_asset_error_text,
             ),
         )
         .add_plugins(DefaultPlugins.set(WindowPlugin {
@@ -51,29 +83,125 @@ pub fn start() {
             }),
             ..Default::default()
         }))
-        .init_state::<AppState>()
-        // 这个阶段在 Startup 阶段之后运行，也用于执行初始化任务 可以使用部分预加载的资源
-        .add_systems(PostStartup, (setup_post_states_boards,))
-        .add_systems(Update, (auto_generate_new_piece, move_piece,rotate_piece).run_if(in_state(AppState::InGame)))
+        .add_plugins((GameRulesPlugin, BotPlugin, PieceSetPlugin, RuleSetPlugin, ThemePlugin, EffectsPlugin, InterpolationPlugin, LayoutPlugin, SoundPlugin, PuzzlePlugin))
+        .add_sub_state::<MenuScreen>()
+        .enable_state_scoped_entities::<AppState>()
+        .enable_state_scoped_entities::<MenuScreen>()
+        .add_event::<NetworkAction>()
+        .add_event::<EditorAction>()
+        // 新游戏的计分板，以及网络对战中对手的面板
+        .add_systems(
+            OnEnter(AppState::InGame),
+            (
+                setup_post_states_boards,
+                setup_network_boards.run_if(resource_exists::<NetSession>),
+            )
+                .chain()
+                .after(setup_game_board)
+                .before(spawn_initial_garbage)
+                .in_set(NewGameSetup),
+        )
+        .add_systems(
+            Update,
+            send_local_input
+                .after(read_keyboard_input)
+                .before(move_piece)
+                .before(rotate_piece)
+                .run_if(in_state(AppState::InGame).and_then(resource_exists::<NetSession>)),
+        )
+        .add_systems(
+            PostUpdate,
+            (send_local_locks, receive_network_messages)
+                .chain()
+                .after(remove_piece_component)
+                .before(count_locked_pieces)
+                .run_if(in_state(AppState::InGame).and_then(resource_exists::<NetSession>)),
+        )
+        // 界面显示在规则之后更新
         .add_systems(
             PostUpdate,
             (
                 update_next_piece_board,
-                check_collision,
-                remove_piece_component,
                 update_scoreboard,
-                check_full_line,
-                check_game_overs
-            ).run_if(in_state(AppState::InGame)),
+                update_linesboard,
+                update_modeboard,
+                update_garbage_warning,
+                update_garbage_meter,
+                update_finesse_hint,
+                animate_line_clear,
+            )
+                .after(check_mode_goal)
+                .run_if(in_state(AppState::InGame)),
+        )
+        // 精灵绘制的面板跟随界面中的占位节点
+        .add_systems(
+            PostUpdate,
+            follow_ui_nodes
+                .after(UiSystem::Layout)
+                .before(TransformSystem::TransformPropagate),
         )
         .add_systems(
             OnEnter(AppState::GameOver),
-            show_game_over_menu,
+            (
+                send_top_out.run_if(resource_exists::<NetSession>),
+                record_high_score,
+                show_game_over_menu,
+                show_puzzle_result_menu,
+            )
+                .chain(),
+        )
+        .add_systems(
+            OnEnter(AppState::Victory),
+            (record_high_score, show_game_over_menu, show_puzzle_result_menu).chain(),
+        )
+        .add_systems(
+            OnEnter(AppState::TimeUp),
+            (record_high_score, show_game_over_menu).chain(),
+        )
+        .add_systems(OnEnter(AppState::MainMenu), (despawn_game_entities, stop_playtest))
+        .add_systems(OnEnter(MenuScreen::Main), (close_network_session, show_main_menu))
+        .add_systems(OnEnter(MenuScreen::ModeSelect), show_mode_select_menu)
+        .add_systems(OnEnter(MenuScreen::HighScores), show_high_scores_menu)
+        .add_systems(OnEnter(MenuScreen::Network), show_network_menu)
+        .add_systems(OnEnter(MenuScreen::PuzzleSelect), show_puzzle_select_menu)
+        .add_systems(OnEnter(AppState::InGame), show_game_board)
+        .add_systems(OnEnter(AppState::Paused), (hide_game_board, show_pause_menu))
+        .add_systems(OnEnter(AppState::Settings), show_settings_menu)
+        .add_systems(
+            OnEnter(AppState::Editor),
+            (despawn_game_entities, stop_playtest, show_editor),
         )
         .add_systems(
             Update,
-            click_button.run_if(
-                in_state(AppState::GameOver)
+            (paint_editor_board, update_editor_board)
+                .chain()
+                .run_if(in_state(AppState::Editor)),
+        )
+        .add_systems(
+            Update,
+            (
+                // 网络对战无法暂停
+                toggle_pause.run_if(
+                    in_state(AppState::InGame)
+                        .or_else(in_state(AppState::Paused))
+                        .and_then(not(resource_exists::<NetSession>)),
+                ),
+                pause_on_focus_lost.run_if(
+                    in_state(AppState::InGame).and_then(not(resource_exists::<NetSession>)),
+                ),
+                update_menu_labels,
+                update_asset_error_text,
+                click_button,
+                update_button_color,
+                handle_network_actions,
+                handle_editor_actions,
+                toggle_playtest,
+                edit_net_address.run_if(in_state(MenuScreen::Network)),
+                accept_connection.run_if(resource_exists::<NetListener>),
+                flush_network_session.run_if(resource_exists::<NetSession>),
+                wait_for_opponent.run_if(
+                    in_state(AppState::MainMenu).and_then(resource_exists::<NetSession>),
+                ),
             ),
         )
         .run();
@@ -1,27 +1,701 @@
-use bevy::color::palettes;
+use bevy::ecs::system::EntityCommands;
+use bevy::input::{keyboard::{Key, KeyboardInput}, ButtonState};
 use bevy::prelude::*;
+use bevy::window::WindowFocused;
 
-use crate::board::QuitButton;
-use crate::common::AppState;
+use crate::common::{AppState, FontTff, GameSettings, MenuScreen, RestartGame, VolumeChannel};
+use crate::editor::{EditorAction, EditorState};
+use crate::highscore::HighScores;
+use crate::mode::{GameConfig, GameMode, MAX_CHEESE_ROWS, MAX_START_LEVEL};
+use crate::network::{is_address_char, NetConfig, NetStatus, NetworkAction};
+use crate::piece::PieceType;
+use crate::piece_set::PieceSets;
+use crate::settings::SettingsReturnState;
+use crate::theme::Themes;
 
+// 按钮颜色
+const NORMAL_BUTTON_COLOR: Color = Color::srgb(0.15, 0.15, 0.15);
+const HOVERED_BUTTON_COLOR: Color = Color::srgb(0.25, 0.25, 0.25);
+const PRESSED_BUTTON_COLOR: Color = Color::srgb(0.35, 0.55, 0.35);
+
+// 菜单按钮
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MenuButton {
+    // 进入模式选择
+    Play,
+    // 查看最高分
+    HighScores,
+    // 机器人演示
+    Demo,
+    // 进入网络对战
+    Network,
+    // 进入谜题选择
+    Puzzles,
+    // 开始指定序号的谜题
+    StartPuzzle(usize),
+    // 进入棋盘编辑器，试玩结束后回到编辑器
+    Editor,
+    // 编辑器中的操作
+    EditorAction(EditorAction),
+    // 作为主机等待对手连接
+    HostGame,
+    // 连接主机
+    JoinGame,
+    // 选择游戏模式
+    SelectMode(GameMode),
+    // 降低起始等级
+    LevelDown,
+    // 提高起始等级
+    LevelUp,
+    // 减少挖掘模式的初始垃圾行
+    GarbageRowsDown,
+    // 增加挖掘模式的初始垃圾行
+    GarbageRowsUp,
+    // 降低垃圾行缺口换列的概率
+    MessinessDown,
+    // 提高垃圾行缺口换列的概率
+    MessinessUp,
+    // 选择上一套骨牌
+    PreviousPieceSet,
+    // 选择下一套骨牌
+    NextPieceSet,
+    // 切换对战模式的对手是否为机器人
+    ToggleVersusBot,
+    // 按所选模式开始新游戏
+    StartGame,
+    // 返回主菜单
+    MainMenu,
+    // 继续游戏
+    Resume,
+    // 重新开始
+    Restart,
+    // 打开设置
+    Settings,
+    // 切换失去焦点自动暂停
+    TogglePauseOnFocusLost,
+    // 切换落点提示
+    ToggleHints,
+    // 切换按键练习
+    ToggleFinessePractice,
+    // 切换粒子效果的强度
+    CycleEffects,
+    // 切换平滑移动
+    ToggleSmoothMovement,
+    // 切换全屏
+    ToggleFullscreen,
+    // 调整音量
+    VolumeDown(VolumeChannel),
+    VolumeUp(VolumeChannel),
+    // 选择上一个主题
+    PreviousTheme,
+    // 选择下一个主题
+    NextTheme,
+    // 从设置返回
+    Back,
+    // 退出游戏
+    Quit,
+}
+
+// 随设置或配置变化而更新的菜单文字
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MenuLabel {
+    PauseOnFocusLost,
+    Hints,
+    FinessePractice,
+    Theme,
+    Effects,
+    SmoothMovement,
+    Fullscreen,
+    Volume(VolumeChannel),
+    VersusBot,
+    Mode,
+    StartLevel,
+    GarbageRows,
+    Messiness,
+    PieceSet,
+    NetAddress,
+    NetStatus,
+    EditorBrush,
+    EditorQueue,
+    EditorHold,
+    EditorGoal,
+    EditorStatus,
+}
 
 pub fn click_button(
     mut interaction_query: Query<
-        (&Interaction, &QuitButton),
+        (&Interaction, &MenuButton),
         (Changed<Interaction>, With<Button>),
     >,
+    app_state: Res<State<AppState>>,
+    mut next_state: ResMut<NextState<AppState>>,
+    mut next_menu_screen: ResMut<NextState<MenuScreen>>,
+    mut restart_events: EventWriter<RestartGame>,
+    mut settings: ResMut<GameSettings>,
+    mut settings_return: ResMut<SettingsReturnState>,
+    mut config: ResMut<GameConfig>,
+    piece_sets: Res<PieceSets>,
+    themes: Res<Themes>,
+    mut network_actions: EventWriter<NetworkAction>,
+    mut editor_actions: EventWriter<EditorAction>,
     mut exit: EventWriter<AppExit>,
 ) {
-    for (interaction,quit_button) in &mut interaction_query {
-        println!("quit_button:{:?}",quit_button);
-        match *interaction {
-            Interaction::Pressed => match quit_button {
-                QuitButton  => {
-                    info!("Quit button clicked");
-                    exit.send_default();
+    for (interaction, menu_button) in &mut interaction_query {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        info!("{:?} button clicked", menu_button);
+        match menu_button {
+            MenuButton::Play => next_menu_screen.set(MenuScreen::ModeSelect),
+            MenuButton::HighScores => next_menu_screen.set(MenuScreen::HighScores),
+            MenuButton::Demo => {
+                config.demo = true;
+                config.mode = GameMode::Endless;
+                restart_events.send(RestartGame);
+            }
+            MenuButton::Network => next_menu_screen.set(MenuScreen::Network),
+            MenuButton::Puzzles => {
+                if *app_state.get() != AppState::MainMenu {
+                    next_state.set(AppState::MainMenu);
                 }
+                next_menu_screen.set(MenuScreen::PuzzleSelect);
+            }
+            MenuButton::StartPuzzle(index) => {
+                config.demo = false;
+                config.mode = GameMode::Puzzle;
+                config.puzzle = *index;
+                restart_events.send(RestartGame);
+            }
+            MenuButton::Editor => next_state.set(AppState::Editor),
+            MenuButton::EditorAction(action) => {
+                editor_actions.send(*action);
+            }
+            MenuButton::HostGame => {
+                network_actions.send(NetworkAction::Host);
+            }
+            MenuButton::JoinGame => {
+                network_actions.send(NetworkAction::Join);
+            }
+            MenuButton::SelectMode(mode) => config.mode = *mode,
+            MenuButton::LevelDown => config.start_level = (config.start_level - 1).max(1),
+            MenuButton::LevelUp => {
+                config.start_level = (config.start_level + 1).min(MAX_START_LEVEL)
+            }
+            MenuButton::GarbageRowsDown => {
+                config.cheese_rows = (config.cheese_rows - 1).max(1)
+            }
+            MenuButton::GarbageRowsUp => {
+                config.cheese_rows = (config.cheese_rows + 1).min(MAX_CHEESE_ROWS)
+            }
+            MenuButton::MessinessDown => {
+                config.garbage_messiness = (config.garbage_messiness - 0.1).max(0.0)
+            }
+            MenuButton::MessinessUp => {
+                config.garbage_messiness = (config.garbage_messiness + 0.1).min(1.0)
+            }
+            MenuButton::PreviousPieceSet => {
+                config.piece_set = piece_sets.cycle(&config.piece_set, -1).name.clone()
+            }
+            MenuButton::NextPieceSet => {
+                config.piece_set = piece_sets.cycle(&config.piece_set, 1).name.clone()
+            }
+            MenuButton::ToggleVersusBot => config.versus_bot = !config.versus_bot,
+            MenuButton::StartGame => {
+                config.demo = false;
+                restart_events.send(RestartGame);
+            }
+            MenuButton::MainMenu => match app_state.get() {
+                AppState::MainMenu => next_menu_screen.set(MenuScreen::Main),
+                _ => next_state.set(AppState::MainMenu),
+            },
+            MenuButton::Resume => next_state.set(AppState::InGame),
+            MenuButton::Restart => {
+                restart_events.send(RestartGame);
+            }
+            MenuButton::Settings => {
+                settings_return.0 = app_state.get().clone();
+                next_state.set(AppState::Settings);
+            }
+            MenuButton::TogglePauseOnFocusLost => {
+                settings.pause_on_focus_lost = !settings.pause_on_focus_lost;
+            }
+            MenuButton::ToggleHints => settings.show_hints = !settings.show_hints,
+            MenuButton::ToggleFinessePractice => {
+                settings.finesse_practice = !settings.finesse_practice;
+            }
+            MenuButton::CycleEffects => settings.effects = settings.effects.next(),
+            MenuButton::ToggleSmoothMovement => {
+                settings.smooth_movement = !settings.smooth_movement;
+            }
+            MenuButton::ToggleFullscreen => settings.fullscreen = !settings.fullscreen,
+            MenuButton::VolumeDown(channel) => {
+                let volume = settings.volume_mut(*channel);
+                *volume = (*volume - 0.1).max(0.0);
+            }
+            MenuButton::VolumeUp(channel) => {
+                let volume = settings.volume_mut(*channel);
+                *volume = (*volume + 0.1).min(1.0);
+            }
+            MenuButton::PreviousTheme => {
+                settings.theme = themes.cycle(&settings.theme, -1).name.clone()
+            }
+            MenuButton::NextTheme => settings.theme = themes.cycle(&settings.theme, 1).name.clone(),
+            MenuButton::Back => next_state.set(settings_return.0.clone()),
+            MenuButton::Quit => {
+                exit.send_default();
+            }
+        }
+    }
+}
+
+// 鼠标悬停和按下时改变按钮颜色
+pub fn update_button_color(
+    mut interaction_query: Query<(&Interaction, &mut UiImage), (Changed<Interaction>, With<MenuButton>)>,
+) {
+    for (interaction, mut image) in &mut interaction_query {
+        image.color = match *interaction {
+            Interaction::Pressed => PRESSED_BUTTON_COLOR,
+            Interaction::Hovered => HOVERED_BUTTON_COLOR,
+            Interaction::None => NORMAL_BUTTON_COLOR,
+        };
+    }
+}
+
+fn on_off(value: bool) -> &'static str {
+    if value {
+        "ON"
+    } else {
+        "OFF"
+    }
+}
+
+// 根据设置、配置和编辑器状态更新菜单文字
+pub fn update_menu_labels(
+    settings: Res<GameSettings>,
+    config: Res<GameConfig>,
+    net_config: Res<NetConfig>,
+    net_status: Res<NetStatus>,
+    editor: Res<EditorState>,
+    mut query: Query<(&mut Text, &MenuLabel)>,
+) {
+    for (mut text, label) in &mut query {
+        let value = match label {
+            MenuLabel::PauseOnFocusLost => format!(
+                "Pause on focus loss: {}",
+                on_off(settings.pause_on_focus_lost)
+            ),
+            MenuLabel::Hints => format!("Placement hints: {}", on_off(settings.show_hints)),
+            MenuLabel::FinessePractice => {
+                format!("Finesse practice: {}", on_off(settings.finesse_practice))
+            }
+            MenuLabel::Theme => format!("Theme: {}", settings.theme),
+            MenuLabel::Effects => format!("Effects: {}", settings.effects.name()),
+            MenuLabel::SmoothMovement => {
+                format!("Smooth movement: {}", on_off(settings.smooth_movement))
+            }
+            MenuLabel::Fullscreen => format!("Fullscreen (F11): {}", on_off(settings.fullscreen)),
+            MenuLabel::Volume(channel) => format!(
+                "{}: {}%",
+                channel.name(),
+                (settings.volume(*channel) * 100.0).round()
+            ),
+            MenuLabel::VersusBot => format!("Versus bot: {}", on_off(config.versus_bot)),
+            MenuLabel::Mode => format!("Mode: {}", config.mode.name()),
+            MenuLabel::StartLevel => format!("Start level: {}", config.start_level),
+            MenuLabel::GarbageRows => format!("Cheese rows: {}", config.cheese_rows),
+            MenuLabel::Messiness => format!(
+                "Messiness: {}%",
+                (config.garbage_messiness * 100.0).round()
+            ),
+            MenuLabel::PieceSet => format!("Pieces: {}", config.piece_set),
+            MenuLabel::NetAddress => format!("Address: {}_", net_config.address),
+            MenuLabel::NetStatus => net_status.0.clone(),
+            MenuLabel::EditorBrush => match editor.brush {
+                Some(piece_type) => format!("Brush: {}", piece_type.letter()),
+                None => "Brush: Garbage".to_string(),
             },
+            MenuLabel::EditorQueue => {
+                let pieces: String = editor.puzzle.pieces.iter().map(PieceType::letter).collect();
+                format!("Queue: {}", if pieces.is_empty() { "-" } else { &pieces })
+            }
+            MenuLabel::EditorHold => format!(
+                "Hold: {}",
+                editor.puzzle.hold.map_or('-', |piece_type| piece_type.letter())
+            ),
+            MenuLabel::EditorGoal => format!("Goal: {}", editor.puzzle.goal.description()),
+            MenuLabel::EditorStatus => editor.status.clone(),
+        };
+        // 只在文字变化时修改，避免每帧重新排版
+        if text.sections[0].value != value {
+            text.sections[0].value = value;
+        }
+    }
+}
+
+// 生成菜单按钮
+pub fn spawn_menu_button(parent: &mut ChildBuilder, text: &str, menu_button: MenuButton) {
+    parent
+        .spawn((
+            ButtonBundle {
+                style: Style {
+                    width: Val::Px(200.0),
+                    height: Val::Px(50.0),
+                    margin: UiRect::all(Val::Px(10.0)),
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                image: UiImage::default().with_color(NORMAL_BUTTON_COLOR),
+                ..default()
+            },
+            menu_button,
+        ))
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section(
+                text,
+                TextStyle {
+                    font_size: 40.0,
+                    color: Color::srgb(0.9, 0.9, 0.9),
+                    ..default()
+                },
+            ));
+        });
+}
+
+// 生成用于调整数值的小按钮
+pub fn spawn_small_menu_button(parent: &mut ChildBuilder, text: &str, menu_button: MenuButton) {
+    parent
+        .spawn((
+            ButtonBundle {
+                style: Style {
+                    width: Val::Px(50.0),
+                    height: Val::Px(40.0),
+                    margin: UiRect::horizontal(Val::Px(5.0)),
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                image: UiImage::default().with_color(NORMAL_BUTTON_COLOR),
+                ..default()
+            },
+            menu_button,
+        ))
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section(
+                text,
+                TextStyle {
+                    font_size: 30.0,
+                    color: Color::srgb(0.9, 0.9, 0.9),
+                    ..default()
+                },
+            ));
+        });
+}
+
+// 生成一行可调整的选项：文字 [-] [+]
+pub fn spawn_option_row(
+    parent: &mut ChildBuilder,
+    label: MenuLabel,
+    down: MenuButton,
+    up: MenuButton,
+) {
+    parent
+        .spawn(NodeBundle {
+            style: Style {
+                flex_direction: FlexDirection::Row,
+                align_items: AlignItems::Center,
+                margin: UiRect::vertical(Val::Px(5.0)),
+                ..default()
+            },
+            ..default()
+        })
+        .with_children(|parent| {
+            spawn_menu_text(parent, "").insert((
+                label,
+                Style {
+                    width: Val::Px(260.0),
+                    ..default()
+                },
+            ));
+            spawn_small_menu_button(parent, "-", down);
+            spawn_small_menu_button(parent, "+", up);
+        });
+}
+
+// 生成一行开关选项：文字 [±]
+pub fn spawn_toggle_row(parent: &mut ChildBuilder, label: MenuLabel, toggle: MenuButton) {
+    parent
+        .spawn(NodeBundle {
+            style: Style {
+                align_items: AlignItems::Center,
+                margin: UiRect::vertical(Val::Px(5.0)),
+                ..default()
+            },
+            ..default()
+        })
+        .with_children(|parent| {
+            spawn_menu_text(parent, "").insert((
+                label,
+                Style {
+                    width: Val::Px(260.0),
+                    ..default()
+                },
+            ));
+            spawn_small_menu_button(parent, "±", toggle);
+        });
+}
+
+// 生成菜单标题
+pub fn spawn_menu_title(parent: &mut ChildBuilder, text: &str) {
+    parent.spawn(TextBundle::from_section(
+        text,
+        TextStyle {
+            font_size: 50.0,
+            color: Color::srgb(0.5, 0.5, 1.0),
+            ..default()
+        },
+    ));
+}
+
+// 生成一行普通文字
+pub fn spawn_menu_text<'a>(parent: &'a mut ChildBuilder, text: &str) -> EntityCommands<'a> {
+    parent.spawn(TextBundle::from_section(
+        text,
+        TextStyle {
+            font_size: 24.0,
+            color: Color::srgb(0.9, 0.9, 0.9),
+            ..default()
+        },
+    ))
+}
+
+// 生成居中的菜单面板，面板内容由 spawn_children 决定，离开 state 时自动销毁
+pub fn spawn_menu_panel<S: States>(
+    commands: &mut Commands,
+    state: S,
+    spawn_children: impl FnOnce(&mut ChildBuilder),
+) {
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    width: Val::Percent(100.),
+                    height: Val::Percent(100.),
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    position_type: PositionType::Absolute,
+                    ..default()
+                },
+                ..default()
+            },
+            StateScoped(state),
+        ))
+        .with_children(|parent| {
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        min_width: Val::Px(400.),
+                        padding: UiRect::all(Val::Px(20.)),
+                        justify_content: JustifyContent::Center,
+                        align_items: AlignItems::Center,
+                        flex_direction: FlexDirection::Column,
+                        ..default()
+                    },
+                    background_color: BackgroundColor::from(Color::srgb(0.1, 0.1, 0.1)),
+                    ..default()
+                })
+                .with_children(spawn_children);
+        });
+}
+
+pub fn show_main_menu(mut commands: Commands, font_tff: Res<FontTff>) {
+    spawn_menu_panel(&mut commands, MenuScreen::Main, |parent| {
+        parent.spawn(TextBundle::from_section(
+            "俄罗斯方块",
+            TextStyle {
+                font_size: 50.0,
+                color: Color::srgb(0.5, 0.5, 1.0),
+                font: font_tff.sim_hei.clone(),
+            },
+        ));
+        spawn_menu_button(parent, "Play", MenuButton::Play);
+        spawn_menu_button(parent, "Puzzles", MenuButton::Puzzles);
+        spawn_menu_button(parent, "Editor", MenuButton::Editor);
+        spawn_menu_button(parent, "Demo", MenuButton::Demo);
+        spawn_menu_button(parent, "Network", MenuButton::Network);
+        spawn_menu_button(parent, "High Scores", MenuButton::HighScores);
+        spawn_menu_button(parent, "Settings", MenuButton::Settings);
+        spawn_menu_button(parent, "Quit", MenuButton::Quit);
+    });
+}
+
+pub fn show_mode_select_menu(mut commands: Commands) {
+    spawn_menu_panel(&mut commands, MenuScreen::ModeSelect, |parent| {
+        spawn_menu_title(parent, "PLAY");
+        spawn_menu_text(parent, "").insert(MenuLabel::Mode);
+        // 模式按钮自动换行排列
+        parent
+            .spawn(NodeBundle {
+                style: Style {
+                    max_width: Val::Px(700.),
+                    flex_wrap: FlexWrap::Wrap,
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                },
+                ..default()
+            })
+            .with_children(|parent| {
+                for mode in GameMode::ALL {
+                    spawn_menu_button(parent, mode.name(), MenuButton::SelectMode(mode));
+                }
+            });
+        spawn_option_row(
+            parent,
+            MenuLabel::StartLevel,
+            MenuButton::LevelDown,
+            MenuButton::LevelUp,
+        );
+        spawn_option_row(
+            parent,
+            MenuLabel::GarbageRows,
+            MenuButton::GarbageRowsDown,
+            MenuButton::GarbageRowsUp,
+        );
+        spawn_option_row(
+            parent,
+            MenuLabel::Messiness,
+            MenuButton::MessinessDown,
+            MenuButton::MessinessUp,
+        );
+        spawn_option_row(
+            parent,
+            MenuLabel::PieceSet,
+            MenuButton::PreviousPieceSet,
+            MenuButton::NextPieceSet,
+        );
+        spawn_toggle_row(parent, MenuLabel::VersusBot, MenuButton::ToggleVersusBot);
+        spawn_menu_button(parent, "Start", MenuButton::StartGame);
+        spawn_menu_button(parent, "Back", MenuButton::MainMenu);
+    });
+}
+
+// 网络对战：一方作为主机等待，另一方加入，连接后由主机决定种子和起始等级
+pub fn show_network_menu(mut commands: Commands) {
+    spawn_menu_panel(&mut commands, MenuScreen::Network, |parent| {
+        spawn_menu_title(parent, "NETWORK VERSUS");
+        spawn_menu_text(parent, "").insert(MenuLabel::NetAddress);
+        spawn_menu_text(parent, "Type to edit, Backspace to delete");
+        spawn_menu_button(parent, "Host", MenuButton::HostGame);
+        spawn_menu_button(parent, "Join", MenuButton::JoinGame);
+        spawn_menu_text(parent, "").insert(MenuLabel::NetStatus);
+        spawn_menu_button(parent, "Back", MenuButton::MainMenu);
+    });
+}
+
+// 地址的最大长度
+const MAX_NET_ADDRESS_LENGTH: usize = 64;
+
+// 网络菜单中直接输入地址：主机只使用其中的端口，加入方连接该地址
+pub fn edit_net_address(
+    mut keyboard_events: EventReader<KeyboardInput>,
+    mut net_config: ResMut<NetConfig>,
+) {
+    for event in keyboard_events.read() {
+        if event.state != ButtonState::Pressed {
+            continue;
+        }
+        match &event.logical_key {
+            Key::Backspace => {
+                net_config.address.pop();
+            }
+            Key::Character(characters) => {
+                for character in characters.chars().filter(|character| is_address_char(*character)) {
+                    if net_config.address.len() < MAX_NET_ADDRESS_LENGTH {
+                        net_config.address.push(character);
+                    }
+                }
+            }
             _ => {}
         }
     }
 }
+
+pub fn show_high_scores_menu(mut commands: Commands, high_scores: Res<HighScores>) {
+    spawn_menu_panel(&mut commands, MenuScreen::HighScores, |parent| {
+        spawn_menu_title(parent, "HIGH SCORES");
+        // 每个模式一列
+        parent
+            .spawn(NodeBundle {
+                style: Style {
+                    flex_direction: FlexDirection::Row,
+                    column_gap: Val::Px(40.),
+                    ..default()
+                },
+                ..default()
+            })
+            .with_children(|parent| {
+                for mode in GameMode::ALL.into_iter().filter(GameMode::has_high_scores) {
+                    parent
+                        .spawn(NodeBundle {
+                            style: Style {
+                                flex_direction: FlexDirection::Column,
+                                align_items: AlignItems::Center,
+                                ..default()
+                            },
+                            ..default()
+                        })
+                        .with_children(|parent| {
+                            spawn_menu_text(parent, mode.name());
+                            let entries = high_scores.for_mode(mode);
+                            if entries.is_empty() {
+                                spawn_menu_text(parent, "-");
+                            }
+                            for (rank, entry) in entries.iter().enumerate() {
+                                spawn_menu_text(
+                                    parent,
+                                    &format!("{:>2}. {}", rank + 1, entry.display()),
+                                );
+                            }
+                        });
+                }
+            });
+        spawn_menu_button(parent, "Back", MenuButton::MainMenu);
+    });
+}
+
+pub fn show_pause_menu(mut commands: Commands) {
+    spawn_menu_panel(&mut commands, AppState::Paused, |parent| {
+        spawn_menu_title(parent, "PAUSED");
+        spawn_menu_button(parent, "Resume", MenuButton::Resume);
+        spawn_menu_button(parent, "Restart", MenuButton::Restart);
+        spawn_menu_button(parent, "Settings", MenuButton::Settings);
+        spawn_menu_button(parent, "Quit", MenuButton::Quit);
+    });
+}
+
+// 按 Escape 或 P 暂停/继续游戏
+pub fn toggle_pause(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    app_state: Res<State<AppState>>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    if !keyboard_input.any_just_pressed([KeyCode::Escape, KeyCode::KeyP]) {
+        return;
+    }
+    match app_state.get() {
+        AppState::InGame => next_state.set(AppState::Paused),
+        AppState::Paused => next_state.set(AppState::InGame),
+        _ => {}
+    }
+}
+
+// 窗口失去焦点时自动暂停
+pub fn pause_on_focus_lost(
+    mut focused_events: EventReader<WindowFocused>,
+    settings: Res<GameSettings>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    if focused_events.read().any(|event| !event.focused) && settings.pause_on_focus_lost {
+        next_state.set(AppState::Paused);
+    }
+}
@@ -0,0 +1,242 @@
+use std::time::Duration;
+
+use bevy::prelude::*;
+
+use crate::{
+    board::Block,
+    common::AppState,
+    board::MainBoard,
+    garbage::{remaining_garbage_rows, GarbageBlock},
+    piece::AutoMovePieceDownTimer,
+    rule_set::RuleSet,
+    state::{Level, Lines, SessionStats},
+};
+
+// 最高可选的起始等级
+pub const MAX_START_LEVEL: u32 = 15;
+
+// 马拉松模式完成所需的行数，或升级到达的等级（到达该等级时结束，从该等级开始时只按行数结束）
+pub const MARATHON_LINES: u32 = 150;
+pub const MARATHON_MAX_LEVEL: u32 = 15;
+
+// 马拉松模式每消除多少行升一级
+pub const LINES_PER_LEVEL: u32 = 10;
+
+// 竞速模式需要消除的行数
+pub const SPRINT_LINES: u32 = 40;
+
+// 挖掘模式可选的初始垃圾行数
+pub const MAX_CHEESE_ROWS: u32 = 18;
+
+// 生存模式垃圾行上升的初始间隔、每次上升后缩短的时间和最短间隔
+pub const SURVIVAL_START_INTERVAL: Duration = Duration::from_secs(10);
+pub const SURVIVAL_INTERVAL_STEP: Duration = Duration::from_millis(500);
+pub const SURVIVAL_MIN_INTERVAL: Duration = Duration::from_secs(2);
+
+// 限时模式的时长
+pub const ULTRA_TIME: Duration = Duration::from_secs(120);
+
+// 游戏模式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum GameMode {
+    // 马拉松：每 10 行升一级，消除 150 行或完成第 15 级时胜利
+    #[default]
+    Marathon,
+    // 无尽马拉松：规则与马拉松相同，但没有终点
+    Endless,
+    // 竞速：以最短时间消除 40 行
+    Sprint,
+    // 限时：两分钟内获得尽可能高的分数
+    Ultra,
+    // 挖掘：以最短时间消除所有初始垃圾行
+    Cheese,
+    // 生存：垃圾行不断从底部上升，坚持得越久越好
+    Survival,
+    // 本地双人对战：消行向对手发送垃圾行，先溢出的一方失败
+    Versus,
+    // 谜题：从文件加载初始面板和骨牌序列，完成目标即胜利
+    Puzzle,
+}
+
+// 排行榜的排名方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ranking {
+    HighestScore,
+    ShortestTime,
+    LongestTime,
+}
+
+impl GameMode {
+    // 主菜单中可选择的所有模式
+    pub const ALL: [GameMode; 7] = [
+        GameMode::Marathon,
+        GameMode::Endless,
+        GameMode::Sprint,
+        GameMode::Ultra,
+        GameMode::Cheese,
+        GameMode::Survival,
+        GameMode::Versus,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            GameMode::Marathon => "Marathon",
+            GameMode::Endless => "Endless",
+            GameMode::Sprint => "Sprint 40L",
+            GameMode::Ultra => "Ultra 2min",
+            GameMode::Cheese => "Cheese",
+            GameMode::Survival => "Survival",
+            GameMode::Versus => "Versus",
+            GameMode::Puzzle => "Puzzle",
+        }
+    }
+
+    // 保存最高分时使用的标识
+    pub fn key(&self) -> &'static str {
+        match self {
+            GameMode::Marathon => "marathon",
+            GameMode::Endless => "endless",
+            GameMode::Sprint => "sprint",
+            GameMode::Ultra => "ultra",
+            GameMode::Cheese => "cheese",
+            GameMode::Survival => "survival",
+            GameMode::Versus => "versus",
+            GameMode::Puzzle => "puzzle",
+        }
+    }
+
+    pub fn ranking(&self) -> Ranking {
+        match self {
+            GameMode::Sprint | GameMode::Cheese => Ranking::ShortestTime,
+            GameMode::Survival => Ranking::LongestTime,
+            _ => Ranking::HighestScore,
+        }
+    }
+
+    // 游戏失败（方块溢出）时是否记录成绩，竞速、限时和挖掘模式只有完成目标或时间结束才计入排行榜
+    pub fn records_on_top_out(&self) -> bool {
+        !matches!(self, GameMode::Sprint | GameMode::Ultra | GameMode::Cheese)
+    }
+
+    // 同时游戏的玩家数
+    pub fn player_count(&self) -> usize {
+        match self {
+            GameMode::Versus => 2,
+            _ => 1,
+        }
+    }
+
+    // 是否有排行榜，对战模式只决出胜负，谜题只有成功或失败，不记录成绩
+    pub fn has_high_scores(&self) -> bool {
+        self.player_count() == 1 && *self != GameMode::Puzzle
+    }
+
+    // 是否使用完整计分规则（等级倍率、T-spin、Back-to-Back、连击、全消），否则只按行数计分
+    pub fn uses_full_scoring(&self) -> bool {
+        matches!(self, GameMode::Ultra | GameMode::Versus | GameMode::Puzzle)
+    }
+
+    // 随消除行数升级，等级决定下落速度
+    pub fn levels_up(&self) -> bool {
+        matches!(self, GameMode::Marathon | GameMode::Endless)
+    }
+
+    pub fn from_key(key: &str) -> Option<Self> {
+        GameMode::ALL.into_iter().find(|mode| mode.key() == key)
+    }
+}
+
+// 开始新游戏时使用的配置，在主菜单中选择
+#[derive(Debug, Resource)]
+pub struct GameConfig {
+    pub mode: GameMode,
+    pub start_level: u32,
+    // 挖掘模式的初始垃圾行数
+    pub cheese_rows: u32,
+    // 垃圾行缺口换列的概率
+    pub garbage_messiness: f32,
+    // 固定的随机种子，为 None 时每局随机生成
+    pub seed: Option<u64>,
+    // 演示模式，由机器人操作
+    pub demo: bool,
+    // 对战模式中 Player 2 由机器人操作
+    pub versus_bot: bool,
+    // 谜题模式选择的谜题序号
+    pub puzzle: usize,
+    // 使用的骨牌名称，见 PieceSets
+    pub piece_set: String,
+}
+
+impl Default for GameConfig {
+    fn default() -> Self {
+        Self {
+            mode: GameMode::default(),
+            start_level: 1,
+            cheese_rows: 10,
+            garbage_messiness: 0.5,
+            seed: None,
+            demo: false,
+            versus_bot: false,
+            puzzle: 0,
+            piece_set: "Standard".to_string(),
+        }
+    }
+}
+
+// 检查当前模式的目标是否完成或时间是否用完
+pub fn check_mode_goal(
+    config: Res<GameConfig>,
+    main_board: Query<(Entity, &Lines, &Level, &SessionStats), With<MainBoard>>,
+    garbage_blocks: Query<(&Parent, &Block), With<GarbageBlock>>,
+    mut app_state: ResMut<NextState<AppState>>,
+) {
+    for (main_board_entity, lines, level, stats) in &main_board {
+        match config.mode {
+            GameMode::Marathon => {
+                let reached_max_level =
+                    level.0 >= MARATHON_MAX_LEVEL && level.0 > config.start_level;
+                if lines.0 >= MARATHON_LINES || reached_max_level {
+                    app_state.set(AppState::Victory);
+                }
+            }
+            GameMode::Endless => {}
+            GameMode::Sprint => {
+                if lines.0 >= SPRINT_LINES {
+                    app_state.set(AppState::Victory);
+                }
+            }
+            GameMode::Ultra => {
+                if stats.play_time >= ULTRA_TIME {
+                    app_state.set(AppState::TimeUp);
+                }
+            }
+            GameMode::Cheese => {
+                if remaining_garbage_rows(&garbage_blocks, main_board_entity) == 0 {
+                    app_state.set(AppState::Victory);
+                }
+            }
+            // 生存模式和对战模式只会因方块溢出而结束，由 check_game_overs 检测
+            GameMode::Survival | GameMode::Versus => {}
+            // 谜题的目标由 check_puzzle_goal 检测
+            GameMode::Puzzle => {}
+        }
+    }
+}
+
+// 按消除行数更新等级，并根据等级表调整自动下落速度
+pub fn update_level(
+    config: Res<GameConfig>,
+    rules: Res<RuleSet>,
+    mut main_board: Query<(&Lines, &mut Level, &mut AutoMovePieceDownTimer), With<MainBoard>>,
+) {
+    if !config.mode.levels_up() {
+        return;
+    }
+    for (lines, mut level, mut auto_move_timer) in &mut main_board {
+        let new_level = config.start_level + lines.0 / LINES_PER_LEVEL;
+        if new_level != level.0 {
+            level.0 = new_level;
+            auto_move_timer.0.set_duration(rules.gravity_interval(new_level));
+        }
+    }
+}
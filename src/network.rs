@@ -0,0 +1,879 @@
+use std::io::{ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use bevy::prelude::*;
+
+use crate::{
+    board::{Block, HasNextPiece, MainBoard, ToppedOut},
+    common::{AppState, RestartGame},
+    mode::{GameConfig, GameMode},
+    piece::{PieceLocked, PieceQueue, PieceRandomizer, PieceType},
+    piece_set::PieceSet,
+    puzzle::cell_color,
+    player::{InputMap, Player, PlayerInput},
+    state::{spawn_board_block, Score},
+    theme::BlockSkin,
+    versus::IncomingGarbage,
+};
+
+// 网络对战默认地址，可以用命令行参数 --address 或环境变量 TETRIS_NET_ADDRESS 修改
+pub const DEFAULT_NET_ADDRESS: &str = "127.0.0.1:7878";
+
+// 地址中没有端口时使用的端口
+pub const DEFAULT_NET_PORT: u16 = 7878;
+
+const NET_ADDRESS_ENV: &str = "TETRIS_NET_ADDRESS";
+
+// 加入时等待连接的最长时间
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(3);
+
+// 待发送的数据超过该长度时认为对手已经断开
+const MAX_OUTGOING_BYTES: usize = 64 * 1024;
+
+// 网络对战设置，主机监听 address 中的端口，加入方连接 address
+#[derive(Debug, Resource)]
+pub struct NetConfig {
+    pub address: String,
+}
+
+impl Default for NetConfig {
+    fn default() -> Self {
+        Self {
+            address: DEFAULT_NET_ADDRESS.to_string(),
+        }
+    }
+}
+
+impl NetConfig {
+    // 命令行参数 --address 优先于环境变量
+    pub fn from_env() -> Self {
+        let mut args = std::env::args().skip_while(|arg| arg != "--address").skip(1);
+        let address = args
+            .next()
+            .or_else(|| std::env::var(NET_ADDRESS_ENV).ok())
+            .filter(|address| !address.trim().is_empty());
+        match address {
+            Some(address) => Self {
+                address: address.trim().to_string(),
+            },
+            None => Self::default(),
+        }
+    }
+
+    // 主机监听的端口，地址中没有端口时使用默认端口
+    pub fn port(&self) -> Result<u16, String> {
+        match self.address.rsplit_once(':') {
+            Some((_, port)) => port
+                .parse()
+                .map_err(|_| format!("invalid port: {}", port)),
+            None => Ok(DEFAULT_NET_PORT),
+        }
+    }
+
+    // 加入方连接的地址，没有端口时加上默认端口
+    pub fn join_address(&self) -> String {
+        if self.address.contains(':') {
+            self.address.clone()
+        } else {
+            format!("{}:{}", self.address, DEFAULT_NET_PORT)
+        }
+    }
+}
+
+// 地址输入框中允许的字符
+pub fn is_address_char(character: char) -> bool {
+    character.is_ascii_alphanumeric() || matches!(character, '.' | ':' | '-' | '[' | ']')
+}
+
+// 网络菜单中显示的连接状态
+#[derive(Debug, Default, Resource)]
+pub struct NetStatus(pub String);
+
+// 菜单中的主机/加入操作
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NetworkAction {
+    Host,
+    Join,
+}
+
+// 主机等待对手连接
+#[derive(Resource)]
+pub struct NetListener(pub TcpListener);
+
+// 与对手的连接
+#[derive(Resource)]
+pub struct NetSession {
+    pub stream: TcpStream,
+    // 本机控制的玩家，主机为 Player 0，加入方为 Player 1
+    pub local_player: Player,
+    // 对手已断开连接
+    pub disconnected: bool,
+    // 握手已完成，游戏已经开始
+    pub started: bool,
+    // 收到的不完整的一行
+    buffer: Vec<u8>,
+    // 握手时与握手消息一起收到的后续消息，开始游戏后处理
+    pending: Vec<NetMessage>,
+    // 还没有写入连接的数据，每帧继续发送
+    outgoing: Vec<u8>,
+}
+
+// 网络对战中对手的面板，由收到的消息驱动
+#[derive(Component, Debug)]
+pub struct RemotePlayer;
+
+// 网络消息，每条消息一行文本
+#[derive(Debug, Clone, PartialEq)]
+pub enum NetMessage {
+    // 主机告知本局的种子和配置
+    Hello {
+        seed: u64,
+        start_level: u32,
+        garbage_messiness: f32,
+    },
+    // 加入方收到配置后回复，主机收到后开始游戏
+    Ready,
+    // 按键状态变化
+    Input { left: bool, right: bool, down: bool },
+    Rotate,
+    HardDrop,
+    // 骨牌固定，附带固定后（消行前）的面板状态和哈希，用于校验和纠正对手面板
+    Lock {
+        piece_type: PieceType,
+        t_spin: bool,
+        hard_drop: bool,
+        incoming: u32,
+        score: u32,
+        hash: u64,
+        // 每个方块的骨牌种类，None 为垃圾方块
+        blocks: Vec<(Block, Option<PieceType>)>,
+    },
+    // 抵消后发送给对手的垃圾行
+    Garbage(u32),
+    // 方块溢出
+    TopOut,
+}
+
+fn flag(value: bool) -> &'static str {
+    if value {
+        "1"
+    } else {
+        "0"
+    }
+}
+
+impl NetMessage {
+    pub fn encode(&self) -> String {
+        match self {
+            NetMessage::Hello {
+                seed,
+                start_level,
+                garbage_messiness,
+            } => format!("hello {} {} {}", seed, start_level, garbage_messiness),
+            NetMessage::Ready => "ready".to_string(),
+            NetMessage::Input { left, right, down } => {
+                format!("input {} {} {}", flag(*left), flag(*right), flag(*down))
+            }
+            NetMessage::Rotate => "rotate".to_string(),
+            NetMessage::HardDrop => "harddrop".to_string(),
+            NetMessage::Lock {
+                piece_type,
+                t_spin,
+                hard_drop,
+                incoming,
+                score,
+                hash,
+                blocks,
+            } => {
+                let blocks = if blocks.is_empty() {
+                    "-".to_string()
+                } else {
+                    blocks
+                        .iter()
+                        .map(|(block, cell)| {
+                            let letter = cell.map_or('#', |piece_type| piece_type.letter());
+                            format!("{},{},{}", block.x, block.y, letter)
+                        })
+                        .collect::<Vec<_>>()
+                        .join(";")
+                };
+                format!(
+                    "lock {} {} {} {} {} {} {}",
+                    piece_type.letter(),
+                    flag(*t_spin),
+                    flag(*hard_drop),
+                    incoming,
+                    score,
+                    hash,
+                    blocks
+                )
+            }
+            NetMessage::Garbage(lines) => format!("garbage {}", lines),
+            NetMessage::TopOut => "topout".to_string(),
+        }
+    }
+
+    pub fn parse(line: &str) -> Option<Self> {
+        let mut parts = line.split_whitespace();
+        let message = match parts.next()? {
+            "hello" => NetMessage::Hello {
+                seed: parts.next()?.parse().ok()?,
+                start_level: parts.next()?.parse().ok()?,
+                garbage_messiness: parts.next()?.parse().ok()?,
+            },
+            "ready" => NetMessage::Ready,
+            "input" => NetMessage::Input {
+                left: parts.next()? == "1",
+                right: parts.next()? == "1",
+                down: parts.next()? == "1",
+            },
+            "rotate" => NetMessage::Rotate,
+            "harddrop" => NetMessage::HardDrop,
+            "lock" => {
+                let mut letter = parts.next()?.chars();
+                let piece_type = PieceType::from_letter(letter.next()?)?;
+                if letter.next().is_some() {
+                    return None;
+                }
+                let t_spin = parts.next()? == "1";
+                let hard_drop = parts.next()? == "1";
+                let incoming = parts.next()?.parse().ok()?;
+                let score = parts.next()?.parse().ok()?;
+                let hash = parts.next()?.parse().ok()?;
+                let mut blocks = Vec::new();
+                let block_list = parts.next()?;
+                if block_list != "-" {
+                    for block in block_list.split(';') {
+                        let mut fields = block.split(',');
+                        let x = fields.next()?.parse().ok()?;
+                        let y = fields.next()?.parse().ok()?;
+                        let cell = match fields.next()? {
+                            "#" => None,
+                            letter => {
+                                let mut letter = letter.chars();
+                                let piece_type = PieceType::from_letter(letter.next()?)?;
+                                if letter.next().is_some() {
+                                    return None;
+                                }
+                                Some(piece_type)
+                            }
+                        };
+                        blocks.push((Block { x, y }, cell));
+                    }
+                }
+                NetMessage::Lock {
+                    piece_type,
+                    t_spin,
+                    hard_drop,
+                    incoming,
+                    score,
+                    hash,
+                    blocks,
+                }
+            }
+            "garbage" => NetMessage::Garbage(parts.next()?.parse().ok()?),
+            "topout" => NetMessage::TopOut,
+            _ => return None,
+        };
+        Some(message)
+    }
+}
+
+impl NetSession {
+    pub fn new(stream: TcpStream, local_player: Player) -> std::io::Result<Self> {
+        stream.set_nodelay(true)?;
+        stream.set_nonblocking(true)?;
+        Ok(Self {
+            stream,
+            local_player,
+            disconnected: false,
+            started: false,
+            buffer: Vec::new(),
+            pending: Vec::new(),
+            outgoing: Vec::new(),
+        })
+    }
+
+    // 消息先放入发送缓冲区，连接能写入多少就写入多少，剩下的在之后的帧中发送
+    pub fn send(&mut self, message: &NetMessage) {
+        if self.disconnected {
+            return;
+        }
+        self.outgoing.extend_from_slice(message.encode().as_bytes());
+        self.outgoing.push(b'\n');
+        self.flush();
+    }
+
+    // 非阻塞地写入发送缓冲区中的数据；对手长时间不读取导致缓冲区过大时视为断开
+    pub fn flush(&mut self) {
+        while !self.disconnected && !self.outgoing.is_empty() {
+            match self.stream.write(&self.outgoing) {
+                Ok(0) => self.disconnected = true,
+                Ok(written) => {
+                    self.outgoing.drain(..written);
+                }
+                Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                Err(err) if err.kind() == ErrorKind::Interrupted => {}
+                Err(err) => {
+                    warn!("network send failed: {}", err);
+                    self.disconnected = true;
+                }
+            }
+        }
+        if self.outgoing.len() > MAX_OUTGOING_BYTES {
+            warn!("network send buffer overflow, closing connection");
+            self.disconnected = true;
+        }
+    }
+
+    // 读取所有已到达的消息，连接关闭时设置 disconnected
+    pub fn receive(&mut self) -> Vec<NetMessage> {
+        let mut chunk = [0u8; 4096];
+        loop {
+            match self.stream.read(&mut chunk) {
+                Ok(0) => {
+                    self.disconnected = true;
+                    break;
+                }
+                Ok(read) => self.buffer.extend_from_slice(&chunk[..read]),
+                Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                Err(err) if err.kind() == ErrorKind::Interrupted => {}
+                Err(err) => {
+                    warn!("network receive failed: {}", err);
+                    self.disconnected = true;
+                    break;
+                }
+            }
+        }
+
+        let mut messages = std::mem::take(&mut self.pending);
+        while let Some(end) = self.buffer.iter().position(|byte| *byte == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=end).collect();
+            let line = String::from_utf8_lossy(&line);
+            match NetMessage::parse(line.trim()) {
+                Some(message) => messages.push(message),
+                None => warn!("ignoring malformed network message: {}", line.trim()),
+            }
+        }
+        messages
+    }
+}
+
+// 面板状态的哈希，只与方块位置有关。对排序后的坐标使用 FNV-1a，两端的结果不依赖标准库的哈希实现
+pub fn board_hash(blocks: impl Iterator<Item = Block>) -> u64 {
+    const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+    const FNV_PRIME: u64 = 0x100000001b3;
+    let mut positions: Vec<(i32, i32)> = blocks.map(|block| (block.x, block.y)).collect();
+    positions.sort();
+    let mut hash = FNV_OFFSET;
+    for (x, y) in positions {
+        for byte in x.to_le_bytes().into_iter().chain(y.to_le_bytes()) {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(FNV_PRIME);
+        }
+    }
+    hash
+}
+
+// 处理菜单中的主机/加入按钮
+pub fn handle_network_actions(
+    mut commands: Commands,
+    mut actions: EventReader<NetworkAction>,
+    net_config: Res<NetConfig>,
+    mut status: ResMut<NetStatus>,
+) {
+    for action in actions.read() {
+        commands.remove_resource::<NetListener>();
+        commands.remove_resource::<NetSession>();
+        match action {
+            NetworkAction::Host => {
+                // 监听所有网卡，局域网中的其他机器也可以加入
+                let port = match net_config.port() {
+                    Ok(port) => port,
+                    Err(err) => {
+                        status.0 = format!("Host failed: {}", err);
+                        continue;
+                    }
+                };
+                let listener = TcpListener::bind(("0.0.0.0", port))
+                    .and_then(|listener| listener.set_nonblocking(true).map(|_| listener));
+                match listener {
+                    Ok(listener) => {
+                        status.0 = format!("Waiting for opponent on port {}", port);
+                        commands.insert_resource(NetListener(listener));
+                    }
+                    Err(err) => status.0 = format!("Host failed: {}", err),
+                }
+            }
+            NetworkAction::Join => {
+                let stream = net_config
+                    .join_address()
+                    .to_socket_addrs()
+                    .and_then(|mut addresses| {
+                        addresses.next().ok_or_else(|| {
+                            std::io::Error::new(ErrorKind::NotFound, "address not found")
+                        })
+                    })
+                    .and_then(|address| TcpStream::connect_timeout(&address, CONNECT_TIMEOUT))
+                    .and_then(|stream| NetSession::new(stream, Player(1)));
+                match stream {
+                    Ok(session) => {
+                        status.0 = "Connected, waiting for host".to_string();
+                        commands.insert_resource(session);
+                    }
+                    Err(err) => status.0 = format!("Join failed: {}", err),
+                }
+            }
+        }
+    }
+}
+
+// 主机：对手连接后发送本局配置，等待对手回复 Ready
+pub fn accept_connection(
+    mut commands: Commands,
+    listener: Res<NetListener>,
+    mut config: ResMut<GameConfig>,
+    mut status: ResMut<NetStatus>,
+) {
+    let stream = match listener.0.accept() {
+        Ok((stream, _)) => stream,
+        Err(err) if err.kind() == ErrorKind::WouldBlock => return,
+        Err(err) => {
+            status.0 = format!("Accept failed: {}", err);
+            commands.remove_resource::<NetListener>();
+            return;
+        }
+    };
+    commands.remove_resource::<NetListener>();
+    let mut session = match NetSession::new(stream, Player(0)) {
+        Ok(session) => session,
+        Err(err) => {
+            status.0 = format!("Accept failed: {}", err);
+            return;
+        }
+    };
+    let seed = rand::random();
+    config.mode = GameMode::Versus;
+    config.seed = Some(seed);
+    session.send(&NetMessage::Hello {
+        seed,
+        start_level: config.start_level,
+        garbage_messiness: config.garbage_messiness,
+    });
+    status.0 = "Opponent connected, waiting for ready".to_string();
+    commands.insert_resource(session);
+}
+
+// 握手：加入方收到主机的配置后回复 Ready 并开始游戏，主机收到 Ready 后开始游戏，
+// 保证双方都在开始后才收到对方的游戏消息。握手消息之后一起到达的消息留给游戏中处理
+pub fn wait_for_opponent(
+    mut commands: Commands,
+    mut session: ResMut<NetSession>,
+    mut config: ResMut<GameConfig>,
+    mut status: ResMut<NetStatus>,
+    mut restart_events: EventWriter<RestartGame>,
+) {
+    if session.started {
+        return;
+    }
+    let host = session.local_player == Player(0);
+    let mut messages = session.receive().into_iter();
+    for message in messages.by_ref() {
+        match message {
+            NetMessage::Hello {
+                seed,
+                start_level,
+                garbage_messiness,
+            } if !host => {
+                config.mode = GameMode::Versus;
+                config.seed = Some(seed);
+                config.start_level = start_level;
+                config.garbage_messiness = garbage_messiness;
+                session.send(&NetMessage::Ready);
+            }
+            NetMessage::Ready if host => {}
+            _ => continue,
+        }
+        session.started = true;
+        restart_events.send(RestartGame);
+        break;
+    }
+    session.pending = messages.collect();
+    if session.disconnected {
+        status.0 = if host { "Opponent disconnected" } else { "Host disconnected" }.to_string();
+        commands.remove_resource::<NetSession>();
+    }
+}
+
+// 回到主菜单时断开连接，之后的本地游戏不再使用主机的种子
+pub fn close_network_session(
+    mut commands: Commands,
+    session: Option<Res<NetSession>>,
+    mut config: ResMut<GameConfig>,
+    mut status: ResMut<NetStatus>,
+) {
+    commands.remove_resource::<NetListener>();
+    if session.is_some() {
+        commands.remove_resource::<NetSession>();
+        config.seed = None;
+    }
+    status.0.clear();
+}
+
+// 网络对战开始时标记对手的面板，本机玩家使用方向键
+pub fn setup_network_boards(
+    mut commands: Commands,
+    session: Res<NetSession>,
+    main_board: Query<(Entity, &Player), With<MainBoard>>,
+) {
+    for (entity, player) in &main_board {
+        if *player == session.local_player {
+            commands.entity(entity).insert(InputMap::arrows());
+        } else {
+            commands.entity(entity).insert(RemotePlayer).remove::<InputMap>();
+        }
+    }
+}
+
+// 本机按键状态变化时发送给对手
+pub fn send_local_input(
+    mut session: ResMut<NetSession>,
+    main_board: Query<&PlayerInput, (With<MainBoard>, Without<RemotePlayer>)>,
+    mut last_sent: Local<PlayerInput>,
+) {
+    for input in &main_board {
+        if input.rotate {
+            session.send(&NetMessage::Rotate);
+        }
+        if input.hard_drop {
+            session.send(&NetMessage::HardDrop);
+        }
+        let held = PlayerInput {
+            rotate: false,
+            hold: false,
+            hard_drop: false,
+            ..*input
+        };
+        if held != *last_sent {
+            session.send(&NetMessage::Input {
+                left: held.left,
+                right: held.right,
+                down: held.down,
+            });
+            *last_sent = held;
+        }
+    }
+}
+
+// 本机骨牌固定时发送面板状态
+pub fn send_local_locks(
+    mut session: ResMut<NetSession>,
+    mut piece_locked_events: EventReader<PieceLocked>,
+    main_board: Query<(&IncomingGarbage, &Score), (With<MainBoard>, Without<RemotePlayer>)>,
+    board_blocks: Query<(&Parent, &Block, &BlockSkin), Without<PieceType>>,
+) {
+    for event in piece_locked_events.read() {
+        let Ok((incoming, score)) = main_board.get(event.board) else {
+            continue;
+        };
+        let blocks: Vec<(Block, Option<PieceType>)> = board_blocks
+            .iter()
+            .filter(|(parent, _, _)| parent.get() == event.board)
+            .map(|(_, block, skin)| match skin {
+                BlockSkin::Piece(piece_type) => (*block, Some(*piece_type)),
+                BlockSkin::Garbage => (*block, None),
+            })
+            .collect();
+        session.send(&NetMessage::Lock {
+            piece_type: event.piece_type,
+            t_spin: event.t_spin,
+            hard_drop: event.hard_drop,
+            incoming: incoming.0,
+            score: score.0,
+            hash: board_hash(blocks.iter().map(|(block, _)| *block)),
+            blocks,
+        });
+    }
+}
+
+// 处理对手发来的消息，更新对手的面板；对手断开连接时本机获胜
+pub fn receive_network_messages(
+    mut commands: Commands,
+    piece_set: Res<PieceSet>,
+    mut session: ResMut<NetSession>,
+    mut app_state: ResMut<NextState<AppState>>,
+    mut main_board: Query<
+        (
+            Entity,
+            &mut PlayerInput,
+            &mut IncomingGarbage,
+            &mut Score,
+            &mut PieceQueue,
+            &mut PieceRandomizer,
+            &mut HasNextPiece,
+            Has<RemotePlayer>,
+        ),
+        With<MainBoard>,
+    >,
+    blocks: Query<(Entity, &Parent, &Block, Has<PieceType>)>,
+    mut piece_locked_events: EventWriter<PieceLocked>,
+) {
+    let mut locks = Vec::new();
+    for message in session.receive() {
+        for (entity, mut input, mut incoming, .., remote) in &mut main_board {
+            match (&message, remote) {
+                (NetMessage::Input { left, right, down }, true) => {
+                    input.left = *left;
+                    input.right = *right;
+                    input.down = *down;
+                }
+                (NetMessage::Rotate, true) => input.rotate = true,
+                (NetMessage::HardDrop, true) => input.hard_drop = true,
+                (NetMessage::Lock { .. }, true) => locks.push(message.clone()),
+                (NetMessage::Garbage(lines), false) => incoming.0 += lines,
+                (NetMessage::TopOut, true) => {
+                    commands.entity(entity).insert(ToppedOut);
+                    app_state.set(AppState::GameOver);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let remote_board = main_board.iter_mut().find(|(.., remote)| *remote);
+    if let (Some(last_lock), Some(remote_board)) = (locks.last(), remote_board) {
+        let (entity, _, mut incoming, mut score, mut piece_queue, mut randomizer, mut has_next_piece, _) =
+            remote_board;
+        let own_blocks: Vec<_> = blocks
+            .iter()
+            .filter(|(_, parent, _, _)| parent.get() == entity)
+            .collect();
+        let has_piece = own_blocks.iter().any(|(_, _, _, is_piece)| *is_piece);
+
+        // 同一帧收到多次固定时，中间的骨牌在本机还没有生成，从队列中取出以保持两边骨牌序列一致
+        let skipped_pieces = locks.len() - if has_piece { 1 } else { 0 };
+        for _ in 0..skipped_pieces {
+            piece_queue.refill(&piece_set, &mut randomizer);
+            piece_queue.0.pop_front();
+            has_next_piece.0 = false;
+        }
+
+        let NetMessage::Lock {
+            incoming: remote_incoming,
+            score: remote_score,
+            hash,
+            blocks: snapshot,
+            ..
+        } = last_lock
+        else {
+            unreachable!();
+        };
+        let local_hash = board_hash(own_blocks.iter().map(|(_, _, block, _)| **block));
+        if locks.len() == 1 && has_piece && local_hash == *hash {
+            // 与对手一致，直接固定当前骨牌
+            for (block_entity, _, _, is_piece) in own_blocks.iter() {
+                if *is_piece {
+                    commands.entity(*block_entity).remove::<PieceType>();
+                }
+            }
+        } else {
+            // 不一致时按对手的面板重建
+            for (block_entity, _, _, _) in own_blocks.iter() {
+                commands.entity(*block_entity).despawn_recursive();
+            }
+            commands.entity(entity).with_children(|parent| {
+                for (block, cell) in snapshot.iter() {
+                    spawn_board_block(
+                        parent,
+                        *block,
+                        cell_color(&piece_set, *cell),
+                        BlockSkin::cell(&piece_set, *cell),
+                    );
+                }
+            });
+        }
+        incoming.0 = *remote_incoming;
+        score.0 = *remote_score;
+        for lock in locks.iter() {
+            if let NetMessage::Lock {
+                piece_type,
+                t_spin,
+                hard_drop,
+                ..
+            } = lock
+            {
+                piece_locked_events.send(PieceLocked {
+                    board: entity,
+                    piece_type: *piece_type,
+                    blocks: Vec::new(),
+                    t_spin: *t_spin,
+                    hard_drop: *hard_drop,
+                });
+            }
+        }
+    }
+
+    if session.disconnected {
+        for (entity, .., remote) in &main_board {
+            if remote {
+                commands.entity(entity).insert(ToppedOut);
+            }
+        }
+        app_state.set(AppState::GameOver);
+    }
+}
+
+// 每帧继续发送之前没有写完的数据
+pub fn flush_network_session(mut session: ResMut<NetSession>) {
+    session.flush();
+}
+
+// 本机方块溢出时通知对手
+pub fn send_top_out(
+    mut session: ResMut<NetSession>,
+    main_board: Query<(), (With<MainBoard>, With<ToppedOut>, Without<RemotePlayer>)>,
+) {
+    if !main_board.is_empty() {
+        session.send(&NetMessage::TopOut);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+
+    use super::*;
+
+    fn round_trip(message: NetMessage) {
+        let line = message.encode();
+        assert!(!line.contains('\n'), "{}", line);
+        assert_eq!(NetMessage::parse(&line), Some(message), "{}", line);
+    }
+
+    #[test]
+    fn messages_round_trip() {
+        round_trip(NetMessage::Hello {
+            seed: u64::MAX,
+            start_level: 5,
+            garbage_messiness: 0.25,
+        });
+        round_trip(NetMessage::Input {
+            left: true,
+            right: false,
+            down: true,
+        });
+        round_trip(NetMessage::Ready);
+        round_trip(NetMessage::Rotate);
+        round_trip(NetMessage::HardDrop);
+        round_trip(NetMessage::Garbage(4));
+        round_trip(NetMessage::TopOut);
+    }
+
+    #[test]
+    fn lock_round_trip() {
+        round_trip(NetMessage::Lock {
+            piece_type: PieceType('T'),
+            t_spin: true,
+            hard_drop: true,
+            incoming: 3,
+            score: 1200,
+            hash: 0x1234_5678_9abc_def0,
+            blocks: vec![
+                (Block { x: -3, y: 21 }, Some(PieceType('T'))),
+                (Block { x: 0, y: 21 }, None),
+                (Block { x: 6, y: 20 }, Some(PieceType('I'))),
+            ],
+        });
+        // 空面板用 - 表示
+        round_trip(NetMessage::Lock {
+            piece_type: PieceType('O'),
+            t_spin: false,
+            hard_drop: false,
+            incoming: 0,
+            score: 0,
+            hash: board_hash(std::iter::empty()),
+            blocks: Vec::new(),
+        });
+    }
+
+    #[test]
+    fn rejects_malformed_lines() {
+        for line in [
+            "",
+            "unknown",
+            "hello 1 2",
+            "garbage x",
+            "lock TT 0 0 0 0 0 -",
+            "lock T 0 0 0 0 0 1,2",
+            "lock T 0 0 0 0 0 1,2,.",
+        ] {
+            assert_eq!(NetMessage::parse(line), None, "{}", line);
+        }
+    }
+
+    #[test]
+    fn board_hash_ignores_order() {
+        let blocks = [Block { x: 1, y: 2 }, Block { x: -3, y: 4 }, Block { x: 0, y: 0 }];
+        let reversed = blocks.iter().rev().copied();
+        assert_eq!(board_hash(blocks.into_iter()), board_hash(reversed));
+        assert_ne!(board_hash(blocks.into_iter()), board_hash(blocks[1..].iter().copied()));
+    }
+
+    #[test]
+    fn address_port() {
+        let config = |address: &str| NetConfig {
+            address: address.to_string(),
+        };
+        assert_eq!(config("0.0.0.0:9000").port(), Ok(9000));
+        assert_eq!(config("localhost").port(), Ok(DEFAULT_NET_PORT));
+        assert!(config("localhost:abc").port().is_err());
+        assert_eq!(config("localhost").join_address(), "localhost:7878");
+    }
+
+    // 在一个 World 中反复运行握手系统，直到握手完成
+    fn handshake(session: NetSession) -> World {
+        let mut world = World::new();
+        world.insert_resource(session);
+        world.insert_resource(GameConfig::default());
+        world.init_resource::<NetStatus>();
+        world.init_resource::<Events<RestartGame>>();
+        for _ in 0..200 {
+            world.run_system_once(wait_for_opponent);
+            if world.resource::<NetSession>().started {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        world
+    }
+
+    #[test]
+    fn handshake_keeps_later_messages() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let joiner = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (host, _) = listener.accept().unwrap();
+        let mut host = NetSession::new(host, Player(0)).unwrap();
+        let joiner = NetSession::new(joiner, Player(1)).unwrap();
+
+        // 配置之后紧跟着的消息不能在握手时丢掉
+        host.send(&NetMessage::Hello {
+            seed: 7,
+            start_level: 3,
+            garbage_messiness: 0.5,
+        });
+        host.send(&NetMessage::Garbage(2));
+        let mut joiner_world = handshake(joiner);
+        assert!(joiner_world.resource::<NetSession>().started);
+        assert_eq!(joiner_world.resource::<GameConfig>().seed, Some(7));
+        assert_eq!(joiner_world.resource::<GameConfig>().start_level, 3);
+        assert_eq!(joiner_world.resource::<Events<RestartGame>>().len(), 1);
+        let later = joiner_world.resource_mut::<NetSession>().receive();
+        assert_eq!(later, vec![NetMessage::Garbage(2)]);
+
+        // 主机收到 Ready 后才开始
+        let host_world = handshake(host);
+        assert!(host_world.resource::<NetSession>().started);
+        assert_eq!(host_world.resource::<Events<RestartGame>>().len(), 1);
+    }
+}
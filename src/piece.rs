@@ -1,198 +1,226 @@
-use bevy::{color::palettes, prelude::*};
-use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+use bevy::prelude::*;
+use rand::rngs::StdRng;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::time::Duration;
 
 use crate::{
-    board::{Block, HasNextPiece, MainBoard, BLOCK_LENGTH, COL_COUNT, ROW_COUNT},
-    common::GameAudios,
-    state::{new_block_sprite, Score},
+    board::{Block, HasNextPiece, MainBoard, COL_COUNT, ROW_COUNT},
+    mode::GameConfig,
+    piece_set::PieceSet,
+    player::PlayerInput,
+    rule_set::RuleSet,
+    scoring::{
+        classic_clear_score, full_clear_score, ClearResult, ScoringState, HARD_DROP_SCORE,
+        SOFT_DROP_SCORE,
+    },
+    state::{new_block_sprite, Level, Lines, Score, SessionStats},
+    theme::BlockSkin,
+    network::RemotePlayer,
+    versus::attack_lines,
 };
-use rand::Rng;
 
 // 自动向下移动四格骨牌计时器
-#[derive(Debug, Resource)]
+#[derive(Debug, Component)]
 pub struct AutoMovePieceDownTimer(pub Timer);
 
+impl AutoMovePieceDownTimer {
+    pub fn new(interval: Duration) -> Self {
+        Self(Timer::new(interval, TimerMode::Repeating))
+    }
+}
+
 // 控制手动移动频率
-#[derive(Debug, Resource)]
+#[derive(Debug, Component)]
 pub struct ManuallyMoveTimer(pub Timer);
 
-#[derive(Debug, Resource)]
-pub struct RemovePieceComponentTimer(pub Timer);
+impl ManuallyMoveTimer {
+    pub fn new(interval: Duration) -> Self {
+        Self(Timer::new(interval, TimerMode::Once))
+    }
+}
 
-// 待生成的骨牌队列
-#[derive(Debug, Resource)]
-pub struct PieceQueue(pub VecDeque<PieceConfig>);
+#[derive(Debug, Component)]
+pub struct RemovePieceComponentTimer(pub Timer);
 
-pub fn setup_piece_queue(mut commands: Commands) {
-    let mut piece_queue = PieceQueue(VecDeque::new());
-    piece_queue.0.extend(random_7_pieces());
-    commands.insert_resource(piece_queue);
+impl RemovePieceComponentTimer {
+    pub fn new(lock_delay: Duration) -> Self {
+        Self(Timer::new(lock_delay, TimerMode::Once))
+    }
 }
 
-// 展示下一个骨牌
-#[derive(Debug, Resource)]
-pub struct NextPieceType(pub Option<PieceType>);
+// 面板正在播放消行动画，计时结束后消除的行上面的方块才下移，期间不生成新的骨牌
+#[derive(Debug, Component)]
+pub struct ClearingLines(pub Timer);
 
-#[derive(Debug, Clone)]
-pub struct PieceConfig {
+// 正在消除的行中的方块
+#[derive(Debug, Component)]
+pub struct ClearingBlock;
+
+// 当前骨牌最后一次成功的操作是否是旋转，用于判断 T-spin
+#[derive(Debug, Default, Component)]
+pub struct LastMoveIsRotation(pub bool);
+
+// 当前骨牌的旋转状态，即 PieceDef::rotations 的序号，生成时为 0
+#[derive(Debug, Default, Component)]
+pub struct PieceRotation(pub usize);
+
+// 当前骨牌已经按直接落下键落到底部，检测碰撞后立即固定，不等锁定延迟
+#[derive(Debug, Default, Component)]
+pub struct HardDropped(pub bool);
+
+// 骨牌落地固定
+#[derive(Event, Debug, Clone)]
+pub struct PieceLocked {
+    // 骨牌所在的游戏面板
+    pub board: Entity,
     pub piece_type: PieceType,
-    pub blocks: [Block; 4],
-    pub color: Color,
+    pub blocks: Vec<Block>,
+    pub t_spin: bool,
+    // 按直接落下键落到底部后固定
+    pub hard_drop: bool,
 }
 
-impl PieceConfig {
-    pub fn new(piece_type: PieceType, blocks: [Block; 4]) -> Self {
-        let color = Color::Srgba(match piece_type {
-            PieceType::I => palettes::css::LIGHT_CYAN,
-            PieceType::J => palettes::css::BLUE,
-            PieceType::L => palettes::css::ORANGE,
-            PieceType::O => palettes::css::YELLOW,
-            PieceType::S => palettes::css::GREEN,
-            PieceType::T => palettes::css::PURPLE,
-            PieceType::Z => palettes::css::RED,
-        });
-        PieceConfig {
-            piece_type,
-            blocks,
-            color,
-        }
-    }
+// 玩家操作骨牌移动或旋转成功，自动下落不算
+#[derive(Event, Debug, Clone, Copy)]
+pub struct PieceMoved {
+    pub board: Entity,
+    pub kind: PieceMove,
 }
 
-// 四格骨牌
-#[derive(Component, Debug, Clone, Copy, PartialEq, Eq, Ord, PartialOrd)]
-pub enum PieceType {
-    // ####
-    I,
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PieceMove {
+    Shift,
+    Rotate,
+    SoftDrop,
+}
+
+// 骨牌固定后的消行结果，没有消行时 lines 为 0
+#[derive(Event, Debug, Clone)]
+pub struct LinesCleared {
+    pub board: Entity,
+    pub lines: u32,
+    pub t_spin: bool,
+    pub perfect_clear: bool,
+    // 对战时发送给对手的垃圾行数
+    pub attack: u32,
+}
 
-    // #
-    // ###
-    J,
+// 待生成的骨牌队列
+#[derive(Debug, Component)]
+pub struct PieceQueue(pub VecDeque<PieceConfig>);
 
-    //   #
-    // ###
-    L,
+impl PieceQueue {
+    pub fn new(piece_set: &PieceSet, randomizer: &mut PieceRandomizer) -> Self {
+        let mut piece_queue = PieceQueue(VecDeque::new());
+        piece_queue.refill(piece_set, randomizer);
+        piece_queue
+    }
 
-    // ##
-    // ##
-    O,
+    // 队列中不足一袋时补充一袋骨牌
+    pub fn refill(&mut self, piece_set: &PieceSet, randomizer: &mut PieceRandomizer) {
+        if self.0.len() < piece_set.pieces.len() {
+            self.0.extend(piece_set.random_bag(&mut randomizer.0));
+        }
+    }
+}
 
-    //  ##
-    // ##
-    S,
+// 骨牌队列固定（谜题模式），用完后不再补充
+#[derive(Debug, Component)]
+pub struct FixedPieceSequence;
 
-    //  #
-    // ###
-    T,
+// 生成骨牌序列的随机数，由本局的种子初始化，相同种子生成相同的骨牌序列
+#[derive(Debug, Component)]
+pub struct PieceRandomizer(pub StdRng);
 
-    // ##
-    //  ##
-    Z,
+// 骨牌及其生成位置，由骨牌定义生成
+#[derive(Debug, Clone)]
+pub struct PieceConfig {
+    pub piece_type: PieceType,
+    pub blocks: Vec<Block>,
+    pub color: Color,
 }
 
+// 骨牌种类，用骨牌定义中的字母表示，形状和颜色见 PieceSet
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq, Hash, Ord, PartialOrd)]
+pub struct PieceType(pub char);
+
 impl PieceType {
-    pub const PIECE_AMOUNT: u32 = 7;
-}
+    // 网络消息和谜题文件中使用的字母
+    pub fn letter(&self) -> char {
+        self.0
+    }
 
-const SHAPE_I: [[i32; 2]; 4] = [[0, 0], [1, 0], [2, 0], [3, 0]];
-const SHAPE_J: [[i32; 2]; 4] = [[0, 1], [0, 0], [1, 0], [2, 0]];
-const SHAPE_L: [[i32; 2]; 4] = [[0, 0], [1, 0], [2, 0], [2, 1]];
-const SHAPE_O: [[i32; 2]; 4] = [[1, 1], [1, 0], [2, 1], [2, 0]];
-const SHAPE_S: [[i32; 2]; 4] = [[1, 0], [2, 0], [1, 1], [2, 1]];
-const SHAPE_T: [[i32; 2]; 4] = [[0, 0], [1, 1], [1, 0], [2, 0]];
-const SHAPE_Z: [[i32; 2]; 4] = [[0, 1], [1, 1], [1, 0], [2, 0]];
+    // '.' 和 '#' 在谜题文件中表示空格和垃圾方块，不能作为骨牌
+    pub fn from_letter(letter: char) -> Option<Self> {
+        letter.is_ascii_alphanumeric().then_some(PieceType(letter))
+    }
+}
 
 // 可移动方向
-#[derive(Component)]
+#[derive(Component, Debug, Clone, Copy)]
 pub struct Movable {
     pub can_down: bool,
     pub can_left: bool,
     pub can_right: bool,
 }
 
-pub fn piece_shape(piece_type: PieceType) -> [Block; 4] {
-    match piece_type {
-        PieceType::I => SHAPE_I.map(|pos| pos.into()),
-        PieceType::J => SHAPE_J.map(|pos| pos.into()),
-        PieceType::L => SHAPE_L.map(|pos| pos.into()),
-        PieceType::O => SHAPE_O.map(|pos| pos.into()),
-        PieceType::S => SHAPE_S.map(|pos| pos.into()),
-        PieceType::T => SHAPE_T.map(|pos| pos.into()),
-        PieceType::Z => SHAPE_Z.map(|pos| pos.into()),
-    }
-}
-// bag7算法实现随机：每次填充7个随机排序的骨牌
-pub fn random_7_pieces() -> Vec<PieceConfig> {
-    let mut rng = rand::thread_rng();
-    let mut piece_type_set = BTreeSet::new();
-
-    loop {
-        match rng.gen_range(0..PieceType::PIECE_AMOUNT) {
-            0 => {
-                piece_type_set.insert(PieceType::I);
-            }
-            1 => {
-                piece_type_set.insert(PieceType::J);
-            }
-            2 => {
-                piece_type_set.insert(PieceType::L);
-            }
-            3 => {
-                piece_type_set.insert(PieceType::O);
-            }
-            4 => {
-                piece_type_set.insert(PieceType::S);
-            }
-            5 => {
-                piece_type_set.insert(PieceType::T);
-            }
-            6 => {
-                piece_type_set.insert(PieceType::Z);
-            }
-            _ => {
-                panic!("Random value is unexpected");
-            }
-        }
-        if piece_type_set.len() == PieceType::PIECE_AMOUNT as usize {
-            break;
-        }
-    }
-    piece_type_set
-        .iter()
-        .map(|piece_type| PieceConfig::new(*piece_type, piece_shape(*piece_type)))
-        .collect()
-}
-
 // 自动生成新的四格骨牌
 pub fn auto_generate_new_piece(
     mut commands: Commands,
-    query: Query<&PieceType>,
-    main_board: Query<Entity, With<MainBoard>>,
-    mut piece_queue: ResMut<PieceQueue>,
-    mut has_next_piece: ResMut<HasNextPiece>,
+    piece_set: Res<PieceSet>,
+    query: Query<&Parent, With<PieceType>>,
+    mut main_board: Query<
+        (
+            Entity,
+            &mut PieceQueue,
+            &mut PieceRandomizer,
+            &mut HasNextPiece,
+            &mut LastMoveIsRotation,
+            &mut PieceRotation,
+            &mut HardDropped,
+            Has<FixedPieceSequence>,
+        ),
+        (With<MainBoard>, Without<ClearingLines>),
+    >,
 ) {
-    // 获取到
-    let main_board_entity = main_board.single();
-    if piece_queue.0.len() < PieceType::PIECE_AMOUNT as usize {
-        piece_queue.0.extend(random_7_pieces());
-    }
-    // 如果没有四格骨牌，则生成新的
-    if query.is_empty() {
+    for (
+        main_board_entity,
+        mut piece_queue,
+        mut randomizer,
+        mut has_next_piece,
+        mut last_move_is_rotation,
+        mut rotation,
+        mut hard_dropped,
+        fixed_sequence,
+    ) in &mut main_board
+    {
+        if !fixed_sequence {
+            piece_queue.refill(&piece_set, &mut randomizer);
+        }
+        // 如果该面板上没有四格骨牌，则生成新的
+        if query.iter().any(|parent| parent.get() == main_board_entity) {
+            continue;
+        }
+        // 固定的骨牌序列已经用完
+        let Some(piece_config) = piece_queue.0.pop_front() else {
+            continue;
+        };
         // 设置 状态值 表示 可以更新下一个待出现 的卡片
         has_next_piece.0 = false;
-        let piece_config = piece_queue.0.pop_front().unwrap();
+        last_move_is_rotation.0 = false;
+        rotation.0 = 0;
+        hard_dropped.0 = false;
         // 生成新的四格骨牌
         let color = piece_config.color;
-        let piece_type: PieceType = piece_config.piece_type.clone();
+        let piece_type: PieceType = piece_config.piece_type;
 
         for block in piece_config.blocks.iter() {
             let cur = block.transform_to();
-            let left = Val::Px(cur.x);
-            let top = Val::Px(cur.y);
             commands.entity(main_board_entity).with_children(|parent| {
                 parent
                     .spawn(piece_type)
-                    .insert(new_block_sprite(color, left, top))
+                    .insert(new_block_sprite(color, cur))
+                    .insert(BlockSkin::Piece(piece_type))
                     .insert(*block)
                     .insert(Movable {
                         can_down: true,
@@ -206,76 +234,175 @@ pub fn auto_generate_new_piece(
 
 // 自动和手动移动四格骨牌
 pub fn move_piece(
-    mut commands: Commands,
-    game_audios: Res<GameAudios>,
-    mut query: Query<(&mut Block, &mut Style, &Movable), With<PieceType>>,
-    mut auto_move_timer: ResMut<AutoMovePieceDownTimer>,
-    mut manually_move_timer: ResMut<ManuallyMoveTimer>,
-    keyboard_input: Res<ButtonInput<KeyCode>>,
+    config: Res<GameConfig>,
+    mut piece_moved_events: EventWriter<PieceMoved>,
+    mut query: Query<(&Parent, &mut Block, &Movable), With<PieceType>>,
+    board_query: Query<(&Parent, &Block), Without<PieceType>>,
+    mut main_board: Query<
+        (
+            Entity,
+            &mut PlayerInput,
+            &mut AutoMovePieceDownTimer,
+            &mut ManuallyMoveTimer,
+            &mut LastMoveIsRotation,
+            &mut HardDropped,
+            &mut Score,
+        ),
+        With<MainBoard>,
+    >,
     time: Res<Time>,
 ) {
-    auto_move_timer.0.tick(time.delta());
-    manually_move_timer.0.tick(time.delta());
-    let mut reset_manually_move_timer = false;
-    let can_manually_move = manually_move_timer.0.finished();
-    // 同一桢 手动向下移动后 不能在自动移动
-    let can_auto_move = auto_move_timer.0.finished()
-        && !(can_manually_move && keyboard_input.pressed(KeyCode::ArrowDown));
-    for (mut block, mut style, movable) in &mut query {
-        // 手动移动
-        if can_manually_move {
-            if keyboard_input.pressed(KeyCode::ArrowLeft) && movable.can_left {
-                block.x -= 1;
-                reset_manually_move_timer = true;
-                spawn_drop_audio(&mut commands, &game_audios);
-            } else if keyboard_input.pressed(KeyCode::ArrowRight) && movable.can_right {
-                block.x += 1;
-                reset_manually_move_timer = true;
-                spawn_drop_audio(&mut commands, &game_audios);
-            } else if keyboard_input.pressed(KeyCode::ArrowDown) && movable.can_down {
-                reset_manually_move_timer = true;
+    for (
+        main_board_entity,
+        mut input,
+        mut auto_move_timer,
+        mut manually_move_timer,
+        mut last_move_is_rotation,
+        mut hard_dropped,
+        mut score,
+    ) in &mut main_board
+    {
+        // 直接落下只在按下的那一次生效，落下后清除
+        if input.hard_drop {
+            input.hard_drop = false;
+            let piece_blocks: Vec<Block> = query
+                .iter()
+                .filter(|(parent, _, _)| parent.get() == main_board_entity)
+                .map(|(_, block, _)| *block)
+                .collect();
+            if !piece_blocks.is_empty() && !hard_dropped.0 {
+                let board_blocks: Vec<Block> = board_query
+                    .iter()
+                    .filter(|(parent, _)| parent.get() == main_board_entity)
+                    .map(|(_, block)| *block)
+                    .collect();
+                let distance = drop_distance(&piece_blocks, &board_blocks);
+                for (parent, mut block, _) in &mut query {
+                    if parent.get() == main_board_entity {
+                        block.y += distance;
+                    }
+                }
+                if distance > 0 {
+                    last_move_is_rotation.0 = false;
+                }
+                if config.mode.uses_full_scoring() {
+                    score.0 += HARD_DROP_SCORE * distance as u32;
+                }
+                // 落地的音效和粒子随 PieceLocked 一起产生
+                hard_dropped.0 = true;
+                continue;
+            }
+        }
+        auto_move_timer.0.tick(time.delta());
+        manually_move_timer.0.tick(time.delta());
+        let mut reset_manually_move_timer = false;
+        let mut shifted = false;
+        let mut soft_dropped = false;
+        let can_manually_move = manually_move_timer.0.finished();
+        // 同一桢 手动向下移动后 不能在自动移动
+        let can_auto_move = auto_move_timer.0.finished()
+            && !(can_manually_move && input.down);
+        for (parent, mut block, movable) in &mut query {
+            if parent.get() != main_board_entity {
+                continue;
+            }
+            // 手动移动
+            if can_manually_move {
+                if input.left && movable.can_left {
+                    block.x -= 1;
+                    reset_manually_move_timer = true;
+                    shifted = true;
+                } else if input.right && movable.can_right {
+                    block.x += 1;
+                    reset_manually_move_timer = true;
+                    shifted = true;
+                } else if input.down && movable.can_down {
+                    reset_manually_move_timer = true;
+                    soft_dropped = true;
+                    block.y += 1;
+                }
+            }
+            // 自动下移
+            if can_auto_move && movable.can_down {
                 block.y += 1;
-                spawn_drop_audio(&mut commands, &game_audios);
+                last_move_is_rotation.0 = false;
             }
+        }
 
-            let cur = block.transform_to();
-            style.top = Val::Px(cur.y);
-            style.left = Val::Px(cur.x);
+        if reset_manually_move_timer {
+            manually_move_timer.0.reset();
+            last_move_is_rotation.0 = false;
         }
-        // 自动下移
-        if can_auto_move && movable.can_down {
-            block.y += 1;
-            spawn_drop_audio(&mut commands, &game_audios);
-            let cur = block.transform_to();
-            style.top = Val::Px(cur.y);
-            style.left = Val::Px(cur.x);
+        if soft_dropped && config.mode.uses_full_scoring() {
+            score.0 += SOFT_DROP_SCORE;
+        }
+        // 每个方块都移动了一次，每块面板只发送一次
+        if shifted {
+            piece_moved_events.send(PieceMoved {
+                board: main_board_entity,
+                kind: PieceMove::Shift,
+            });
+        } else if soft_dropped {
+            piece_moved_events.send(PieceMoved {
+                board: main_board_entity,
+                kind: PieceMove::SoftDrop,
+            });
         }
     }
+}
 
-    if reset_manually_move_timer {
-        manually_move_timer.0.reset();
+// 检查碰撞
+pub fn check_collision(
+    mut piece_query: Query<(&Parent, &Block, &mut Movable), With<PieceType>>,
+    board_query: Query<(&Parent, &Block), Without<PieceType>>,
+    main_board: Query<Entity, With<MainBoard>>,
+) {
+    for main_board_entity in &main_board {
+        let piece_blocks: Vec<Block> = piece_query
+            .iter()
+            .filter(|(parent, _, _)| parent.get() == main_board_entity)
+            .map(|(_, block, _)| *block)
+            .collect();
+        let board_blocks: Vec<Block> = board_query
+            .iter()
+            .filter(|(parent, _)| parent.get() == main_board_entity)
+            .map(|(_, block)| *block)
+            .collect();
+        let flags = movable_flags(&piece_blocks, &board_blocks);
+
+        // 更新Movable
+        for (parent, _, mut movable) in &mut piece_query {
+            if parent.get() != main_board_entity {
+                continue;
+            }
+            movable.can_left = flags.can_left;
+            movable.can_right = flags.can_right;
+            movable.can_down = flags.can_down;
+        }
     }
 }
 
-// 向下移动的音效
-fn spawn_drop_audio(commands: &mut Commands, game_audios: &Res<GameAudios>) {
-    commands.spawn(AudioBundle {
-        source: game_audios.drop.clone(),
-        settings: PlaybackSettings::DESPAWN,
-    });
+// 骨牌一直向下移动直到落地的格数
+pub fn drop_distance(piece_blocks: &[Block], board_blocks: &[Block]) -> i32 {
+    let mut blocks = piece_blocks.to_vec();
+    let mut distance = 0;
+    while movable_flags(&blocks, board_blocks).can_down {
+        for block in blocks.iter_mut() {
+            block.y += 1;
+        }
+        distance += 1;
+    }
+    distance
 }
 
-// 检查碰撞
-pub fn check_collision(
-    mut piece_query: Query<(&mut Block, &mut Movable), With<PieceType>>,
-    board_query: Query<&Block, Without<PieceType>>,
-) {
+// 计算骨牌可以移动的方向
+pub fn movable_flags(piece_blocks: &[Block], board_blocks: &[Block]) -> Movable {
     let mut can_down = true;
     let mut can_left = true;
     let mut can_right = true;
 
     // 遍历正在移动的方块 检查是否碰撞边界
-    for (block, _) in &mut piece_query {
+    for block in piece_blocks {
         let cur_pos = block.transform_to_real_pos();
         if cur_pos.0 == 0 {
             // 碰撞左边界
@@ -292,9 +419,9 @@ pub fn check_collision(
     }
 
     // 遍历正在移动的方块 检查是否碰撞面板方块
-    for (block, _) in &piece_query {
+    for block in piece_blocks {
         // 遍历 面板已经存在的方块
-        for board_block in &board_query {
+        for board_block in board_blocks {
             let board_y = board_block.y;
             let board_x = board_block.x;
             if board_y == block.y {
@@ -319,266 +446,355 @@ pub fn check_collision(
         }
     }
 
-    // 更新Movable
-    for (_, mut movable) in &mut piece_query {
-        movable.can_left = can_left;
-        movable.can_right = can_right;
-        movable.can_down = can_down;
+    Movable {
+        can_down,
+        can_left,
+        can_right,
     }
 }
 
 // 当piece移到底部后，移除piece组件
 pub fn remove_piece_component(
     mut commands: Commands,
-    q_piece_blocks: Query<(Entity, &Movable), With<PieceType>>,
-    mut timer: ResMut<RemovePieceComponentTimer>,
-    keyboard_input: Res<ButtonInput<KeyCode>>,
+    piece_set: Res<PieceSet>,
+    q_piece_blocks: Query<(Entity, &Parent, &Movable, &Block, &PieceType)>,
+    q_board: Query<(&Parent, &Block), Without<PieceType>>,
+    mut main_board: Query<
+        (
+            Entity,
+            &PlayerInput,
+            &mut RemovePieceComponentTimer,
+            &LastMoveIsRotation,
+            &HardDropped,
+        ),
+        (With<MainBoard>, Without<RemotePlayer>),
+    >,
+    mut piece_locked_events: EventWriter<PieceLocked>,
     time: Res<Time>,
 ) {
-    if !q_piece_blocks.is_empty() && !q_piece_blocks.iter().last().unwrap().1.can_down {
-        if !q_piece_blocks.iter().last().unwrap().1.can_down {
-            timer.0.tick(time.delta());
-        } else {
-            // 无法下移时，通过左右移动获得重新下移能力
-            timer.0.reset();
+    // 网络对战中对手的骨牌由对手决定何时固定
+    for (main_board_entity, input, mut timer, last_move_is_rotation, hard_dropped) in
+        &mut main_board
+    {
+        let piece_blocks: Vec<(Entity, &Movable, &Block, &PieceType)> = q_piece_blocks
+            .iter()
+            .filter(|(_, parent, _, _, _)| parent.get() == main_board_entity)
+            .map(|(entity, _, movable, block, piece_type)| (entity, movable, block, piece_type))
+            .collect();
+        if let Some((_, movable, _, _)) = piece_blocks.last() {
+            // 无法下移时计时，到时间后固定
+            if !movable.can_down {
+                timer.0.tick(time.delta());
+            }
         }
-    }
-    let mut reset_timer = false;
-    for (entity, movable) in &q_piece_blocks {
-        // 到达底部后，仍可短时间内左右移动
-        if !movable.can_down {
-            // 当到达底部后，按向下键时，跳过timer直接开始新一个piece
-            if timer.0.just_finished() || keyboard_input.pressed(KeyCode::ArrowDown) {
-                commands.entity(entity).remove::<PieceType>();
-                reset_timer = true;
+        let mut reset_timer = false;
+        for (entity, movable, _, _) in piece_blocks.iter() {
+            // 到达底部后，仍可短时间内左右移动
+            if !movable.can_down {
+                // 当到达底部后，按向下键或直接落下时，跳过timer直接开始新一个piece
+                if timer.0.just_finished() || input.down || hard_dropped.0 {
+                    commands.entity(*entity).remove::<PieceType>();
+                    reset_timer = true;
+                }
             }
         }
-    }
-    if reset_timer {
-        timer.0.reset();
+        if reset_timer {
+            timer.0.reset();
+            let piece_type = *piece_blocks[0].3;
+            let blocks: Vec<Block> = piece_blocks.iter().map(|(_, _, block, _)| **block).collect();
+            let board_blocks: Vec<Block> = q_board
+                .iter()
+                .filter(|(parent, _)| parent.get() == main_board_entity)
+                .map(|(_, block)| *block)
+                .collect();
+            let t_spin = piece_set.get(piece_type).is_some_and(|piece| piece.spin)
+                && last_move_is_rotation.0
+                && is_t_spin(&blocks, &board_blocks);
+            piece_locked_events.send(PieceLocked {
+                board: main_board_entity,
+                piece_type,
+                blocks,
+                t_spin,
+                hard_drop: hard_dropped.0,
+            });
+        }
     }
 }
 
+// 判断是否构成 T-spin：中心方块四个对角中至少有三个被占据（边界也算占据）
+pub fn is_t_spin(piece_blocks: &[Block], board_blocks: &[Block]) -> bool {
+    // 中心方块与其余三个方块都相邻
+    let center = piece_blocks.iter().find(|block| {
+        piece_blocks
+            .iter()
+            .filter(|other| (other.x - block.x).abs() + (other.y - block.y).abs() == 1)
+            .count()
+            == 3
+    });
+    let Some(center) = center else {
+        return false;
+    };
+    let occupied_corners = [(-1, -1), (1, -1), (-1, 1), (1, 1)]
+        .iter()
+        .filter(|(dx, dy)| {
+            let corner = Block {
+                x: center.x + dx,
+                y: center.y + dy,
+            };
+            let (real_x, real_y) = corner.transform_to_real_pos();
+            real_x < 0
+                || real_x >= COL_COUNT as i32
+                || real_y >= ROW_COUNT as i32
+                || board_blocks.contains(&corner)
+        })
+        .count();
+    occupied_corners >= 3
+}
+
 // 检查是否有成功的行
+//
+// 满行的方块先标记为正在消除，等消行动画结束后由 collapse_cleared_lines 销毁，分数和消行结果立即结算
 pub fn check_full_line(
     mut commands: Commands,
-    game_audios: Res<GameAudios>,
-    mut score: ResMut<Score>,
-    mut query: Query<(Entity, &mut Block, &mut Style), Without<PieceType>>,
+    config: Res<GameConfig>,
+    rules: Res<RuleSet>,
+    mut main_board: Query<
+        (
+            Entity,
+            &Level,
+            &mut Score,
+            &mut Lines,
+            &mut SessionStats,
+            &mut ScoringState,
+        ),
+        (With<MainBoard>, Without<ClearingLines>),
+    >,
+    mut piece_locked_events: EventReader<PieceLocked>,
+    mut lines_cleared_events: EventWriter<LinesCleared>,
+    query: Query<(Entity, &Parent, &Block), Without<PieceType>>,
 ) {
-    let mut y_to_x_set_map: HashMap<i32, HashSet<i32>> = HashMap::new();
-    for (_, block, _) in &query {
-        if y_to_x_set_map.contains_key(&block.y) {
-            let x_set = y_to_x_set_map.get_mut(&block.y).unwrap();
-            x_set.insert(block.x);
-        } else {
-            let mut x_set = HashSet::new();
-            x_set.insert(block.x);
-            y_to_x_set_map.insert(block.y, x_set);
+    let locked_pieces: Vec<PieceLocked> = piece_locked_events.read().cloned().collect();
+    for (main_board_entity, level, mut score, mut lines, mut stats, mut scoring_state) in
+        &mut main_board
+    {
+        let mut y_to_x_set_map: HashMap<i32, HashSet<i32>> = HashMap::new();
+        let mut block_count = 0;
+        for (_, parent, block) in &query {
+            if parent.get() != main_board_entity {
+                continue;
+            }
+            block_count += 1;
+            y_to_x_set_map.entry(block.y).or_default().insert(block.x);
         }
-    }
-    let mut full_lines = Vec::new();
-    for (y, x_set) in y_to_x_set_map.iter() {
-        if x_set.len() == COL_COUNT as usize {
-            full_lines.push(y);
+        let mut full_lines = Vec::new();
+        for (y, x_set) in y_to_x_set_map.iter() {
+            if x_set.len() == COL_COUNT as usize {
+                full_lines.push(*y);
+            }
         }
-    }
-    if full_lines.len() > 0 {
-        dbg!(full_lines.len());
-        commands.spawn(AudioBundle {
-            source: game_audios.line_clear.clone(),
-            ..default()
-        });
-    }
-    // 分数增加
-    score.0 += match full_lines.len() {
-        0 => 0,
-        1 => 100,
-        2 => 200,
-        3 => 400,
-        4 => 800,
-        _ => 1000,
-    };
-
-    // 消除行
-    let mut despawn_entities = Vec::new();
-    for line_no in full_lines.iter() {
-        let line_no = **line_no;
-        for (entity, block, _) in &mut query {
-            if block.y == line_no {
-                despawn_entities.push(entity);
-                commands.entity(entity).despawn();
+        // 只有骨牌固定的那一帧才结算连击、T-spin 和对战攻击
+        let locked = locked_pieces
+            .iter()
+            .rev()
+            .find(|locked| locked.board == main_board_entity);
+        if let Some(locked) = locked {
+            let result = ClearResult {
+                lines: full_lines.len() as u32,
+                t_spin: locked.t_spin,
+                perfect_clear: !full_lines.is_empty()
+                    && block_count == full_lines.len() * COL_COUNT as usize,
+            };
+            let back_to_back = scoring_state.back_to_back;
+            let full_score = full_clear_score(result, level.0, &mut scoring_state);
+            if config.mode.uses_full_scoring() {
+                score.0 += full_score;
             }
+            lines_cleared_events.send(LinesCleared {
+                board: main_board_entity,
+                lines: result.lines,
+                t_spin: result.t_spin,
+                perfect_clear: result.perfect_clear,
+                attack: attack_lines(result, back_to_back, scoring_state.combo),
+            });
         }
-    }
-    // 消除行的上面block整体向下移
-    full_lines.sort();
-    for line_no in full_lines.iter() {
-        println!("line_no: {}", line_no);
-        for (entity, mut block, mut style) in &mut query {
-            if !despawn_entities.contains(&entity) && block.y < **line_no {
-                info!("down block: {:?}, line_no: {}", block, line_no);
-                block.y += 1;
-                let cur = block.transform_to();
-                style.top = Val::Px(cur.y);
-                style.left = Val::Px(cur.x);
+        // 分数增加
+        if !config.mode.uses_full_scoring() {
+            score.0 += classic_clear_score(full_lines.len() as u32);
+        }
+        lines.0 += full_lines.len() as u32;
+        if full_lines.len() == 4 {
+            stats.tetrises += 1;
+        }
+
+        if full_lines.is_empty() {
+            continue;
+        }
+        // 标记消除的行，开始消行动画
+        for (entity, parent, block) in &query {
+            if parent.get() == main_board_entity && full_lines.contains(&block.y) {
+                commands.entity(entity).insert(ClearingBlock);
             }
         }
+        commands
+            .entity(main_board_entity)
+            .insert(ClearingLines(Timer::new(rules.line_clear_delay(), TimerMode::Once)));
     }
 }
 
-pub fn rotate_piece(
-    keyboard_input: Res<ButtonInput<KeyCode>>,
-    mut q_piece: Query<(&mut PieceType, &mut Block, &mut Style)>,
-    q_board: Query<&Block, Without<PieceType>>,
+// 消行动画结束后销毁消除的行，上面的方块整体下移
+pub fn collapse_cleared_lines(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut main_board: Query<(Entity, &mut ClearingLines), With<MainBoard>>,
+    clearing_blocks: Query<(Entity, &Parent, &Block), With<ClearingBlock>>,
+    mut query: Query<
+        (&Parent, &mut Block),
+        (Without<PieceType>, Without<ClearingBlock>),
+    >,
 ) {
-    if keyboard_input.just_pressed(KeyCode::ArrowUp) {
-        let piece_type = match q_piece.iter().next() {
-            Some((piece_type, _, _)) => piece_type.clone(),
-            None => {
-                return;
-            }
-        };
-        let sum_x = q_piece.iter().map(|(_, block, _)| block.x).sum::<i32>();
-        let sum_y = q_piece.iter().map(|(_, block, _)| block.y).sum::<i32>();
-
-        let original_blocks: Vec<Block> =
-            q_piece.iter().map(|(_, block, _)| block.clone()).collect();
-        // 通过矩阵变化实现旋转，可以理解为沿y=x对称后沿y=0对称，然后平移
-        for (_, mut block, mut style) in &mut q_piece {
-            *block = match piece_type {
-                // 微调平移量，使其更自然
-                PieceType::O | PieceType::L | PieceType::J => shift_block(
-                    [block.y, -block.x].into(),
-                    Some(sum_x / 4 - sum_y / 4),
-                    Some(sum_x / 4 + sum_y / 4 + 1),
-                ),
-                _ => shift_block(
-                    [block.y, -block.x].into(),
-                    Some(sum_x / 4 - sum_y / 4),
-                    Some(sum_x / 4 + sum_y / 4),
-                ),
-            };
-            let cur = block.transform_to();
-            style.top = Val::Px(cur.y);
-            style.left = Val::Px(cur.x);
+    for (main_board_entity, mut clearing_lines) in &mut main_board {
+        clearing_lines.0.tick(time.delta());
+        if !clearing_lines.0.finished() {
+            continue;
         }
-
-
-        // 当出现碰撞时，尝试左右平移最多2格（也可采取旋转后一旦出现碰撞则恢复原样）
-        if whether_colliding(&q_piece, &q_board) {
-            for (_, mut block, mut style) in &mut q_piece {
-                *block = shift_block(block.clone(), Some(-1), None);
-                let cur = block.transform_to();
-                style.top = Val::Px(cur.y);
-                style.left = Val::Px(cur.x);
+        commands.entity(main_board_entity).remove::<ClearingLines>();
+        // 动画期间垃圾行可能已经上升，按消除的方块当前的位置计算
+        let mut full_lines: Vec<i32> = Vec::new();
+        for (entity, parent, block) in &clearing_blocks {
+            if parent.get() != main_board_entity {
+                continue;
             }
-        }
-        if whether_colliding(&q_piece, &q_board) {
-            for (_, mut block, mut style) in &mut q_piece {
-                *block = shift_block(block.clone(), Some(-1), None);
-                let cur = block.transform_to();
-                style.top = Val::Px(cur.y);
-                style.left = Val::Px(cur.x);
+            commands.entity(entity).despawn();
+            if !full_lines.contains(&block.y) {
+                full_lines.push(block.y);
             }
         }
-        if whether_colliding(&q_piece, &q_board) {
-            for (_, mut block, mut style) in &mut q_piece {
-                *block = shift_block(block.clone(), Some(3), None);
-                let cur = block.transform_to();
-                style.top = Val::Px(cur.y);
-                style.left = Val::Px(cur.x);
-            }
-        }
-        if whether_colliding(&q_piece, &q_board) {
-            for (_, mut block, mut style) in &mut q_piece {
-                *block = shift_block(block.clone(), Some(3), None);
-                let cur = block.transform_to();
-                style.top = Val::Px(cur.y);
-                style.left = Val::Px(cur.x);
-            }
-        }
-        // 恢复旋转前样子
-        if whether_colliding(&q_piece, &q_board) {
-            let mut index = 0;
-            for (_, mut block, mut style) in &mut q_piece {
-                *block = original_blocks[index];
-                let cur = block.transform_to();
-                style.top = Val::Px(cur.y);
-                style.left = Val::Px(cur.x);
-                index += 1;
+        // 消除行的上面block整体向下移
+        full_lines.sort();
+        for line_no in full_lines.iter() {
+            for (parent, mut block) in &mut query {
+                if parent.get() == main_board_entity && block.y < *line_no {
+                    block.y += 1;
+                }
             }
         }
     }
 }
 
-fn shift_block(mut block: Block, delta_x: Option<i32>, delta_y: Option<i32>) -> Block {
-    match delta_x {
-        Some(delta) => {
-            block.x += delta;
-        }
-        None => {}
-    }
-    match delta_y {
-        Some(delta) => {
-            block.y += delta;
-        }
-        None => {}
+// 消除的行先闪烁，再逐渐消失
+pub fn animate_line_clear(
+    main_board: Query<&ClearingLines, With<MainBoard>>,
+    mut clearing_blocks: Query<(&Parent, &mut Sprite), With<ClearingBlock>>,
+) {
+    for (parent, mut sprite) in &mut clearing_blocks {
+        let Ok(clearing_lines) = main_board.get(parent.get()) else {
+            continue;
+        };
+        let progress = clearing_lines.0.fraction();
+        let alpha = if progress < 0.5 {
+            if (progress * 8.0) as u32 & 1 == 0 {
+                1.0
+            } else {
+                0.3
+            }
+        } else {
+            (1.0 - progress) * 2.0
+        };
+        sprite.color = Color::WHITE.with_alpha(alpha);
     }
-    block
 }
 
-// 检测旋转过程中是否发送碰撞
-pub fn whether_colliding(
-    piece_query: &Query<(&mut PieceType, &mut Block, &mut Style)>,
-    board_query: &Query<&Block, Without<PieceType>>,
-) -> bool {
-    // 检查是否碰撞边界
-    for (_, block, _) in piece_query {
-        let cur_pos = block.transform_to_real_pos();
-        if cur_pos.0 == 0 {
-            // 碰撞左边界
-            return true;
-        }
-        if cur_pos.0 == COL_COUNT as i32 - 1 {
-            // 碰撞右边界
-            return true;
-        }
-        if cur_pos.1 == (ROW_COUNT as i32) - 1 {
-            // 碰撞下边界
-            return true;
+pub fn rotate_piece(
+    piece_set: Res<PieceSet>,
+    mut piece_moved_events: EventWriter<PieceMoved>,
+    mut q_piece: Query<(&Parent, &PieceType, &mut Block)>,
+    q_board: Query<(&Parent, &Block), Without<PieceType>>,
+    mut main_board: Query<
+        (Entity, &mut PlayerInput, &mut LastMoveIsRotation, &mut PieceRotation),
+        With<MainBoard>,
+    >,
+) {
+    for (main_board_entity, mut input, mut last_move_is_rotation, mut rotation) in &mut main_board {
+        if !input.rotate {
+            continue;
         }
-    }
+        input.rotate = false;
+        let Some(piece_type) = q_piece
+            .iter()
+            .find(|(parent, _, _)| parent.get() == main_board_entity)
+            .map(|(_, piece_type, _)| *piece_type)
+        else {
+            continue;
+        };
+        let original_blocks: Vec<Block> = q_piece
+            .iter()
+            .filter(|(parent, _, _)| parent.get() == main_board_entity)
+            .map(|(_, _, block)| *block)
+            .collect();
+        let board_blocks: Vec<Block> = q_board
+            .iter()
+            .filter(|(parent, _)| parent.get() == main_board_entity)
+            .map(|(_, block)| *block)
+            .collect();
+
+        let Some((rotated_blocks, next_rotation)) =
+            rotate_blocks(&piece_set, piece_type, &original_blocks, rotation.0, &board_blocks)
+        else {
+            continue;
+        };
+        last_move_is_rotation.0 = true;
+        rotation.0 = next_rotation;
+        piece_moved_events.send(PieceMoved {
+            board: main_board_entity,
+            kind: PieceMove::Rotate,
+        });
 
-    // 检查是否碰撞面板方块
-    for (_, block, _) in piece_query {
-        for board_block in board_query {
-            let board_y = board_block.y;
-            let board_x = board_block.x;
-            if board_y == block.y {
-                if board_x == block.x - 1 {
-                    // 左侧碰撞
-                    return true;
-                } else if board_x == block.x + 1 {
-                    // 右侧碰撞
-                    return true;
-                }
-            }
-            if board_x == block.x {
-                if block.y > 0 && board_y == block.y + 1 {
-                    // 下侧碰撞
-                    return true;
-                }
-                if board_y == 0 {
-                    // 底部碰撞
-                    return true;
-                }
+        let mut index = 0;
+        for (parent, _, mut block) in &mut q_piece {
+            if parent.get() != main_board_entity {
+                continue;
             }
+            *block = rotated_blocks[index];
+            index += 1;
         }
     }
-
-    return false;
 }
 
+// 计算顺时针旋转后的骨牌位置和旋转状态，按踢墙表依次尝试偏移，都会重叠时无法旋转
+pub fn rotate_blocks(
+    piece_set: &PieceSet,
+    piece_type: PieceType,
+    blocks: &[Block],
+    rotation: usize,
+    board_blocks: &[Block],
+) -> Option<(Vec<Block>, usize)> {
+    let piece = piece_set.get(piece_type)?;
+    let origin = piece.origin(blocks, rotation)?;
+    let next = (rotation + 1) % piece.rotations.len();
+    for (dx, dy) in piece_set.kicks(piece, rotation) {
+        let rotated = piece.cells_at(
+            next,
+            Block {
+                x: origin.x + dx,
+                y: origin.y + dy,
+            },
+        );
+        if overlaps_board(&rotated, board_blocks) {
+            continue;
+        }
+        // 形状和位置不变（如 O）时不算旋转
+        if rotated.iter().all(|block| blocks.contains(block)) {
+            return None;
+        }
+        return Some((rotated, next));
+    }
+    None
+}
 
-
+// 骨牌是否超出面板左右和底部，或与面板上的方块重叠
+pub fn overlaps_board(piece_blocks: &[Block], board_blocks: &[Block]) -> bool {
+    piece_blocks.iter().any(|block| {
+        let (x, y) = block.transform_to_real_pos();
+        x < 0 || x >= COL_COUNT as i32 || y >= ROW_COUNT as i32 || board_blocks.contains(block)
+    })
+}
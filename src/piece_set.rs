@@ -0,0 +1,334 @@
+use bevy::{
+    asset::{io::Reader, AssetLoader, AsyncReadExt, LoadContext},
+    prelude::*,
+};
+use rand::{seq::SliceRandom, Rng};
+use serde::Deserialize;
+
+use crate::{
+    asset_errors::{track_asset_errors, AssetErrors},
+    board::{Block, HasNextPiece, MainBoard},
+    common::AppState,
+    mode::GameConfig,
+    network::NetSession,
+    piece::{FixedPieceSequence, PieceConfig, PieceQueue, PieceRandomizer, PieceType},
+    state::BASE_OFFSET,
+};
+
+// 骨牌定义所在的资源目录，每个 .pieces.ron 文件是一套骨牌
+const PIECE_SET_DIR: &str = "pieces";
+
+// 内置的标准骨牌，资源加载完成前和无界面模拟时使用
+const STANDARD_PIECE_SET: &str = include_str!("../assets/pieces/standard.pieces.ron");
+
+// 从资源目录加载骨牌定义，在模式选择菜单中选择下一局使用的骨牌，文件修改后游戏中立即生效
+pub struct PieceSetPlugin;
+
+impl Plugin for PieceSetPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<PieceSet>()
+            .init_asset_loader::<PieceSetLoader>()
+            .init_resource::<PieceSets>()
+            .init_resource::<AssetErrors>()
+            .add_systems(Startup, load_piece_sets)
+            .add_systems(
+                Update,
+                (
+                    track_asset_errors::<PieceSet>,
+                    update_piece_sets,
+                    select_piece_set.run_if(
+                        resource_changed::<GameConfig>.or_else(resource_changed::<PieceSets>),
+                    ),
+                    refresh_piece_queues
+                        .run_if(in_state(AppState::InGame).and_then(resource_changed::<PieceSet>)),
+                )
+                    .chain(),
+            );
+    }
+}
+
+// 一套骨牌。作为资源时是当前游戏使用的骨牌
+#[derive(Asset, Resource, TypePath, Debug, Clone, PartialEq, Deserialize)]
+pub struct PieceSet {
+    pub name: String,
+    // 旋转状态 0 的原点在面板上的位置，面板左上角为 (0, 0)
+    pub spawn: (i32, i32),
+    // kicks[r] 为从旋转状态 r 顺时针旋转时依次尝试的偏移
+    pub kicks: Vec<Vec<(i32, i32)>>,
+    pub pieces: Vec<PieceDef>,
+}
+
+// 一种骨牌的定义，坐标中 y 向下增大
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct PieceDef {
+    // 骨牌的标识，网络消息和谜题文件中使用
+    pub letter: char,
+    // sRGB 颜色
+    pub color: (f32, f32, f32),
+    // 按顺时针顺序列出每个旋转状态的方块
+    pub rotations: Vec<Vec<(i32, i32)>>,
+    // 覆盖整套骨牌的生成位置
+    #[serde(default)]
+    pub spawn: Option<(i32, i32)>,
+    // 覆盖整套骨牌的踢墙表
+    #[serde(default)]
+    pub kicks: Option<Vec<Vec<(i32, i32)>>>,
+    // 旋转入位时按 T-spin 规则判断
+    #[serde(default)]
+    pub spin: bool,
+}
+
+impl Default for PieceSet {
+    fn default() -> Self {
+        PieceSet::parse(STANDARD_PIECE_SET).expect("built-in piece set is valid")
+    }
+}
+
+impl PieceSet {
+    pub fn parse(content: &str) -> Result<Self, String> {
+        let piece_set: PieceSet = ron::from_str(content).map_err(|err| err.to_string())?;
+        piece_set.validate()?;
+        Ok(piece_set)
+    }
+
+    // 检查骨牌能否使用：字母不能重复，每个旋转状态的方块数相同
+    pub fn validate(&self) -> Result<(), String> {
+        if self.pieces.is_empty() {
+            return Err("piece set has no pieces".to_string());
+        }
+        for (index, piece) in self.pieces.iter().enumerate() {
+            if PieceType::from_letter(piece.letter).is_none() {
+                return Err(format!("invalid piece letter: {}", piece.letter));
+            }
+            if self.pieces[..index].iter().any(|other| other.letter == piece.letter) {
+                return Err(format!("duplicate piece: {}", piece.letter));
+            }
+            let cell_count = piece.rotations.first().map_or(0, Vec::len);
+            if cell_count == 0 || piece.rotations.iter().any(|cells| cells.len() != cell_count) {
+                return Err(format!(
+                    "piece {} needs rotation states with the same number of cells",
+                    piece.letter
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn get(&self, piece_type: PieceType) -> Option<&PieceDef> {
+        self.pieces.iter().find(|piece| piece.letter == piece_type.letter())
+    }
+
+    pub fn piece_types(&self) -> impl Iterator<Item = PieceType> + '_ {
+        self.pieces.iter().map(|piece| PieceType(piece.letter))
+    }
+
+    // 在生成位置的骨牌，不在这套骨牌中时返回 None
+    pub fn config(&self, piece_type: PieceType) -> Option<PieceConfig> {
+        let piece = self.get(piece_type)?;
+        let (spawn_x, spawn_y) = piece.spawn.unwrap_or(self.spawn);
+        let origin = Block {
+            x: spawn_x - BASE_OFFSET.0,
+            y: spawn_y - BASE_OFFSET.1,
+        };
+        Some(PieceConfig {
+            piece_type,
+            blocks: piece.cells_at(0, origin),
+            color: piece.color(),
+        })
+    }
+
+    // bag 随机：每次填充每种骨牌各一个，随机排序
+    pub fn random_bag(&self, rng: &mut impl Rng) -> Vec<PieceConfig> {
+        let mut piece_types: Vec<PieceType> = self.piece_types().collect();
+        piece_types.shuffle(rng);
+        piece_types
+            .into_iter()
+            .filter_map(|piece_type| self.config(piece_type))
+            .collect()
+    }
+
+    // 从旋转状态 rotation 顺时针旋转时依次尝试的偏移
+    pub fn kicks<'a>(&'a self, piece: &'a PieceDef, rotation: usize) -> &'a [(i32, i32)] {
+        let kicks = piece.kicks.as_ref().unwrap_or(&self.kicks);
+        match kicks.len() {
+            0 => &[(0, 0)],
+            len => &kicks[rotation % len],
+        }
+    }
+}
+
+impl PieceDef {
+    pub fn color(&self) -> Color {
+        Color::srgb(self.color.0, self.color.1, self.color.2)
+    }
+
+    // 原点在 origin 时旋转状态 rotation 的方块
+    pub fn cells_at(&self, rotation: usize, origin: Block) -> Vec<Block> {
+        self.rotations[rotation]
+            .iter()
+            .map(|(x, y)| Block {
+                x: origin.x + x,
+                y: origin.y + y,
+            })
+            .collect()
+    }
+
+    // 下一个骨牌面板中显示的形状，旋转状态 0 平移到左上角
+    pub fn preview(&self) -> Vec<Block> {
+        let cells = &self.rotations[0];
+        let min_x = cells.iter().map(|(x, _)| *x).min().unwrap_or(0);
+        let min_y = cells.iter().map(|(_, y)| *y).min().unwrap_or(0);
+        self.cells_at(0, Block { x: -min_x, y: -min_y })
+    }
+
+    // 由旋转状态 rotation 的方块位置推算原点
+    pub fn origin(&self, blocks: &[Block], rotation: usize) -> Option<Block> {
+        let cells = self.rotations.get(rotation)?;
+        Some(Block {
+            x: blocks.iter().map(|block| block.x).min()? - cells.iter().map(|(x, _)| *x).min()?,
+            y: blocks.iter().map(|block| block.y).min()? - cells.iter().map(|(_, y)| *y).min()?,
+        })
+    }
+}
+
+// 加载 .pieces.ron 文件
+#[derive(Default)]
+pub struct PieceSetLoader;
+
+impl AssetLoader for PieceSetLoader {
+    type Asset = PieceSet;
+    type Settings = ();
+    type Error = Box<dyn std::error::Error + Send + Sync>;
+
+    async fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader<'_>,
+        _settings: &'a (),
+        _load_context: &'a mut LoadContext<'_>,
+    ) -> Result<PieceSet, Self::Error> {
+        let mut content = String::new();
+        reader.read_to_string(&mut content).await?;
+        Ok(PieceSet::parse(&content)?)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["pieces.ron"]
+    }
+}
+
+// 所有可选的骨牌，第一个是标准骨牌
+#[derive(Debug, Resource)]
+pub struct PieceSets(pub Vec<PieceSet>);
+
+impl Default for PieceSets {
+    fn default() -> Self {
+        Self(vec![PieceSet::default()])
+    }
+}
+
+impl PieceSets {
+    // 在骨牌列表中循环选择
+    pub fn cycle(&self, name: &str, offset: i32) -> &PieceSet {
+        let index = self.0.iter().position(|set| set.name == name).unwrap_or(0) as i32;
+        &self.0[(index + offset).rem_euclid(self.0.len() as i32) as usize]
+    }
+}
+
+// 加载的骨牌文件的句柄，保持句柄才不会被卸载
+#[derive(Debug, Resource)]
+pub struct PieceSetHandles(pub Vec<Handle<PieceSet>>);
+
+pub fn load_piece_sets(mut commands: Commands, asset_server: Res<AssetServer>) {
+    #[cfg(not(target_arch = "wasm32"))]
+    let paths = piece_set_paths();
+    // 网页版无法读取目录，只加载标准骨牌
+    #[cfg(target_arch = "wasm32")]
+    let paths = vec![format!("{}/standard.pieces.ron", PIECE_SET_DIR)];
+
+    let handles = paths.into_iter().map(|path| asset_server.load(path)).collect();
+    commands.insert_resource(PieceSetHandles(handles));
+}
+
+// 骨牌目录下的所有骨牌文件。逐个加载而不是加载整个目录，一个文件出错时不影响其他文件
+#[cfg(not(target_arch = "wasm32"))]
+fn piece_set_paths() -> Vec<String> {
+    let Ok(entries) = std::fs::read_dir(format!("assets/{}", PIECE_SET_DIR)) else {
+        return Vec::new();
+    };
+    let mut paths: Vec<String> = entries
+        .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+        .filter(|name| name.ends_with(".pieces.ron"))
+        .map(|name| format!("{}/{}", PIECE_SET_DIR, name))
+        .collect();
+    paths.sort();
+    paths
+}
+
+// 骨牌文件加载完成或修改后重新生成骨牌列表，同名的骨牌代替内置的骨牌
+pub fn update_piece_sets(
+    mut asset_events: EventReader<AssetEvent<PieceSet>>,
+    handles: Option<Res<PieceSetHandles>>,
+    assets: Res<Assets<PieceSet>>,
+    mut piece_sets: ResMut<PieceSets>,
+) {
+    if asset_events.is_empty() {
+        return;
+    }
+    asset_events.clear();
+    let mut sets = vec![PieceSet::default()];
+    for piece_set in handles.iter().flat_map(|handles| handles.0.iter()).filter_map(|handle| assets.get(handle)) {
+        match sets.iter_mut().find(|set| set.name == piece_set.name) {
+            Some(set) => *set = piece_set.clone(),
+            None => sets.push(piece_set.clone()),
+        }
+    }
+    sets[1..].sort_by(|a, b| a.name.cmp(&b.name));
+    piece_sets.0 = sets;
+}
+
+// 使用菜单中选择的骨牌；网络对战双方都使用内置的标准骨牌
+//
+// 游戏进行中不更换骨牌，只接受当前骨牌定义的修改
+pub fn select_piece_set(
+    config: Res<GameConfig>,
+    piece_sets: Res<PieceSets>,
+    session: Option<Res<NetSession>>,
+    app_state: Res<State<AppState>>,
+    mut piece_set: ResMut<PieceSet>,
+) {
+    let selected = match session {
+        Some(_) => PieceSet::default(),
+        None => piece_sets.cycle(&config.piece_set, 0).clone(),
+    };
+    let in_game = matches!(app_state.get(), AppState::InGame | AppState::Paused);
+    if *piece_set != selected && (!in_game || piece_set.name == selected.name) {
+        *piece_set = selected;
+    }
+}
+
+// 骨牌定义修改后按新的定义重新生成队列中的骨牌，并刷新下一个骨牌的显示
+pub fn refresh_piece_queues(
+    piece_set: Res<PieceSet>,
+    mut main_board: Query<
+        (
+            &mut PieceQueue,
+            &mut PieceRandomizer,
+            &mut HasNextPiece,
+            Has<FixedPieceSequence>,
+        ),
+        With<MainBoard>,
+    >,
+) {
+    for (mut piece_queue, mut randomizer, mut has_next_piece, fixed_sequence) in &mut main_board {
+        // 从定义中删除的骨牌也从队列中删除
+        piece_queue.0 = piece_queue
+            .0
+            .iter()
+            .filter_map(|piece| piece_set.config(piece.piece_type))
+            .collect();
+        if !fixed_sequence {
+            piece_queue.refill(&piece_set, &mut randomizer);
+        }
+        has_next_piece.0 = false;
+    }
+}
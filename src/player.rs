@@ -0,0 +1,182 @@
+use bevy::prelude::*;
+use rand::{rngs::StdRng, SeedableRng};
+
+use crate::{
+    board::HasNextPiece,
+    finesse::FinesseTracker,
+    garbage::{GarbageGenerator, GarbageRiseTimer},
+    mode::{GameConfig, SURVIVAL_START_INTERVAL},
+    piece::{
+        AutoMovePieceDownTimer, HardDropped, LastMoveIsRotation, ManuallyMoveTimer, PieceQueue,
+        PieceRandomizer, PieceRotation, RemovePieceComponentTimer,
+    },
+    piece_set::PieceSet,
+    rule_set::RuleSet,
+    scoring::ScoringState,
+    state::{Level, Lines, Score, SessionStats},
+    versus::IncomingGarbage,
+};
+
+// 玩家编号，从 0 开始。游戏面板、下一个骨牌面板和计分板都带有该组件，用来区分属于哪个玩家
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Player(pub usize);
+
+impl Player {
+    pub fn name(&self) -> String {
+        format!("Player {}", self.0 + 1)
+    }
+}
+
+// 本局使用的随机种子，所有玩家的骨牌序列和垃圾行都由它生成，保证双方拿到相同的骨牌
+#[derive(Debug, Clone, Copy, Resource)]
+pub struct GameSeed(pub u64);
+
+// 玩家的按键
+#[derive(Component, Debug, Clone)]
+pub struct InputMap {
+    pub left: KeyCode,
+    pub right: KeyCode,
+    pub down: KeyCode,
+    pub rotate: KeyCode,
+    pub hold: KeyCode,
+    pub hard_drop: KeyCode,
+}
+
+impl InputMap {
+    pub fn arrows() -> Self {
+        Self {
+            left: KeyCode::ArrowLeft,
+            right: KeyCode::ArrowRight,
+            down: KeyCode::ArrowDown,
+            rotate: KeyCode::ArrowUp,
+            hold: KeyCode::ShiftRight,
+            hard_drop: KeyCode::Space,
+        }
+    }
+
+    pub fn wasd() -> Self {
+        Self {
+            left: KeyCode::KeyA,
+            right: KeyCode::KeyD,
+            down: KeyCode::KeyS,
+            rotate: KeyCode::KeyW,
+            hold: KeyCode::ShiftLeft,
+            hard_drop: KeyCode::KeyQ,
+        }
+    }
+
+    // 单人游戏使用方向键；双人对战时左边的玩家使用 WASD，右边的玩家使用方向键
+    pub fn for_player(player: Player, player_count: usize) -> Self {
+        if player_count > 1 && player.0 == 0 {
+            Self::wasd()
+        } else {
+            Self::arrows()
+        }
+    }
+}
+
+// 玩家当前的操作。本地玩家由键盘更新，网络对战时对手的面板由收到的消息更新
+#[derive(Component, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PlayerInput {
+    pub left: bool,
+    pub right: bool,
+    pub down: bool,
+    // 旋转只在按下的那一次生效，旋转后清除
+    pub rotate: bool,
+    // 暂存只在按下的那一次生效，暂存后清除
+    pub hold: bool,
+    // 直接落下只在按下的那一次生效，落下后清除
+    pub hard_drop: bool,
+}
+
+// 按本地玩家的按键更新操作
+pub fn read_keyboard_input(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    mut query: Query<(&InputMap, &mut PlayerInput)>,
+) {
+    for (input_map, mut input) in &mut query {
+        input.left = keyboard_input.pressed(input_map.left);
+        input.right = keyboard_input.pressed(input_map.right);
+        input.down = keyboard_input.pressed(input_map.down);
+        if keyboard_input.just_pressed(input_map.rotate) {
+            input.rotate = true;
+        }
+        if keyboard_input.just_pressed(input_map.hold) {
+            input.hold = true;
+        }
+        if keyboard_input.just_pressed(input_map.hard_drop) {
+            input.hard_drop = true;
+        }
+    }
+}
+
+// 垃圾行随机数与骨牌随机数使用不同的种子，避免两者序列相关
+const GARBAGE_SEED_SALT: u64 = 0x9e37_79b9_7f4a_7c15;
+
+// 每个玩家的游戏状态，挂在该玩家的 MainBoard 实体上
+#[derive(Bundle)]
+pub struct PlayerBundle {
+    pub player: Player,
+    pub input_map: InputMap,
+    pub input: PlayerInput,
+    pub piece_queue: PieceQueue,
+    pub randomizer: PieceRandomizer,
+    pub has_next_piece: HasNextPiece,
+    pub score: Score,
+    pub lines: Lines,
+    pub level: Level,
+    pub stats: SessionStats,
+    pub scoring_state: ScoringState,
+    pub auto_move_timer: AutoMovePieceDownTimer,
+    pub manually_move_timer: ManuallyMoveTimer,
+    pub remove_piece_timer: RemovePieceComponentTimer,
+    pub last_move_is_rotation: LastMoveIsRotation,
+    pub rotation: PieceRotation,
+    pub hard_dropped: HardDropped,
+    pub garbage_generator: GarbageGenerator,
+    pub garbage_rise_timer: GarbageRiseTimer,
+    pub incoming_garbage: IncomingGarbage,
+    pub finesse_tracker: FinesseTracker,
+}
+
+impl PlayerBundle {
+    pub fn new(
+        player: Player,
+        player_count: usize,
+        config: &GameConfig,
+        piece_set: &PieceSet,
+        rules: &RuleSet,
+        seed: GameSeed,
+    ) -> Self {
+        let mut randomizer = PieceRandomizer(StdRng::seed_from_u64(seed.0));
+        Self {
+            player,
+            input_map: InputMap::for_player(player, player_count),
+            input: PlayerInput::default(),
+            piece_queue: PieceQueue::new(piece_set, &mut randomizer),
+            randomizer,
+            has_next_piece: HasNextPiece(false),
+            score: Score(0),
+            lines: Lines(0),
+            level: Level(config.start_level),
+            stats: SessionStats::default(),
+            scoring_state: ScoringState::default(),
+            auto_move_timer: AutoMovePieceDownTimer::new(rules.gravity_interval(config.start_level)),
+            manually_move_timer: ManuallyMoveTimer::new(rules.move_interval()),
+            remove_piece_timer: RemovePieceComponentTimer::new(rules.lock_delay()),
+            last_move_is_rotation: LastMoveIsRotation(false),
+            rotation: PieceRotation(0),
+            hard_dropped: HardDropped(false),
+            garbage_generator: GarbageGenerator::new(
+                config.garbage_messiness,
+                seed.0 ^ GARBAGE_SEED_SALT,
+            ),
+            garbage_rise_timer: GarbageRiseTimer(Timer::new(
+                SURVIVAL_START_INTERVAL,
+                TimerMode::Once,
+            )),
+            incoming_garbage: IncomingGarbage(0),
+            finesse_tracker: FinesseTracker::default(),
+        }
+    }
+}
@@ -0,0 +1,629 @@
+use bevy::{
+    asset::{io::Reader, AssetLoader, AsyncReadExt, LoadContext},
+    prelude::*,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    asset_errors::{track_asset_errors, AssetErrors},
+    board::{Block, MainBoard, COL_COUNT, ROW_COUNT},
+    common::{AppState, MenuScreen},
+    editor::PlaytestPuzzle,
+    finesse::FinesseTracker,
+    garbage::GARBAGE_COLOR,
+    menu::{spawn_menu_button, spawn_menu_panel, spawn_menu_text, spawn_menu_title, MenuButton},
+    mode::{GameConfig, GameMode},
+    piece::{FixedPieceSequence, LinesCleared, PieceLocked, PieceQueue, PieceType},
+    piece_set::PieceSet,
+    player::PlayerInput,
+    state::{BASE_OFFSET, format_duration, spawn_board_block, SessionStats},
+    theme::BlockSkin,
+};
+
+// 谜题所在的资源目录，每个 .puzzle.ron 文件是一个谜题，按文件名排序
+const PUZZLE_DIR: &str = "puzzles";
+
+// 网页版无法读取目录，只加载这些内置的谜题
+#[cfg(target_arch = "wasm32")]
+const BUILTIN_PUZZLES: [&str; 3] = [
+    "01_tetris.puzzle.ron",
+    "02_perfect_clear.puzzle.ron",
+    "03_two_wells.puzzle.ron",
+];
+
+// 从资源目录加载谜题，在主菜单的 Puzzles 中选择
+pub struct PuzzlePlugin;
+
+impl Plugin for PuzzlePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<Puzzle>()
+            .init_asset_loader::<PuzzleLoader>()
+            .init_resource::<Puzzles>()
+            .init_resource::<AssetErrors>()
+            .add_systems(Startup, load_puzzles)
+            .add_systems(Update, (track_asset_errors::<Puzzle>, update_puzzles).chain());
+    }
+}
+
+// 谜题目标
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PuzzleGoal {
+    // 累计消除指定行数
+    Lines(u32),
+    // 一次 T-spin 消除指定行数，1 到 3 分别为 Single、Double、Triple
+    TSpin(u32),
+    // 全消
+    PerfectClear,
+    // 练习局面：没有目标，骨牌序列用完后继续随机生成，直到溢出
+    Practice,
+}
+
+impl PuzzleGoal {
+    // 编辑器中可以选择的目标
+    pub const ALL: [PuzzleGoal; 9] = [
+        PuzzleGoal::Lines(1),
+        PuzzleGoal::Lines(2),
+        PuzzleGoal::Lines(3),
+        PuzzleGoal::Lines(4),
+        PuzzleGoal::TSpin(1),
+        PuzzleGoal::TSpin(2),
+        PuzzleGoal::TSpin(3),
+        PuzzleGoal::PerfectClear,
+        PuzzleGoal::Practice,
+    ];
+
+    // 在 ALL 中前后切换，offset 为 1 或 -1
+    pub fn cycle(&self, offset: isize) -> Self {
+        let count = Self::ALL.len() as isize;
+        let index = Self::ALL.iter().position(|goal| goal == self).unwrap_or(0) as isize;
+        Self::ALL[(index + offset).rem_euclid(count) as usize]
+    }
+
+    pub fn description(&self) -> String {
+        match self {
+            PuzzleGoal::Lines(1) => "Clear 1 line".to_string(),
+            PuzzleGoal::Lines(lines) => format!("Clear {} lines", lines),
+            PuzzleGoal::TSpin(1) => "T-spin Single".to_string(),
+            PuzzleGoal::TSpin(2) => "T-spin Double".to_string(),
+            PuzzleGoal::TSpin(_) => "T-spin Triple".to_string(),
+            PuzzleGoal::PerfectClear => "Perfect Clear".to_string(),
+            PuzzleGoal::Practice => "Practice".to_string(),
+        }
+    }
+
+    // 一次消行后是否完成目标，total_lines 为包括这次在内累计消除的行数
+    fn is_met(&self, event: &LinesCleared, total_lines: u32) -> bool {
+        match self {
+            PuzzleGoal::Lines(lines) => total_lines >= *lines,
+            PuzzleGoal::TSpin(lines) => event.t_spin && event.lines == *lines,
+            PuzzleGoal::PerfectClear => event.perfect_clear,
+            PuzzleGoal::Practice => false,
+        }
+    }
+}
+
+// 谜题：初始面板、固定的骨牌序列、可选的暂存骨牌和目标
+#[derive(Asset, TypePath, Debug, Clone, PartialEq)]
+pub struct Puzzle {
+    pub name: String,
+    pub goal: PuzzleGoal,
+    pub pieces: Vec<PieceType>,
+    pub hold: Option<PieceType>,
+    // 初始方块的真实坐标和颜色所属的骨牌，None 为灰色的垃圾方块
+    pub cells: Vec<(i32, i32, Option<PieceType>)>,
+}
+
+// 谜题文件的内容，骨牌和面板用字母书写
+//
+// board 每行 10 个字符，. 为空，# 为垃圾方块，字母为该骨牌颜色的方块，最后一行是面板底部
+#[derive(Debug, Serialize, Deserialize)]
+struct PuzzleFile {
+    name: String,
+    goal: PuzzleGoal,
+    // 骨牌字母，可以用空格分隔
+    #[serde(default)]
+    pieces: String,
+    #[serde(default)]
+    hold: Option<char>,
+    #[serde(default)]
+    board: Vec<String>,
+}
+
+impl Puzzle {
+    pub fn parse(content: &str) -> Result<Self, String> {
+        let file: PuzzleFile = ron::from_str(content).map_err(|err| err.to_string())?;
+        if file.board.len() > ROW_COUNT as usize {
+            return Err(format!("board has more than {} rows", ROW_COUNT));
+        }
+        let mut cells = Vec::new();
+        // 面板底部对齐
+        let top = ROW_COUNT as usize - file.board.len();
+        for (index, row) in file.board.iter().enumerate() {
+            if row.chars().count() != COL_COUNT as usize {
+                return Err(format!("board row must have {} cells: {}", COL_COUNT, row));
+            }
+            for (x, cell) in row.chars().enumerate() {
+                let color = match cell {
+                    '.' => continue,
+                    '#' => None,
+                    letter => Some(
+                        PieceType::from_letter(letter)
+                            .ok_or_else(|| format!("invalid cell: {}", letter))?,
+                    ),
+                };
+                cells.push((x as i32, (top + index) as i32, color));
+            }
+        }
+        let puzzle = Puzzle {
+            name: file.name,
+            goal: file.goal,
+            pieces: file
+                .pieces
+                .chars()
+                .filter(|letter| !letter.is_whitespace())
+                .map(parse_piece)
+                .collect::<Result<_, _>>()?,
+            hold: file.hold.map(parse_piece).transpose()?,
+            cells,
+        };
+        puzzle.validate()?;
+        Ok(puzzle)
+    }
+
+    // 检查谜题能否开始：目标的行数有效，需要有骨牌可用，面板上不能有已经填满的行
+    pub fn validate(&self) -> Result<(), String> {
+        match self.goal {
+            PuzzleGoal::Lines(0) => return Err("goal needs at least 1 line".to_string()),
+            PuzzleGoal::TSpin(lines) if !(1..=3).contains(&lines) => {
+                return Err("T-spin goal needs 1 to 3 lines".to_string());
+            }
+            _ => {}
+        }
+        if self.pieces.is_empty() && self.hold.is_none() && self.goal != PuzzleGoal::Practice {
+            return Err("puzzle has no pieces".to_string());
+        }
+        for row in 0..ROW_COUNT as i32 {
+            if self.cells.iter().filter(|(_, y, _)| *y == row).count() >= COL_COUNT as usize {
+                return Err(format!("board row {} is full", row + 1));
+            }
+        }
+        Ok(())
+    }
+
+    // 按 parse 的格式输出，面板只输出最上面有方块的一行到底部
+    pub fn to_file_string(&self) -> Result<String, String> {
+        let top = self.cells.iter().map(|(_, y, _)| *y).min().unwrap_or(ROW_COUNT as i32);
+        let board = (top..ROW_COUNT as i32)
+            .map(|y| {
+                (0..COL_COUNT as i32)
+                    .map(|x| match self.cells.iter().find(|(cell_x, cell_y, _)| *cell_x == x && *cell_y == y) {
+                        Some((_, _, Some(piece_type))) => piece_type.letter(),
+                        Some((_, _, None)) => '#',
+                        None => '.',
+                    })
+                    .collect()
+            })
+            .collect();
+        let pieces: Vec<String> = self.pieces.iter().map(|piece_type| piece_type.letter().to_string()).collect();
+        let file = PuzzleFile {
+            name: self.name.clone(),
+            goal: self.goal,
+            pieces: pieces.join(" "),
+            hold: self.hold.map(|piece_type| piece_type.letter()),
+            board,
+        };
+        ron::ser::to_string_pretty(&file, ron::ser::PrettyConfig::default()).map_err(|err| err.to_string())
+    }
+}
+
+// 面板上初始方块的颜色，不在当前骨牌中的字母与垃圾方块颜色相同
+pub fn cell_color(piece_set: &PieceSet, piece_type: Option<PieceType>) -> Color {
+    piece_type
+        .and_then(|piece_type| piece_set.get(piece_type))
+        .map_or(GARBAGE_COLOR, |piece| piece.color())
+}
+
+fn parse_piece(letter: char) -> Result<PieceType, String> {
+    PieceType::from_letter(letter).ok_or_else(|| format!("invalid piece: {}", letter))
+}
+
+// 加载 .puzzle.ron 文件
+#[derive(Default)]
+pub struct PuzzleLoader;
+
+impl AssetLoader for PuzzleLoader {
+    type Asset = Puzzle;
+    type Settings = ();
+    type Error = Box<dyn std::error::Error + Send + Sync>;
+
+    async fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader<'_>,
+        _settings: &'a (),
+        _load_context: &'a mut LoadContext<'_>,
+    ) -> Result<Puzzle, Self::Error> {
+        let mut content = String::new();
+        reader.read_to_string(&mut content).await?;
+        Ok(Puzzle::parse(&content)?)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["puzzle.ron"]
+    }
+}
+
+// 所有加载成功的谜题，按文件名排序
+#[derive(Debug, Default, Resource)]
+pub struct Puzzles(pub Vec<Puzzle>);
+
+// 加载的谜题文件的句柄，保持句柄才不会被卸载
+#[derive(Debug, Default, Resource)]
+pub struct PuzzleHandles(pub Vec<Handle<Puzzle>>);
+
+// 谜题进行中的状态
+#[derive(Debug, Component)]
+pub struct PuzzleState {
+    pub goal: PuzzleGoal,
+    pub lines: u32,
+    pub solved: bool,
+}
+
+// 暂存的骨牌，每个骨牌固定前只能暂存一次
+#[derive(Debug, Default, Component)]
+pub struct HoldPiece {
+    pub piece: Option<PieceType>,
+    pub used: bool,
+}
+
+pub fn load_puzzles(mut commands: Commands, asset_server: Res<AssetServer>) {
+    #[cfg(not(target_arch = "wasm32"))]
+    let paths = puzzle_paths();
+    #[cfg(target_arch = "wasm32")]
+    let paths: Vec<String> = BUILTIN_PUZZLES
+        .iter()
+        .map(|name| format!("{}/{}", PUZZLE_DIR, name))
+        .collect();
+
+    let handles = paths.into_iter().map(|path| asset_server.load(path)).collect();
+    commands.insert_resource(PuzzleHandles(handles));
+}
+
+// 谜题目录下的所有谜题文件。逐个加载而不是加载整个目录，一个文件出错时不影响其他文件
+#[cfg(not(target_arch = "wasm32"))]
+fn puzzle_paths() -> Vec<String> {
+    let Ok(entries) = std::fs::read_dir(format!("assets/{}", PUZZLE_DIR)) else {
+        return Vec::new();
+    };
+    let mut paths: Vec<String> = entries
+        .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+        .filter(|name| name.ends_with(".puzzle.ron"))
+        .map(|name| format!("{}/{}", PUZZLE_DIR, name))
+        .collect();
+    paths.sort();
+    paths
+}
+
+// 谜题文件加载完成或修改后按文件顺序重新生成谜题列表
+pub fn update_puzzles(
+    mut asset_events: EventReader<AssetEvent<Puzzle>>,
+    handles: Option<Res<PuzzleHandles>>,
+    assets: Res<Assets<Puzzle>>,
+    mut puzzles: ResMut<Puzzles>,
+) {
+    if asset_events.is_empty() {
+        return;
+    }
+    asset_events.clear();
+    puzzles.0 = handles
+        .iter()
+        .flat_map(|handles| handles.0.iter())
+        .filter_map(|handle| assets.get(handle))
+        .cloned()
+        .collect();
+}
+
+// 保存到谜题目录，文件名 custom_N.puzzle.ron 和谜题名称 Custom N 使用第一个未被使用的序号，
+// 返回相对资源目录的文件路径
+#[cfg(not(target_arch = "wasm32"))]
+pub fn save_puzzle(puzzle: &mut Puzzle) -> Result<String, String> {
+    let dir = format!("assets/{}", PUZZLE_DIR);
+    std::fs::create_dir_all(&dir).map_err(|err| err.to_string())?;
+    let (index, name) = (1..)
+        .map(|index| (index, format!("custom_{}.puzzle.ron", index)))
+        .find(|(_, name)| !std::path::Path::new(&dir).join(name).exists())
+        .ok_or("no free file name")?;
+    puzzle.name = format!("Custom {}", index);
+    let content = puzzle.to_file_string()?;
+    std::fs::write(std::path::Path::new(&dir).join(&name), content).map_err(|err| err.to_string())?;
+    Ok(format!("{}/{}", PUZZLE_DIR, name))
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn save_puzzle(_puzzle: &mut Puzzle) -> Result<String, String> {
+    Err("saving is not supported on the web".to_string())
+}
+
+// 当前要开始的谜题：试玩编辑器中的局面，或者谜题选择界面中选择的谜题
+pub fn current_puzzle<'a>(
+    config: &GameConfig,
+    puzzles: Option<&'a Puzzles>,
+    playtest: Option<&'a PlaytestPuzzle>,
+) -> Option<&'a Puzzle> {
+    match playtest {
+        Some(playtest) => Some(&playtest.0),
+        None => puzzles.and_then(|puzzles| puzzles.0.get(config.puzzle)),
+    }
+}
+
+// 谜题模式开始时按谜题生成初始方块，并替换骨牌队列
+pub fn setup_puzzle(
+    mut commands: Commands,
+    config: Res<GameConfig>,
+    piece_set: Res<PieceSet>,
+    puzzles: Option<Res<Puzzles>>,
+    playtest: Option<Res<PlaytestPuzzle>>,
+    mut main_board: Query<(Entity, &mut PieceQueue), With<MainBoard>>,
+) {
+    if config.mode != GameMode::Puzzle {
+        return;
+    }
+    let Some(puzzle) = current_puzzle(&config, puzzles.as_deref(), playtest.as_deref()) else {
+        return;
+    };
+    for (main_board_entity, mut piece_queue) in &mut main_board {
+        piece_queue.0 = puzzle
+            .pieces
+            .iter()
+            .filter_map(|piece_type| piece_set.config(*piece_type))
+            .collect();
+        let mut entity_commands = commands.entity(main_board_entity);
+        entity_commands
+            .insert((
+                PuzzleState {
+                    goal: puzzle.goal,
+                    lines: 0,
+                    solved: false,
+                },
+                HoldPiece {
+                    piece: puzzle.hold,
+                    used: false,
+                },
+            ))
+            .with_children(|parent| {
+                for (x, y, piece_type) in puzzle.cells.iter() {
+                    let block = Block {
+                        x: x - BASE_OFFSET.0,
+                        y: y - BASE_OFFSET.1,
+                    };
+                    spawn_board_block(
+                        parent,
+                        block,
+                        cell_color(&piece_set, *piece_type),
+                        BlockSkin::cell(&piece_set, *piece_type),
+                    );
+                }
+            });
+        // 练习局面在骨牌序列用完后继续随机生成
+        if puzzle.goal != PuzzleGoal::Practice {
+            entity_commands.insert(FixedPieceSequence);
+        }
+    }
+}
+
+// 暂存当前骨牌，取出之前暂存的骨牌；没有暂存的骨牌时使用队列中的下一个
+pub fn hold_piece(
+    mut commands: Commands,
+    piece_set: Res<PieceSet>,
+    mut piece_locked_events: EventReader<PieceLocked>,
+    mut main_board: Query<
+        (
+            Entity,
+            &mut PlayerInput,
+            &mut HoldPiece,
+            &mut PieceQueue,
+            Option<&mut FinesseTracker>,
+        ),
+        With<MainBoard>,
+    >,
+    piece_blocks: Query<(Entity, &Parent, &PieceType)>,
+) {
+    // 骨牌固定后可以再次暂存
+    for event in piece_locked_events.read() {
+        if let Ok((_, _, mut hold, ..)) = main_board.get_mut(event.board) {
+            hold.used = false;
+        }
+    }
+    for (main_board_entity, mut input, mut hold, mut piece_queue, tracker) in &mut main_board {
+        if !std::mem::take(&mut input.hold) || hold.used {
+            continue;
+        }
+        let piece: Vec<(Entity, PieceType)> = piece_blocks
+            .iter()
+            .filter(|(_, parent, _)| parent.get() == main_board_entity)
+            .map(|(entity, _, piece_type)| (entity, *piece_type))
+            .collect();
+        let Some((_, piece_type)) = piece.first().copied() else {
+            continue;
+        };
+        for (entity, _) in piece.iter() {
+            commands.entity(*entity).despawn_recursive();
+        }
+        if let Some(held) = hold.piece.replace(piece_type).and_then(|held| piece_set.config(held)) {
+            piece_queue.0.push_front(held);
+        }
+        hold.used = true;
+        // 换出来的骨牌重新统计按键
+        if let Some(mut tracker) = tracker {
+            tracker.spawn = None;
+            tracker.presses = 0;
+        }
+    }
+}
+
+// 完成目标时胜利；骨牌用完仍未完成时失败
+pub fn check_puzzle_goal(
+    piece_set: Res<PieceSet>,
+    mut lines_cleared_events: EventReader<LinesCleared>,
+    mut main_board: Query<(Entity, &mut PuzzleState, &mut PieceQueue, &mut HoldPiece), With<MainBoard>>,
+    piece_blocks: Query<&Parent, With<PieceType>>,
+    mut app_state: ResMut<NextState<AppState>>,
+) {
+    for event in lines_cleared_events.read() {
+        let Ok((_, mut state, ..)) = main_board.get_mut(event.board) else {
+            continue;
+        };
+        state.lines += event.lines;
+        if state.goal.is_met(event, state.lines) {
+            state.solved = true;
+            app_state.set(AppState::Victory);
+        }
+    }
+    for (main_board_entity, state, mut piece_queue, mut hold) in &mut main_board {
+        if state.solved || state.goal == PuzzleGoal::Practice || !piece_queue.0.is_empty() {
+            continue;
+        }
+        if piece_blocks.iter().any(|parent| parent.get() == main_board_entity) {
+            continue;
+        }
+        // 队列用完时使用暂存的骨牌
+        match hold.piece.take().and_then(|held| piece_set.config(held)) {
+            Some(held) => piece_queue.0.push_back(held),
+            None => app_state.set(AppState::GameOver),
+        }
+    }
+}
+
+// 谜题模式 HUD 中显示的目标、暂存和剩余骨牌
+pub fn puzzle_status(state: &PuzzleState, hold: &HoldPiece, piece_queue: &PieceQueue) -> String {
+    let hold = hold
+        .piece
+        .map_or("-".to_string(), |piece_type| piece_type.letter().to_string());
+    let pieces: String = piece_queue
+        .0
+        .iter()
+        .map(|piece| piece.piece_type.letter())
+        .collect();
+    format!(
+        "目标: {}\n暂存: {}\n剩余: {}",
+        state.goal.description(),
+        hold,
+        if pieces.is_empty() { "-".to_string() } else { pieces }
+    )
+}
+
+pub fn show_puzzle_select_menu(mut commands: Commands, puzzles: Res<Puzzles>) {
+    spawn_menu_panel(&mut commands, MenuScreen::PuzzleSelect, |parent| {
+        spawn_menu_title(parent, "PUZZLES");
+        if puzzles.0.is_empty() {
+            spawn_menu_text(parent, &format!("No puzzles found in assets/{}", PUZZLE_DIR));
+        }
+        for (index, puzzle) in puzzles.0.iter().enumerate() {
+            spawn_menu_text(parent, &format!("{}. {} - {}", index + 1, puzzle.name, puzzle.goal.description()));
+            spawn_menu_button(parent, "Start", MenuButton::StartPuzzle(index));
+        }
+        spawn_menu_button(parent, "Back", MenuButton::MainMenu);
+    });
+}
+
+// 谜题的成功/失败界面，试玩编辑器中的局面时可以回到编辑器
+pub fn show_puzzle_result_menu(
+    mut commands: Commands,
+    app_state: Res<State<AppState>>,
+    config: Res<GameConfig>,
+    puzzles: Res<Puzzles>,
+    playtest: Option<Res<PlaytestPuzzle>>,
+    main_board: Query<&SessionStats, With<MainBoard>>,
+) {
+    if config.mode != GameMode::Puzzle {
+        return;
+    }
+    let state = app_state.get().clone();
+    let solved = state == AppState::Victory;
+    let puzzle = current_puzzle(&config, Some(&puzzles), playtest.as_deref());
+    let title = match puzzle.map(|puzzle| puzzle.goal) {
+        Some(PuzzleGoal::Practice) => "PRACTICE OVER",
+        _ if solved => "PUZZLE SOLVED",
+        _ => "PUZZLE FAILED",
+    };
+    spawn_menu_panel(&mut commands, state, |parent| {
+        spawn_menu_title(parent, title);
+        if let Some(puzzle) = puzzle {
+            spawn_menu_text(parent, &format!("{} - {}", puzzle.name, puzzle.goal.description()));
+        }
+        if let Ok(stats) = main_board.get_single() {
+            spawn_menu_text(parent, &format!("Time: {}", format_duration(stats.play_time)));
+            spawn_menu_text(parent, &format!("Pieces: {}", stats.pieces_placed));
+        }
+        if playtest.is_some() {
+            spawn_menu_button(parent, "Retry", MenuButton::Restart);
+            spawn_menu_button(parent, "Edit", MenuButton::Editor);
+            spawn_menu_button(parent, "Main Menu", MenuButton::MainMenu);
+            return;
+        }
+        if solved && config.puzzle + 1 < puzzles.0.len() {
+            spawn_menu_button(parent, "Next", MenuButton::StartPuzzle(config.puzzle + 1));
+        }
+        spawn_menu_button(parent, "Retry", MenuButton::Restart);
+        spawn_menu_button(parent, "Puzzles", MenuButton::Puzzles);
+        spawn_menu_button(parent, "Main Menu", MenuButton::MainMenu);
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BUILTIN_FILES: [&str; 3] = [
+        include_str!("../assets/puzzles/01_tetris.puzzle.ron"),
+        include_str!("../assets/puzzles/02_perfect_clear.puzzle.ron"),
+        include_str!("../assets/puzzles/03_two_wells.puzzle.ron"),
+    ];
+
+    #[test]
+    fn builtin_puzzles_round_trip() {
+        for content in BUILTIN_FILES {
+            let puzzle = Puzzle::parse(content).unwrap();
+            let saved = puzzle.to_file_string().unwrap();
+            assert_eq!(Puzzle::parse(&saved), Ok(puzzle), "{}", saved);
+        }
+    }
+
+    #[test]
+    fn edited_puzzle_round_trip() {
+        // 编辑器中的方块按绘制顺序保存，读回后按行排列
+        let mut puzzle = Puzzle {
+            name: "Custom 1".to_string(),
+            goal: PuzzleGoal::TSpin(2),
+            pieces: vec![PieceType('T'), PieceType('I')],
+            hold: Some(PieceType('O')),
+            cells: vec![(9, 19, Some(PieceType('L'))), (0, 19, None), (3, 17, None), (0, 18, Some(PieceType('S')))],
+        };
+        let saved = Puzzle::parse(&puzzle.to_file_string().unwrap()).unwrap();
+        puzzle.cells.sort_by_key(|(x, y, _)| (*y, *x));
+        assert_eq!(saved, puzzle);
+    }
+
+    #[test]
+    fn parse_fills_defaults_and_aligns_to_bottom() {
+        let puzzle = Puzzle::parse(r#"(name: "Empty", goal: Practice)"#).unwrap();
+        assert!(puzzle.pieces.is_empty() && puzzle.hold.is_none() && puzzle.cells.is_empty());
+
+        let puzzle = Puzzle::parse(r##"(name: "Row", goal: Lines(1), pieces: "I", board: ["#........T"])"##).unwrap();
+        assert_eq!(puzzle.cells, vec![(0, 19, None), (9, 19, Some(PieceType('T')))]);
+    }
+
+    #[test]
+    fn rejects_invalid_puzzles() {
+        for content in [
+            r#"(name: "No pieces", goal: Lines(1))"#,
+            r#"(name: "Zero", goal: Lines(0), pieces: "I")"#,
+            r#"(name: "Quad spin", goal: TSpin(4), pieces: "T")"#,
+            r##"(name: "Short row", goal: Lines(1), pieces: "I", board: ["#"])"##,
+            r##"(name: "Full row", goal: Lines(1), pieces: "I", board: ["T#########"])"##,
+            r##"(name: "Bad cell", goal: Lines(1), pieces: "I", board: ["?........."])"##,
+            r#"(name: "Bad hold", goal: Lines(1), hold: Some('.'))"#,
+        ] {
+            assert!(Puzzle::parse(content).is_err(), "{}", content);
+        }
+    }
+}
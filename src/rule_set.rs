@@ -0,0 +1,194 @@
+use std::time::Duration;
+
+use bevy::{
+    asset::{io::Reader, AssetLoader, AsyncReadExt, LoadContext},
+    prelude::*,
+};
+use serde::Deserialize;
+
+use crate::{
+    asset_errors::{track_asset_errors, AssetErrors},
+    board::MainBoard,
+    common::AppState,
+    piece::{AutoMovePieceDownTimer, ManuallyMoveTimer, RemovePieceComponentTimer},
+    state::Level,
+};
+
+// 规则数值文件
+const RULE_SET_PATH: &str = "rules/standard.rules.ron";
+
+// 内置的规则数值，资源加载完成前和无界面模拟时使用
+const STANDARD_RULE_SET: &str = include_str!("../assets/rules/standard.rules.ron");
+
+// 从资源文件加载规则数值，文件修改后游戏中立即生效
+pub struct RuleSetPlugin;
+
+impl Plugin for RuleSetPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<RuleSet>()
+            .init_asset_loader::<RuleSetLoader>()
+            .init_resource::<AssetErrors>()
+            .add_systems(Startup, load_rule_set)
+            .add_systems(
+                Update,
+                (
+                    track_asset_errors::<RuleSet>,
+                    update_rule_set,
+                    apply_rule_set
+                        .run_if(in_state(AppState::InGame).and_then(resource_changed::<RuleSet>)),
+                )
+                    .chain(),
+            );
+    }
+}
+
+// 规则数值。作为资源时是当前使用的数值
+#[derive(Asset, Resource, TypePath, Debug, Clone, PartialEq, Deserialize)]
+pub struct RuleSet {
+    // 每个等级的自动下落间隔（秒），从 1 级开始，更高的等级使用最后一个
+    pub gravity: Vec<f32>,
+    // 骨牌无法下移后到固定的时间（毫秒）
+    pub lock_delay_ms: u64,
+    // 按住方向键时每次移动的间隔（毫秒）
+    pub move_interval_ms: u64,
+    // 消行动画的时间（毫秒），期间不生成新的骨牌
+    pub line_clear_delay_ms: u64,
+}
+
+impl Default for RuleSet {
+    fn default() -> Self {
+        RuleSet::parse(STANDARD_RULE_SET).expect("built-in rule set is valid")
+    }
+}
+
+impl RuleSet {
+    pub fn parse(content: &str) -> Result<Self, String> {
+        let rule_set: RuleSet = ron::from_str(content).map_err(|err| err.to_string())?;
+        if rule_set.gravity.is_empty() {
+            return Err("gravity needs at least one level".to_string());
+        }
+        if let Some(seconds) = rule_set
+            .gravity
+            .iter()
+            .find(|seconds| !seconds.is_finite() || **seconds <= 0.0)
+        {
+            // 间隔为 0 时每帧都会下落，不允许
+            return Err(format!("gravity must be greater than 0: {}", seconds));
+        }
+        Ok(rule_set)
+    }
+
+    // 等级对应的自动下落间隔
+    pub fn gravity_interval(&self, level: u32) -> Duration {
+        let index = (level.max(1) as usize - 1).min(self.gravity.len() - 1);
+        Duration::from_secs_f32(self.gravity[index])
+    }
+
+    pub fn lock_delay(&self) -> Duration {
+        Duration::from_millis(self.lock_delay_ms)
+    }
+
+    pub fn move_interval(&self) -> Duration {
+        Duration::from_millis(self.move_interval_ms)
+    }
+
+    pub fn line_clear_delay(&self) -> Duration {
+        Duration::from_millis(self.line_clear_delay_ms)
+    }
+}
+
+// 加载 .rules.ron 文件
+#[derive(Default)]
+pub struct RuleSetLoader;
+
+impl AssetLoader for RuleSetLoader {
+    type Asset = RuleSet;
+    type Settings = ();
+    type Error = Box<dyn std::error::Error + Send + Sync>;
+
+    async fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader<'_>,
+        _settings: &'a (),
+        _load_context: &'a mut LoadContext<'_>,
+    ) -> Result<RuleSet, Self::Error> {
+        let mut content = String::new();
+        reader.read_to_string(&mut content).await?;
+        Ok(RuleSet::parse(&content)?)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["rules.ron"]
+    }
+}
+
+// 保持规则文件的句柄，否则加载的数值会被卸载
+#[derive(Debug, Resource)]
+pub struct RuleSetHandle(pub Handle<RuleSet>);
+
+pub fn load_rule_set(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(RuleSetHandle(asset_server.load(RULE_SET_PATH)));
+}
+
+// 规则文件加载完成或修改后替换当前使用的数值
+pub fn update_rule_set(
+    mut asset_events: EventReader<AssetEvent<RuleSet>>,
+    handle: Option<Res<RuleSetHandle>>,
+    assets: Res<Assets<RuleSet>>,
+    mut rule_set: ResMut<RuleSet>,
+) {
+    let Some(handle) = handle else {
+        return;
+    };
+    for event in asset_events.read() {
+        if !event.is_loaded_with_dependencies(&handle.0) && !event.is_modified(&handle.0) {
+            continue;
+        }
+        if let Some(loaded) = assets.get(&handle.0) {
+            if *rule_set != *loaded {
+                *rule_set = loaded.clone();
+            }
+        }
+    }
+}
+
+// 游戏进行中修改规则数值时更新各面板的计时器
+pub fn apply_rule_set(
+    rule_set: Res<RuleSet>,
+    mut main_board: Query<
+        (
+            &Level,
+            &mut AutoMovePieceDownTimer,
+            &mut ManuallyMoveTimer,
+            &mut RemovePieceComponentTimer,
+        ),
+        With<MainBoard>,
+    >,
+) {
+    for (level, mut auto_move_timer, mut manually_move_timer, mut remove_piece_timer) in
+        &mut main_board
+    {
+        auto_move_timer.0.set_duration(rule_set.gravity_interval(level.0));
+        manually_move_timer.0.set_duration(rule_set.move_interval());
+        remove_piece_timer.0.set_duration(rule_set.lock_delay());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_invalid_gravity() {
+        let rules = |gravity: &str| {
+            format!(
+                "(gravity: {}, lock_delay_ms: 500, move_interval_ms: 100, line_clear_delay_ms: 0)",
+                gravity
+            )
+        };
+        assert!(RuleSet::parse(&rules("[1.0, 0.5]")).is_ok());
+        for gravity in ["[]", "[1.0, 0.0]", "[-0.5]", "[inf]"] {
+            assert!(RuleSet::parse(&rules(gravity)).is_err(), "{}", gravity);
+        }
+    }
+}
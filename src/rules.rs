@@ -0,0 +1,112 @@
+use bevy::prelude::*;
+
+use crate::{
+    board::{check_game_overs, restart_game, setup_game_board, MainBoard},
+    common::{AppState, GameSettings, RestartGame},
+    finesse::{check_finesse, count_finesse_inputs},
+    garbage::{rise_garbage, spawn_initial_garbage},
+    mode::{check_mode_goal, update_level, GameConfig},
+    piece_set::PieceSet,
+    rule_set::RuleSet,
+    piece::{
+        auto_generate_new_piece, check_collision, check_full_line, collapse_cleared_lines,
+        move_piece, remove_piece_component, rotate_piece, LinesCleared, PieceLocked, PieceMoved,
+    },
+    player::read_keyboard_input,
+    puzzle::{check_puzzle_goal, hold_piece, setup_puzzle},
+    state::{count_locked_pieces, tick_play_time},
+    versus::apply_garbage_attacks,
+};
+
+// 开始新游戏时生成面板等实体的系统，从暂停恢复时不会运行
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct NewGameSetup;
+
+// 游戏规则：面板、骨牌、计分、垃圾行、模式目标和游戏结束。不包括界面、音频和菜单，无界面模拟也使用它
+pub struct GameRulesPlugin;
+
+impl Plugin for GameRulesPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<GameSettings>()
+            .init_resource::<GameConfig>()
+            .init_resource::<PieceSet>()
+            .init_resource::<RuleSet>()
+            .init_state::<AppState>()
+            .add_event::<RestartGame>()
+            .add_event::<PieceMoved>()
+            .add_event::<PieceLocked>()
+            .add_event::<LinesCleared>()
+            // 进入 InGame 时如果还没有游戏面板（新游戏或重新开始），生成每个玩家的面板、骨牌队列和计时器
+            .configure_sets(
+                OnEnter(AppState::InGame),
+                NewGameSetup.run_if(not(any_with_component::<MainBoard>)),
+            )
+            .add_systems(
+                OnEnter(AppState::InGame),
+                (setup_game_board, spawn_initial_garbage, setup_puzzle)
+                    .chain()
+                    .in_set(NewGameSetup),
+            )
+            .add_systems(
+                Update,
+                (
+                    read_keyboard_input,
+                    (auto_generate_new_piece, move_piece, rotate_piece, tick_play_time),
+                )
+                    .chain()
+                    .run_if(in_state(AppState::InGame)),
+            )
+            // 按顺序执行，骨牌固定后同一帧内就能检测消行
+            .add_systems(
+                PostUpdate,
+                (
+                    rise_garbage,
+                    check_collision,
+                    remove_piece_component,
+                    count_locked_pieces,
+                    check_full_line,
+                    collapse_cleared_lines,
+                    apply_garbage_attacks,
+                    update_level,
+                    check_game_overs,
+                    check_mode_goal,
+                )
+                    .chain()
+                    .run_if(in_state(AppState::InGame)),
+            )
+            // 谜题模式：暂存骨牌，检测目标是否完成
+            .add_systems(
+                Update,
+                hold_piece
+                    .after(read_keyboard_input)
+                    .before(auto_generate_new_piece)
+                    .before(move_piece)
+                    .before(rotate_piece)
+                    .run_if(in_state(AppState::InGame)),
+            )
+            .add_systems(
+                PostUpdate,
+                check_puzzle_goal
+                    .after(check_full_line)
+                    .before(check_mode_goal)
+                    .run_if(in_state(AppState::InGame)),
+            )
+            // 按键失误统计：在移动之前读取按键，在骨牌固定后比较
+            .add_systems(
+                Update,
+                count_finesse_inputs
+                    .after(read_keyboard_input)
+                    .before(move_piece)
+                    .before(rotate_piece)
+                    .run_if(in_state(AppState::InGame)),
+            )
+            .add_systems(
+                PostUpdate,
+                check_finesse
+                    .after(remove_piece_component)
+                    .before(check_full_line)
+                    .run_if(in_state(AppState::InGame)),
+            )
+            .add_systems(Update, restart_game);
+    }
+}
@@ -0,0 +1,139 @@
+use bevy::prelude::*;
+
+// 完整计分规则（参考 Tetris Guideline）中需要跨骨牌保存的状态
+#[derive(Debug, Default, Component)]
+pub struct ScoringState {
+    // 连续消行次数，第一次消行为 0，没有连击时为 None
+    pub combo: Option<u32>,
+    // 上一次消行是否为“困难消除”（四行消除或 T-spin 消行），连续两次困难消除时加分
+    pub back_to_back: bool,
+}
+
+// 一次骨牌固定的消行结果
+#[derive(Debug, Clone, Copy)]
+pub struct ClearResult {
+    pub lines: u32,
+    pub t_spin: bool,
+    // 消行后面板上没有剩余方块
+    pub perfect_clear: bool,
+}
+
+// 经典计分：只按消除的行数加分
+pub fn classic_clear_score(lines: u32) -> u32 {
+    match lines {
+        0 => 0,
+        1 => 100,
+        2 => 200,
+        3 => 400,
+        4 => 800,
+        _ => 1000,
+    }
+}
+
+// 完整计分规则：等级倍率、T-spin、Back-to-Back、连击和全消奖励
+pub fn full_clear_score(result: ClearResult, level: u32, state: &mut ScoringState) -> u32 {
+    let base = match (result.t_spin, result.lines) {
+        (false, 0) => 0,
+        (false, 1) => 100,
+        (false, 2) => 300,
+        (false, 3) => 500,
+        (false, _) => 800,
+        (true, 0) => 400,
+        (true, 1) => 800,
+        (true, 2) => 1200,
+        (true, _) => 1600,
+    };
+
+    if result.lines == 0 {
+        // 没有消行时连击中断，Back-to-Back 保持不变
+        state.combo = None;
+        return base * level;
+    }
+
+    let difficult = result.t_spin || result.lines >= 4;
+    let mut score = base * level;
+    if difficult && state.back_to_back {
+        score = score * 3 / 2;
+    }
+    state.back_to_back = difficult;
+
+    let combo = state.combo.map_or(0, |combo| combo + 1);
+    state.combo = Some(combo);
+    score += 50 * combo * level;
+
+    if result.perfect_clear {
+        score += match result.lines {
+            1 => 800,
+            2 => 1200,
+            3 => 1800,
+            _ => 2000,
+        } * level;
+    }
+    score
+}
+
+// 软降每格加 1 分
+pub const SOFT_DROP_SCORE: u32 = 1;
+// 直接落下每格加 2 分
+pub const HARD_DROP_SCORE: u32 = 2;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clear(lines: u32, t_spin: bool, perfect_clear: bool) -> ClearResult {
+        ClearResult {
+            lines,
+            t_spin,
+            perfect_clear,
+        }
+    }
+
+    #[test]
+    fn classic_score_table() {
+        let scores: Vec<u32> = (0..=5).map(classic_clear_score).collect();
+        assert_eq!(scores, vec![0, 100, 200, 400, 800, 1000]);
+    }
+
+    #[test]
+    fn full_score_table() {
+        // 没有连击和 Back-to-Back 时的基础分乘以等级
+        let cases = [
+            (clear(0, false, false), 0),
+            (clear(1, false, false), 100),
+            (clear(2, false, false), 300),
+            (clear(3, false, false), 500),
+            (clear(4, false, false), 800),
+            (clear(0, true, false), 400),
+            (clear(1, true, false), 800),
+            (clear(2, true, false), 1200),
+            (clear(3, true, false), 1600),
+        ];
+        for (result, base) in cases {
+            let mut state = ScoringState::default();
+            assert_eq!(full_clear_score(result, 2, &mut state), base * 2, "{:?}", result);
+        }
+    }
+
+    #[test]
+    fn back_to_back_and_combo() {
+        let mut state = ScoringState::default();
+        assert_eq!(full_clear_score(clear(4, false, false), 1, &mut state), 800);
+        // 连续第二次困难消除 1.5 倍，连击第 1 次加 50
+        assert_eq!(full_clear_score(clear(4, false, false), 1, &mut state), 1200 + 50);
+        // 普通消行中断 Back-to-Back，连击继续
+        assert_eq!(full_clear_score(clear(1, false, false), 1, &mut state), 100 + 100);
+        assert!(!state.back_to_back);
+        // 没有消行时连击中断
+        assert_eq!(full_clear_score(clear(0, false, false), 1, &mut state), 0);
+        assert_eq!(state.combo, None);
+    }
+
+    #[test]
+    fn perfect_clear_bonus() {
+        let mut state = ScoringState::default();
+        assert_eq!(full_clear_score(clear(2, false, true), 1, &mut state), 300 + 1200);
+        let mut state = ScoringState::default();
+        assert_eq!(full_clear_score(clear(4, false, true), 3, &mut state), (800 + 2000) * 3);
+    }
+}
@@ -0,0 +1,41 @@
+use bevy::prelude::*;
+
+use crate::{
+    common::{AppState, VolumeChannel},
+    menu::{
+        spawn_menu_button, spawn_menu_panel, spawn_menu_title, spawn_option_row, spawn_toggle_row,
+        MenuButton, MenuLabel,
+    },
+};
+
+// 关闭设置界面后返回的状态
+#[derive(Debug, Resource)]
+pub struct SettingsReturnState(pub AppState);
+
+impl Default for SettingsReturnState {
+    fn default() -> Self {
+        Self(AppState::MainMenu)
+    }
+}
+
+pub fn show_settings_menu(mut commands: Commands) {
+    spawn_menu_panel(&mut commands, AppState::Settings, |parent| {
+        spawn_menu_title(parent, "SETTINGS");
+        spawn_toggle_row(parent, MenuLabel::PauseOnFocusLost, MenuButton::TogglePauseOnFocusLost);
+        spawn_toggle_row(parent, MenuLabel::Hints, MenuButton::ToggleHints);
+        spawn_toggle_row(parent, MenuLabel::FinessePractice, MenuButton::ToggleFinessePractice);
+        spawn_toggle_row(parent, MenuLabel::Effects, MenuButton::CycleEffects);
+        spawn_toggle_row(parent, MenuLabel::SmoothMovement, MenuButton::ToggleSmoothMovement);
+        spawn_toggle_row(parent, MenuLabel::Fullscreen, MenuButton::ToggleFullscreen);
+        spawn_option_row(parent, MenuLabel::Theme, MenuButton::PreviousTheme, MenuButton::NextTheme);
+        for channel in [VolumeChannel::Master, VolumeChannel::Music, VolumeChannel::Sfx] {
+            spawn_option_row(
+                parent,
+                MenuLabel::Volume(channel),
+                MenuButton::VolumeDown(channel),
+                MenuButton::VolumeUp(channel),
+            );
+        }
+        spawn_menu_button(parent, "Back", MenuButton::Back);
+    });
+}
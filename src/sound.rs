@@ -0,0 +1,239 @@
+use bevy::{audio::Volume, prelude::*};
+
+use crate::{
+    board::MainBoard,
+    common::{AppState, GameSettings},
+    mode::{check_mode_goal, GameConfig},
+    piece::{LinesCleared, PieceLocked, PieceMove, PieceMoved},
+    player::InputMap,
+    state::Level,
+};
+
+// 同一个音效最多同时播放的数量，超过时不再播放新的
+const MAX_COPIES: usize = 3;
+
+// 音频：根据游戏事件播放音效，游戏中循环播放音乐，音量由设置中的三个音量通道决定。
+// 只播放玩家自己操作的面板的音效，对战中的机器人和网络对手的面板不播放，观看机器人演示时播放
+pub struct SoundPlugin;
+
+impl Plugin for SoundPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, (setup_game_audios_resource, spawn_music).chain())
+            .add_systems(Update, update_music)
+            .add_systems(
+                PostUpdate,
+                play_game_sounds
+                    .after(check_mode_goal)
+                    .run_if(in_state(AppState::InGame)),
+            )
+            .add_systems(OnEnter(AppState::GameOver), play_game_over_sound);
+    }
+}
+
+#[derive(Debug, Resource)]
+pub struct GameAudios {
+    pub move_piece: Handle<AudioSource>,
+    pub rotate: Handle<AudioSource>,
+    pub soft_drop: Handle<AudioSource>,
+    pub hard_drop: Handle<AudioSource>,
+    pub lock: Handle<AudioSource>,
+    // 一次消除 1 到 4 行的音效
+    pub line_clears: [Handle<AudioSource>; 4],
+    pub t_spin: Handle<AudioSource>,
+    pub level_up: Handle<AudioSource>,
+    pub game_over: Handle<AudioSource>,
+    pub music: Handle<AudioSource>,
+}
+
+/// 加载游戏音频资源
+///
+/// 该函数负责加载游戏过程中使用的音频资源，并将它们插入到游戏资源池中。
+///
+/// # 参数
+/// - `mut command: Commands`: 命令缓冲区，用于插入资源命令。
+/// - `asset_server: Res<AssetServer>`: 资源服务器，用于加载音频资源。
+pub fn setup_game_audios_resource(mut command: Commands, asset_server: Res<AssetServer>) {
+    // 定义并加载游戏音频资源
+    let game_audios: GameAudios = GameAudios {
+        move_piece: asset_server.load("sounds/move.wav"),
+        rotate: asset_server.load("sounds/rotate.wav"),
+        soft_drop: asset_server.load("sounds/drop.wav"),
+        hard_drop: asset_server.load("sounds/hard_drop.wav"),
+        lock: asset_server.load("sounds/lock.wav"),
+        line_clears: [
+            asset_server.load("sounds/clear_1.wav"),
+            asset_server.load("sounds/clear_2.wav"),
+            asset_server.load("sounds/clear_3.wav"),
+            asset_server.load("sounds/clear_4.wav"),
+        ],
+        t_spin: asset_server.load("sounds/t_spin.wav"),
+        level_up: asset_server.load("sounds/level_up.wav"),
+        game_over: asset_server.load("sounds/Gameover.wav"),
+        music: asset_server.load("sounds/music.wav"),
+    };
+
+    // 将加载的音频资源插入到命令缓冲区中
+    command.insert_resource(game_audios);
+}
+
+// 音效，也作为正在播放的音效实体的组件，用来限制同时播放的数量
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sfx {
+    Move,
+    Rotate,
+    SoftDrop,
+    HardDrop,
+    Lock,
+    // 消除的行数
+    LineClear(u32),
+    TSpin,
+    LevelUp,
+    GameOver,
+}
+
+impl Sfx {
+    fn source(&self, audios: &GameAudios) -> Handle<AudioSource> {
+        match self {
+            Sfx::Move => audios.move_piece.clone(),
+            Sfx::Rotate => audios.rotate.clone(),
+            Sfx::SoftDrop => audios.soft_drop.clone(),
+            Sfx::HardDrop => audios.hard_drop.clone(),
+            Sfx::Lock => audios.lock.clone(),
+            // 一次消除超过 4 行（自定义骨牌）时使用 4 行的音效
+            Sfx::LineClear(lines) => {
+                audios.line_clears[(*lines).clamp(1, 4) as usize - 1].clone()
+            }
+            Sfx::TSpin => audios.t_spin.clone(),
+            Sfx::LevelUp => audios.level_up.clone(),
+            Sfx::GameOver => audios.game_over.clone(),
+        }
+    }
+}
+
+// 背景音乐
+#[derive(Debug, Component)]
+pub struct Music;
+
+fn play_sfx(
+    commands: &mut Commands,
+    audios: &GameAudios,
+    settings: &GameSettings,
+    playing: &mut Vec<Sfx>,
+    sfx: Sfx,
+) {
+    let volume = settings.master_volume * settings.sfx_volume;
+    if volume <= 0.0 || playing.iter().filter(|other| **other == sfx).count() >= MAX_COPIES {
+        return;
+    }
+    playing.push(sfx);
+    commands.spawn((
+        AudioBundle {
+            source: sfx.source(audios),
+            settings: PlaybackSettings::DESPAWN.with_volume(Volume::new(volume)),
+        },
+        sfx,
+    ));
+}
+
+// 根据这一帧的游戏事件播放音效
+pub fn play_game_sounds(
+    mut commands: Commands,
+    audios: Option<Res<GameAudios>>,
+    settings: Res<GameSettings>,
+    config: Res<GameConfig>,
+    mut piece_moved_events: EventReader<PieceMoved>,
+    mut piece_locked_events: EventReader<PieceLocked>,
+    mut lines_cleared_events: EventReader<LinesCleared>,
+    main_board: Query<(Ref<Level>, Has<InputMap>), With<MainBoard>>,
+    playing_sounds: Query<&Sfx>,
+) {
+    let Some(audios) = audios else {
+        return;
+    };
+    let audible = |board: Entity| {
+        main_board
+            .get(board)
+            .is_ok_and(|(_, human)| human || config.demo)
+    };
+    let mut sounds = Vec::new();
+    for event in piece_moved_events.read() {
+        if audible(event.board) {
+            sounds.push(match event.kind {
+                PieceMove::Shift => Sfx::Move,
+                PieceMove::Rotate => Sfx::Rotate,
+                PieceMove::SoftDrop => Sfx::SoftDrop,
+            });
+        }
+    }
+    for event in piece_locked_events.read() {
+        if audible(event.board) {
+            sounds.push(if event.hard_drop { Sfx::HardDrop } else { Sfx::Lock });
+        }
+    }
+    for event in lines_cleared_events.read() {
+        if !audible(event.board) {
+            continue;
+        }
+        if event.t_spin {
+            sounds.push(Sfx::TSpin);
+        }
+        if event.lines > 0 {
+            sounds.push(Sfx::LineClear(event.lines));
+        }
+    }
+    // 新游戏开始时等级也会被设置，不算升级
+    if main_board.iter().any(|(level, human)| {
+        (human || config.demo) && level.is_changed() && !level.is_added()
+    })
+    {
+        sounds.push(Sfx::LevelUp);
+    }
+
+    let mut playing: Vec<Sfx> = playing_sounds.iter().copied().collect();
+    for sfx in sounds {
+        play_sfx(&mut commands, &audios, &settings, &mut playing, sfx);
+    }
+}
+
+pub fn play_game_over_sound(
+    mut commands: Commands,
+    audios: Option<Res<GameAudios>>,
+    settings: Res<GameSettings>,
+    playing_sounds: Query<&Sfx>,
+) {
+    let Some(audios) = audios else {
+        return;
+    };
+    let mut playing: Vec<Sfx> = playing_sounds.iter().copied().collect();
+    play_sfx(&mut commands, &audios, &settings, &mut playing, Sfx::GameOver);
+}
+
+pub fn spawn_music(mut commands: Commands, audios: Res<GameAudios>, settings: Res<GameSettings>) {
+    commands.spawn((
+        AudioBundle {
+            source: audios.music.clone(),
+            settings: PlaybackSettings::LOOP
+                .with_volume(Volume::new(settings.master_volume * settings.music_volume))
+                .paused(),
+        },
+        Music,
+    ));
+}
+
+// 音乐只在游戏进行中播放，音量随设置变化
+pub fn update_music(
+    app_state: Res<State<AppState>>,
+    settings: Res<GameSettings>,
+    music: Query<&AudioSink, With<Music>>,
+) {
+    let Ok(sink) = music.get_single() else {
+        return;
+    };
+    sink.set_volume(settings.master_volume * settings.music_volume);
+    let playing = *app_state.get() == AppState::InGame;
+    if playing && sink.is_paused() {
+        sink.play();
+    } else if !playing && !sink.is_paused() {
+        sink.pause();
+    }
+}
@@ -1,43 +1,126 @@
-use bevy::{color::palettes, prelude::*};
+use std::time::Duration;
+
+use bevy::prelude::*;
 
 use crate::{
-    board::{Block, NextPieceBoard, BLOCK_LENGTH, BLOCK_STICKER_LENGTH},
-    common::FontTff,
-    piece::{piece_shape, NextPieceType, PieceQueue, PieceType},
+    board::{
+        board_translation, Block, MainBoard, PlayerPanel, StatsPanel,
+        BLOCK_LENGTH, BLOCK_STICKER_LENGTH, BLOCK_Z,
+    },
+    common::{FontTff, GameEntity},
+    finesse::FinesseHintText,
+    garbage::{remaining_garbage_rows, GarbageBlock, GarbageRiseTimer},
+    mode::{GameConfig, GameMode, MARATHON_LINES, SPRINT_LINES, ULTRA_TIME},
+    piece::{PieceLocked, PieceQueue},
+    player::Player,
+    puzzle::{puzzle_status, HoldPiece, PuzzleState},
+    theme::{BlockSkin, HudText, Theme},
+    versus::IncomingGarbage,
 };
 
 // 计分板长宽
-const STATS_BOARD_LENGTH: f32 = 300.0;
+pub const STATS_BOARD_LENGTH: f32 = 300.0;
 const STATS_BOARD_WIDTH: f32 = 50.0;
 
 
 // 方块初始 出现的偏移量
-pub const base_offset:(i32,i32) = (3, -2);
+pub const BASE_OFFSET:(i32,i32) = (3, -2);
 // 分数
-#[derive(Resource)]
+#[derive(Component)]
 pub struct Score(pub u32);
 
+// 等级
+#[derive(Component)]
+pub struct Level(pub u32);
+
+// 已消除的行数
+#[derive(Component)]
+pub struct Lines(pub u32);
+
+// 本局统计数据，在结算界面展示
+#[derive(Debug, Default, Component)]
+pub struct SessionStats {
+    // 游戏时长，不包括暂停时间
+    pub play_time: Duration,
+    // 已放置的骨牌数
+    pub pieces_placed: u32,
+    // 一次消除四行的次数
+    pub tetrises: u32,
+    pub t_spins: u32,
+    // 对战模式中发送给对手的垃圾行数（抵消后）
+    pub garbage_sent: u32,
+    // 比最少按键多按的次数
+    pub finesse_faults: u32,
+    // 本局是否刷新了该模式的最高分
+    pub new_personal_best: bool,
+}
+
+impl SessionStats {
+    // 每秒放置的骨牌数
+    pub fn pieces_per_second(&self) -> f32 {
+        let seconds = self.play_time.as_secs_f32();
+        if seconds > 0.0 {
+            self.pieces_placed as f32 / seconds
+        } else {
+            0.0
+        }
+    }
+}
+
+// 格式化时长为 分:秒.毫秒
+pub fn format_duration(duration: Duration) -> String {
+    let millis = duration.as_millis();
+    format!(
+        "{:02}:{:02}.{:03}",
+        millis / 60_000,
+        millis / 1000 % 60,
+        millis % 1000
+    )
+}
+
 #[derive(Component)]
 pub struct Scoreboard;
 
 #[derive(Component)]
 pub struct Linesboard;
 
+// 当前模式相关的信息，比如竞速模式的计时和剩余行数
+#[derive(Component)]
+pub struct ModeBoard;
+
+// 累计游戏时长，只在 InGame 状态下运行，暂停时不计时
+pub fn tick_play_time(time: Res<Time>, mut main_board: Query<&mut SessionStats, With<MainBoard>>) {
+    for mut stats in &mut main_board {
+        stats.play_time += time.delta();
+    }
+}
+
+// 统计放置的骨牌和 T-spin
+pub fn count_locked_pieces(
+    mut piece_locked_events: EventReader<PieceLocked>,
+    mut main_board: Query<&mut SessionStats, With<MainBoard>>,
+) {
+    for event in piece_locked_events.read() {
+        let Ok(mut stats) = main_board.get_mut(event.board) else {
+            continue;
+        };
+        stats.pieces_placed += 1;
+        if event.t_spin {
+            stats.t_spins += 1;
+        }
+    }
+}
+
 // 设置 游戏相关信息
 pub fn setup_post_states_boards(
     mut commands: Commands,
     font_tff: Res<FontTff>,
-    windows: Query<&Window>,
+    theme: Res<Theme>,
+    config: Res<GameConfig>,
+    player_panels: Query<(Entity, &Player), With<PlayerPanel>>,
+    stats_panels: Query<Entity, With<StatsPanel>>,
 ) {
-    // 通过窗口大小和棋盘大小计算stats位置
-    let window = windows.single();
-
-    // gameboard左上角在窗口上的位置
-    let gameboard_left_corner_pos = (
-        window.physical_width() as f32 / 2.0 - 5.0 * BLOCK_LENGTH,
-        window.physical_height() as f32 / 2.0 - 10.0 * BLOCK_LENGTH,
-    );
-
+    let font = theme.hud_font(&font_tff);
 
     // 标题
     commands.spawn(
@@ -45,8 +128,8 @@ pub fn setup_post_states_boards(
             "俄罗斯方块",
             TextStyle {
                 font_size: 40.0,
-                color: Color::srgb(0.5, 0.5, 1.0),
-                font: font_tff.sim_hei.clone(),
+                color: theme.label_color(),
+                font: font.clone(),
             },
         )])
         .with_style(Style {
@@ -56,103 +139,297 @@ pub fn setup_post_states_boards(
             ..default()
         })
         .with_text_justify(JustifyText::Center),
-    );
-
-    // 分数
-    commands
-        .spawn(
-            TextBundle::from_sections([
-                TextSection::new(
-                    "分数: ",
-                    TextStyle {
-                        font_size: 40.0,
-                        color: Color::srgb(0.5, 0.5, 1.0),
-                        font: font_tff.sim_hei.clone(),
-                    },
-                ),
-                TextSection::new(
-                    "0",
+    )
+    .insert(HudText::Label)
+    .insert(GameEntity);
+
+    // 多人游戏时每个玩家的信息显示在自己面板旁边
+    if config.mode.player_count() > 1 {
+        for (player_panel, player) in &player_panels {
+            spawn_player_panel_texts(&mut commands, &theme, &font, player_panel, *player);
+        }
+        return;
+    }
+
+    // 单人游戏的信息在游戏面板左边，随布局一起移动
+    let Ok(stats_panel) = stats_panels.get_single() else {
+        return;
+    };
+    // 每项占的高度，模式信息可能有多行
+    let row = |height: f32| Style {
+        min_height: Val::Px(height),
+        ..default()
+    };
+    commands.entity(stats_panel).with_children(|parent| {
+        // 分数
+        parent
+            .spawn(
+                TextBundle::from_sections([
+                    TextSection::new(
+                        "分数: ",
+                        TextStyle {
+                            font_size: 40.0,
+                            color: theme.label_color(),
+                            font: font.clone(),
+                        },
+                    ),
+                    TextSection::new(
+                        "0",
+                        TextStyle {
+                            font_size: 40.0,
+                            color: theme.value_color(),
+                            ..default()
+                        },
+                    ),
+                ])
+                .with_style(row(STATS_BOARD_WIDTH)),
+            )
+            .insert(Scoreboard)
+            .insert(HudText::Label)
+            .insert(Player(0));
+
+        // 行数
+        parent
+            .spawn(
+                TextBundle::from_sections([
+                    TextSection::new(
+                        "行数: ",
+                        TextStyle {
+                            font_size: 40.0,
+                            color: theme.label_color(),
+                            font: font.clone(),
+                        },
+                    ),
+                    TextSection::new(
+                        "0",
+                        TextStyle {
+                            font_size: 40.0,
+                            color: theme.value_color(),
+                            ..default()
+                        },
+                    ),
+                ])
+                .with_style(row(STATS_BOARD_WIDTH)),
+            )
+            .insert(Linesboard)
+            .insert(HudText::Label)
+            .insert(Player(0));
+
+        // 模式信息
+        parent
+            .spawn(
+                TextBundle::from_section(
+                    "",
                     TextStyle {
-                        font_size: 40.0,
-                        color: Color::srgb(1.0, 0.5, 0.5),
-                        ..default()
+                        font_size: 30.0,
+                        color: theme.label_color(),
+                        font: font.clone(),
                     },
-                ),
-            ])
-            .with_style(Style {
-                position_type: PositionType::Absolute,
-                top: Val::Px(gameboard_left_corner_pos.1),
-                left: Val::Px(gameboard_left_corner_pos.0 - STATS_BOARD_LENGTH),
-                ..default()
-            }),
-        )
-        .insert(Scoreboard);
-
-    // // 行数
-    // commands
-    //     .spawn(
-    //         TextBundle::from_sections([
-    //             TextSection::new(
-    //                 "行数: ",
-    //                 TextStyle {
-    //                     font_size: 40.0,
-    //                     color: Color::srgb(0.5, 0.5, 1.0),
-    //                     font: font_tff.sim_hei.clone(),
-    //                 },
-    //             ),
-    //             TextSection::new(
-    //                 "0",
-    //                 TextStyle {
-    //                     font_size: 40.0,
-    //                     color: Color::srgb(1.0, 0.5, 0.5),
-    //                     ..default()
-    //                 },
-    //             ),
-    //         ])
-    //         .with_style(Style {
-    //             position_type: PositionType::Absolute,
-    //             top: Val::Px(gameboard_left_corner_pos.1 + STATS_BOARD_WIDTH),
-    //             left: Val::Px(gameboard_left_corner_pos.0 - STATS_BOARD_LENGTH),
-    //             ..default()
-    //         }),
-    //     )
-    //     .insert(Linesboard);
+                )
+                .with_style(row(STATS_BOARD_WIDTH * 2.5)),
+            )
+            .insert(ModeBoard)
+            .insert(HudText::Label)
+            .insert(Player(0));
+
+        // 按键练习的最优按键
+        parent
+            .spawn(TextBundle::from_section(
+                "",
+                TextStyle {
+                    font_size: 24.0,
+                    color: theme.highlight_color(),
+                    font: font.clone(),
+                },
+            ))
+            .insert(FinesseHintText)
+            .insert(HudText::Highlight)
+            .insert(Player(0));
+    });
+}
+
+// 对战时玩家面板中的名字、分数、行数和模式信息
+fn spawn_player_panel_texts(
+    commands: &mut Commands,
+    theme: &Theme,
+    font: &Handle<Font>,
+    player_panel: Entity,
+    player: Player,
+) {
+    let label_style = TextStyle {
+        font_size: 24.0,
+        color: theme.label_color(),
+        font: font.clone(),
+    };
+    let value_style = TextStyle {
+        font_size: 24.0,
+        color: theme.value_color(),
+        ..default()
+    };
+    commands.entity(player_panel).with_children(|parent| {
+        parent.spawn((
+            TextBundle::from_section(
+                player.name(),
+                TextStyle {
+                    font_size: 28.0,
+                    ..label_style.clone()
+                },
+            ),
+            HudText::Label,
+        ));
+        parent
+            .spawn(TextBundle::from_sections([
+                TextSection::new("分数: ", label_style.clone()),
+                TextSection::new("0", value_style.clone()),
+            ]))
+            .insert((Scoreboard, HudText::Label, player));
+        parent
+            .spawn(TextBundle::from_sections([
+                TextSection::new("行数: ", label_style.clone()),
+                TextSection::new("0", value_style.clone()),
+            ]))
+            .insert((Linesboard, HudText::Label, player));
+        parent
+            .spawn(TextBundle::from_section("", label_style.clone()))
+            .insert((ModeBoard, HudText::Label, player));
+        parent
+            .spawn(TextBundle::from_section(
+                "",
+                TextStyle {
+                    font_size: 20.0,
+                    color: theme.highlight_color(),
+                    ..label_style.clone()
+                },
+            ))
+            .insert((FinesseHintText, HudText::Highlight, player));
+    });
 }
 
 // 提前显示下一个即将出现的方块
 pub fn spawn_next_piece_board(
     commands: &mut Commands,
     next_piece_board: Entity,
-    blocks: [Block; 4],
+    blocks: &[Block],
     color: Color,
+    skin: BlockSkin,
 ) {
     for block in blocks.iter() {
-        let left = Val::Px(block.x as f32 * BLOCK_LENGTH);
-        let top = Val::Px(block.y as f32 * BLOCK_LENGTH);
-        let new_block_sprite = commands.spawn(new_block_sprite(color, left, top)).id();
+        let position = Vec2::new(block.x as f32, block.y as f32) * BLOCK_LENGTH;
+        let new_block_sprite = commands.spawn((new_block_sprite(color, position), skin)).id();
         commands.entity(next_piece_board).add_child(new_block_sprite);
     }
 }
 
-pub fn new_block_sprite(color: Color, left: Val, top: Val) -> NodeBundle {
-    NodeBundle {
-        style: Style {
-            position_type: PositionType::Absolute,
-            top,
-            left,
-            width: Val::Px(BLOCK_LENGTH),
-            height: Val::Px(BLOCK_LENGTH),
-            border: UiRect::all(Val::Px(1.0)),
-            ..Default::default()
+// 面板中的方块，position 为方块左上角相对面板左上角的位置（像素，y 向下）
+pub fn new_block_sprite(color: Color, position: Vec2) -> SpriteBundle {
+    SpriteBundle {
+        sprite: Sprite {
+            color,
+            custom_size: Some(Vec2::splat(BLOCK_STICKER_LENGTH)),
+            ..default()
         },
-        background_color: BackgroundColor(color),
-        border_color: BorderColor(Color::BLACK),
-        ..Default::default()
+        transform: Transform::from_translation(board_translation(position, BLOCK_Z)),
+        ..default()
+    }
+}
+
+// 面板上已经固定的方块，垃圾方块同时标记为 GarbageBlock
+pub fn spawn_board_block(parent: &mut ChildBuilder, block: Block, color: Color, skin: BlockSkin) {
+    let mut block_commands = parent.spawn((new_block_sprite(color, block.transform_to()), block, skin));
+    if skin == BlockSkin::Garbage {
+        block_commands.insert(GarbageBlock);
+    }
+}
+
+pub fn update_scoreboard(
+    main_board: Query<(&Player, &Score), With<MainBoard>>,
+    mut query: Query<(&Player, &mut Text), With<Scoreboard>>,
+) {
+    for (scoreboard_player, mut text) in &mut query {
+        for (player, score) in &main_board {
+            if player == scoreboard_player {
+                text.sections[1].value = score.0.to_string();
+            }
+        }
     }
 }
 
+pub fn update_linesboard(
+    main_board: Query<(&Player, &Lines), With<MainBoard>>,
+    mut query: Query<(&Player, &mut Text), With<Linesboard>>,
+) {
+    for (linesboard_player, mut text) in &mut query {
+        for (player, lines) in &main_board {
+            if player == linesboard_player {
+                text.sections[1].value = lines.0.to_string();
+            }
+        }
+    }
+}
 
-pub fn update_scoreboard(score: Res<Score>, mut query: Query<&mut Text, With<Scoreboard>>) {
-    let mut text = query.single_mut();
-    text.sections[1].value = score.0.to_string();
-}
\ No newline at end of file
+pub fn update_modeboard(
+    config: Res<GameConfig>,
+    main_board: Query<
+        (
+            Entity,
+            &Player,
+            &Level,
+            &Lines,
+            &SessionStats,
+            &GarbageRiseTimer,
+            &IncomingGarbage,
+        ),
+        With<MainBoard>,
+    >,
+    garbage_blocks: Query<(&Parent, &Block), With<GarbageBlock>>,
+    puzzle_boards: Query<(&PuzzleState, &HoldPiece, &PieceQueue)>,
+    mut query: Query<(&Player, &mut Text), With<ModeBoard>>,
+) {
+    for (modeboard_player, mut text) in &mut query {
+        for (main_board_entity, player, level, lines, stats, garbage_rise_timer, incoming) in
+            &main_board
+        {
+            if player != modeboard_player {
+                continue;
+            }
+            let mode_text = match config.mode {
+                GameMode::Marathon => {
+                    format!("等级: {}\n目标: {}/{}", level.0, lines.0, MARATHON_LINES)
+                }
+                GameMode::Endless => format!("等级: {}", level.0),
+                GameMode::Sprint => format!(
+                    "时间: {}\n剩余: {}",
+                    format_duration(stats.play_time),
+                    SPRINT_LINES.saturating_sub(lines.0)
+                ),
+                GameMode::Ultra => format!(
+                    "剩余时间: {}",
+                    format_duration(ULTRA_TIME.saturating_sub(stats.play_time))
+                ),
+                GameMode::Cheese => format!(
+                    "时间: {}\n垃圾行: {}",
+                    format_duration(stats.play_time),
+                    remaining_garbage_rows(&garbage_blocks, main_board_entity)
+                ),
+                GameMode::Survival => format!(
+                    "时间: {}\n下次上升: {:.1}s",
+                    format_duration(stats.play_time),
+                    garbage_rise_timer.0.remaining_secs()
+                ),
+                GameMode::Versus => format!(
+                    "攻击: {}\n待升起: {}",
+                    stats.garbage_sent, incoming.0
+                ),
+                GameMode::Puzzle => puzzle_boards
+                    .get(main_board_entity)
+                    .map(|(state, hold, piece_queue)| puzzle_status(state, hold, piece_queue))
+                    .unwrap_or_default(),
+            };
+            // 只在内容变化时修改，避免每帧重新排版文字
+            let value = format!("{}\n按键失误: {}", mode_text, stats.finesse_faults);
+            if text.sections[0].value != value {
+                text.sections[0].value = value;
+            }
+        }
+    }
+}
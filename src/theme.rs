@@ -0,0 +1,374 @@
+use std::collections::BTreeMap;
+
+use bevy::{
+    asset::{io::Reader, AssetLoader, AsyncReadExt, LoadContext},
+    prelude::*,
+};
+use serde::Deserialize;
+
+use crate::{
+    asset_errors::{track_asset_errors, AssetErrors},
+    common::{FontTff, GameSettings},
+    piece::{ClearingBlock, PieceType},
+    piece_set::PieceSet,
+};
+
+// 主题所在的资源目录，每个 .theme.ron 文件是一个主题
+const THEME_DIR: &str = "themes";
+
+// 内置的默认主题，资源加载完成前使用
+const CLASSIC_THEME: &str = include_str!("../assets/themes/classic.theme.ron");
+
+// 从资源目录加载主题，在设置界面中切换，切换后已经显示的面板和方块立即使用新的主题
+pub struct ThemePlugin;
+
+impl Plugin for ThemePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<Theme>()
+            .init_asset_loader::<ThemeLoader>()
+            .init_resource::<Themes>()
+            .init_resource::<Theme>()
+            .init_resource::<AssetErrors>()
+            .add_systems(Startup, load_themes)
+            .add_systems(
+                Update,
+                (
+                    track_asset_errors::<Theme>,
+                    update_themes,
+                    select_theme.run_if(
+                        resource_changed::<GameSettings>.or_else(resource_changed::<Themes>),
+                    ),
+                )
+                    .chain(),
+            )
+            // 在 Update 中生成的方块和文字在同一帧使用主题
+            .add_systems(
+                PostUpdate,
+                (
+                    apply_clear_color.run_if(resource_changed::<Theme>),
+                    apply_board_theme,
+                    apply_block_skins,
+                    apply_hud_theme,
+                ),
+            );
+    }
+}
+
+// 主题。作为资源时是当前使用的主题，颜色都是 sRGB
+#[derive(Asset, Resource, TypePath, Debug, Clone, PartialEq, Deserialize)]
+pub struct Theme {
+    pub name: String,
+    // 窗口背景
+    pub background: (f32, f32, f32),
+    pub board_background: (f32, f32, f32, f32),
+    // 面板上的网格线，None 不显示
+    #[serde(default)]
+    pub grid: Option<(f32, f32, f32, f32)>,
+    pub border: (f32, f32, f32),
+    // 按骨牌字母覆盖骨牌定义中的颜色
+    #[serde(default)]
+    pub pieces: BTreeMap<char, (f32, f32, f32)>,
+    pub garbage: (f32, f32, f32),
+    // 面板文字：标题和名称、数值、提示
+    pub label: (f32, f32, f32),
+    pub value: (f32, f32, f32),
+    pub highlight: (f32, f32, f32),
+    // 面板文字的字体，None 使用默认字体
+    #[serde(default)]
+    pub font: Option<String>,
+    // 方块的贴图，与方块颜色相乘
+    #[serde(default)]
+    pub block_texture: Option<String>,
+    // 加载主题文件时一起加载的字体和贴图
+    #[serde(skip)]
+    #[dependency]
+    pub font_handle: Option<Handle<Font>>,
+    #[serde(skip)]
+    #[dependency]
+    pub block_texture_handle: Option<Handle<Image>>,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme::parse(CLASSIC_THEME).expect("built-in theme is valid")
+    }
+}
+
+fn rgb((r, g, b): (f32, f32, f32)) -> Color {
+    Color::srgb(r, g, b)
+}
+
+fn rgba((r, g, b, a): (f32, f32, f32, f32)) -> Color {
+    Color::srgba(r, g, b, a)
+}
+
+impl Theme {
+    pub fn parse(content: &str) -> Result<Self, String> {
+        let theme: Theme = ron::from_str(content).map_err(|err| err.to_string())?;
+        if let Some(letter) = theme
+            .pieces
+            .keys()
+            .find(|letter| PieceType::from_letter(**letter).is_none())
+        {
+            return Err(format!("invalid piece letter: {}", letter));
+        }
+        Ok(theme)
+    }
+
+    // 方块的颜色，主题没有覆盖的骨牌使用骨牌定义的颜色
+    pub fn block_color(&self, piece_set: &PieceSet, skin: BlockSkin) -> Color {
+        match skin {
+            BlockSkin::Piece(piece_type) => match self.pieces.get(&piece_type.letter()) {
+                Some(color) => rgb(*color),
+                None => piece_set
+                    .get(piece_type)
+                    .map_or(rgb(self.garbage), |piece| piece.color()),
+            },
+            BlockSkin::Garbage => rgb(self.garbage),
+        }
+    }
+
+    pub fn border_color(&self) -> Color {
+        rgb(self.border)
+    }
+
+    pub fn label_color(&self) -> Color {
+        rgb(self.label)
+    }
+
+    pub fn value_color(&self) -> Color {
+        rgb(self.value)
+    }
+
+    pub fn highlight_color(&self) -> Color {
+        rgb(self.highlight)
+    }
+
+    // 面板文字的字体，主题没有指定时使用默认字体
+    pub fn hud_font(&self, font_tff: &FontTff) -> Handle<Font> {
+        self.font_handle
+            .clone()
+            .unwrap_or_else(|| font_tff.sim_hei.clone())
+    }
+}
+
+// 加载 .theme.ron 文件，同时加载其中的字体和贴图
+#[derive(Default)]
+pub struct ThemeLoader;
+
+impl AssetLoader for ThemeLoader {
+    type Asset = Theme;
+    type Settings = ();
+    type Error = Box<dyn std::error::Error + Send + Sync>;
+
+    async fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader<'_>,
+        _settings: &'a (),
+        load_context: &'a mut LoadContext<'_>,
+    ) -> Result<Theme, Self::Error> {
+        let mut content = String::new();
+        reader.read_to_string(&mut content).await?;
+        let mut theme = Theme::parse(&content)?;
+        theme.font_handle = theme.font.clone().map(|path| load_context.load(path));
+        theme.block_texture_handle = theme.block_texture.clone().map(|path| load_context.load(path));
+        Ok(theme)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["theme.ron"]
+    }
+}
+
+// 所有可选的主题，第一个是默认主题
+#[derive(Debug, Resource)]
+pub struct Themes(pub Vec<Theme>);
+
+impl Default for Themes {
+    fn default() -> Self {
+        Self(vec![Theme::default()])
+    }
+}
+
+impl Themes {
+    // 在主题列表中循环选择
+    pub fn cycle(&self, name: &str, offset: i32) -> &Theme {
+        let index = self.0.iter().position(|theme| theme.name == name).unwrap_or(0) as i32;
+        &self.0[(index + offset).rem_euclid(self.0.len() as i32) as usize]
+    }
+}
+
+// 加载的主题文件的句柄，保持句柄才不会被卸载
+#[derive(Debug, Resource)]
+pub struct ThemeHandles(pub Vec<Handle<Theme>>);
+
+pub fn load_themes(mut commands: Commands, asset_server: Res<AssetServer>) {
+    #[cfg(not(target_arch = "wasm32"))]
+    let paths = theme_paths();
+    // 网页版无法读取目录，只加载自带的主题
+    #[cfg(target_arch = "wasm32")]
+    let paths = ["classic", "neon"]
+        .iter()
+        .map(|name| format!("{}/{}.theme.ron", THEME_DIR, name))
+        .collect::<Vec<_>>();
+
+    let handles = paths.into_iter().map(|path| asset_server.load(path)).collect();
+    commands.insert_resource(ThemeHandles(handles));
+}
+
+// 主题目录下的所有主题文件，逐个加载，一个文件出错时不影响其他文件
+#[cfg(not(target_arch = "wasm32"))]
+fn theme_paths() -> Vec<String> {
+    let Ok(entries) = std::fs::read_dir(format!("assets/{}", THEME_DIR)) else {
+        return Vec::new();
+    };
+    let mut paths: Vec<String> = entries
+        .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+        .filter(|name| name.ends_with(".theme.ron"))
+        .map(|name| format!("{}/{}", THEME_DIR, name))
+        .collect();
+    paths.sort();
+    paths
+}
+
+// 主题文件加载完成或修改后重新生成主题列表，同名的主题代替内置的主题
+pub fn update_themes(
+    mut asset_events: EventReader<AssetEvent<Theme>>,
+    handles: Option<Res<ThemeHandles>>,
+    assets: Res<Assets<Theme>>,
+    mut themes: ResMut<Themes>,
+) {
+    if asset_events.is_empty() {
+        return;
+    }
+    asset_events.clear();
+    let mut list = vec![Theme::default()];
+    for theme in handles.iter().flat_map(|handles| handles.0.iter()).filter_map(|handle| assets.get(handle)) {
+        match list.iter_mut().find(|item| item.name == theme.name) {
+            Some(item) => *item = theme.clone(),
+            None => list.push(theme.clone()),
+        }
+    }
+    list[1..].sort_by(|a, b| a.name.cmp(&b.name));
+    themes.0 = list;
+}
+
+// 使用设置中选择的主题
+pub fn select_theme(settings: Res<GameSettings>, themes: Res<Themes>, mut theme: ResMut<Theme>) {
+    let selected = themes.cycle(&settings.theme, 0);
+    if *theme != *selected {
+        *theme = selected.clone();
+    }
+}
+
+// 方块的种类，颜色和贴图由当前主题决定
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockSkin {
+    Piece(PieceType),
+    Garbage,
+}
+
+impl BlockSkin {
+    // 谜题和编辑器中的方块，不在当前骨牌中的字母作为垃圾方块
+    pub fn cell(piece_set: &PieceSet, piece_type: Option<PieceType>) -> Self {
+        match piece_type {
+            Some(piece_type) if piece_set.get(piece_type).is_some() => BlockSkin::Piece(piece_type),
+            _ => BlockSkin::Garbage,
+        }
+    }
+}
+
+// 游戏面板和编辑器面板的边框
+#[derive(Debug, Component)]
+pub struct BoardFrame;
+
+// 游戏面板和编辑器面板的背景
+#[derive(Debug, Component)]
+pub struct BoardBackground;
+
+// 面板上的网格线
+#[derive(Debug, Component)]
+pub struct GridLine;
+
+// 面板旁边的文字，第一段使用该颜色和主题字体，其余段落（数值）使用数值颜色
+#[derive(Debug, Component, Clone, Copy, PartialEq, Eq)]
+pub enum HudText {
+    Label,
+    Highlight,
+}
+
+pub fn apply_clear_color(theme: Res<Theme>, mut clear_color: ResMut<ClearColor>) {
+    clear_color.0 = rgb(theme.background);
+}
+
+// 面板的背景和网格线是精灵，边框是占位节点的轮廓
+pub fn apply_board_theme(
+    theme: Res<Theme>,
+    mut frames: Query<(Ref<BoardFrame>, &mut Outline)>,
+    mut backgrounds: Query<(Ref<BoardBackground>, &mut Sprite), Without<GridLine>>,
+    mut grid_lines: Query<(Ref<GridLine>, &mut Sprite), Without<BoardBackground>>,
+) {
+    for (frame, mut outline) in &mut frames {
+        if theme.is_changed() || frame.is_added() {
+            outline.color = theme.border_color();
+        }
+    }
+    for (background, mut sprite) in &mut backgrounds {
+        if theme.is_changed() || background.is_added() {
+            sprite.color = rgba(theme.board_background);
+        }
+    }
+    for (grid_line, mut sprite) in &mut grid_lines {
+        if theme.is_changed() || grid_line.is_added() {
+            sprite.color = theme.grid.map_or(Color::NONE, rgba);
+        }
+    }
+}
+
+// 切换主题或骨牌定义修改后重新设置所有方块的颜色和贴图，新生成的方块也在这里设置
+pub fn apply_block_skins(
+    theme: Res<Theme>,
+    piece_set: Res<PieceSet>,
+    // 正在消除的方块由消行动画设置颜色
+    mut sprites: Query<
+        (Ref<BlockSkin>, &mut Sprite, &mut Handle<Image>),
+        Without<ClearingBlock>,
+    >,
+) {
+    let refresh_all = theme.is_changed() || piece_set.is_changed();
+    for (skin, mut sprite, mut texture) in &mut sprites {
+        if !refresh_all && !skin.is_added() {
+            continue;
+        }
+        sprite.color = theme.block_color(&piece_set, *skin);
+        // 默认贴图是纯白色，与颜色相乘后就是纯色方块
+        *texture = theme.block_texture_handle.clone().unwrap_or_default();
+    }
+}
+
+pub fn apply_hud_theme(
+    theme: Res<Theme>,
+    font_tff: Option<Res<FontTff>>,
+    mut query: Query<(Ref<HudText>, &mut Text)>,
+) {
+    let Some(font_tff) = font_tff else {
+        return;
+    };
+    let font = theme.hud_font(&font_tff);
+    for (hud_text, mut text) in &mut query {
+        if !theme.is_changed() && !hud_text.is_added() {
+            continue;
+        }
+        let Some((first, rest)) = text.sections.split_first_mut() else {
+            continue;
+        };
+        first.style.font = font.clone();
+        first.style.color = match *hud_text {
+            HudText::Label => theme.label_color(),
+            HudText::Highlight => theme.highlight_color(),
+        };
+        for section in rest {
+            section.style.color = theme.value_color();
+        }
+    }
+}
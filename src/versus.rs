@@ -0,0 +1,227 @@
+use bevy::prelude::*;
+
+use crate::{
+    board::{Block, MainBoard, ROW_COUNT},
+    garbage::{insert_garbage_rows, GarbageGenerator},
+    mode::GameConfig,
+    network::{NetMessage, NetSession, RemotePlayer},
+    piece::{LinesCleared, PieceType},
+    player::Player,
+    scoring::ClearResult,
+    state::SessionStats,
+};
+
+// 每次骨牌固定后最多升起的垃圾行数，超出的部分留到下一次
+pub const MAX_GARBAGE_PER_LOCK: u32 = 8;
+
+// 垃圾行槽颜色
+const METER_COLOR: Color = Color::srgb(0.9, 0.2, 0.2);
+
+// 等待升起的垃圾行数
+#[derive(Debug, Default, Component)]
+pub struct IncomingGarbage(pub u32);
+
+// 面板左侧的垃圾行槽，高度表示等待升起的垃圾行数
+#[derive(Debug, Component)]
+pub struct GarbageMeter;
+
+// 攻击表：一次消行发送给对手的垃圾行数
+//
+// back_to_back 为消行前的状态，combo 为消行后的连击数
+pub fn attack_lines(result: ClearResult, back_to_back: bool, combo: Option<u32>) -> u32 {
+    if result.lines == 0 {
+        return 0;
+    }
+    if result.perfect_clear {
+        return 10;
+    }
+    let base = match (result.t_spin, result.lines) {
+        (false, 1) => 0,
+        (false, 2) => 1,
+        (false, 3) => 2,
+        (false, _) => 4,
+        (true, 1) => 2,
+        (true, 2) => 4,
+        (true, _) => 6,
+    };
+    let difficult = result.t_spin || result.lines >= 4;
+    let back_to_back_bonus = if difficult && back_to_back { 1 } else { 0 };
+    let combo_bonus = match combo.unwrap_or(0) {
+        0 => 0,
+        1..=2 => 1,
+        3..=4 => 2,
+        5..=6 => 3,
+        7..=9 => 4,
+        _ => 5,
+    };
+    base + back_to_back_bonus + combo_bonus
+}
+
+// 结算攻击：消行时先抵消自己等待升起的垃圾行，剩余的发送给对手；没有消行时升起等待中的垃圾行
+//
+// 网络对战时只结算本机的面板，攻击通过网络发送，对手面板的变化由对手发来的面板状态同步
+pub fn apply_garbage_attacks(
+    mut commands: Commands,
+    config: Res<GameConfig>,
+    mut session: Option<ResMut<NetSession>>,
+    mut lines_cleared_events: EventReader<LinesCleared>,
+    mut main_board: Query<
+        (
+            Entity,
+            &mut IncomingGarbage,
+            &mut GarbageGenerator,
+            &mut SessionStats,
+            Has<RemotePlayer>,
+        ),
+        With<MainBoard>,
+    >,
+    mut board_blocks: Query<(&Parent, &mut Block), Without<PieceType>>,
+    mut piece_blocks: Query<(&Parent, &mut Block), With<PieceType>>,
+) {
+    if config.mode.player_count() < 2 {
+        lines_cleared_events.clear();
+        return;
+    }
+    for event in lines_cleared_events.read() {
+        if main_board
+            .get(event.board)
+            .is_ok_and(|(.., remote)| remote)
+        {
+            continue;
+        }
+        if event.lines == 0 {
+            let Ok((_, mut incoming, mut generator, ..)) = main_board.get_mut(event.board) else {
+                continue;
+            };
+            let rows = incoming.0.min(MAX_GARBAGE_PER_LOCK);
+            incoming.0 -= rows;
+            let holes: Vec<i32> = (0..rows).map(|_| generator.next_hole()).collect();
+            insert_garbage_rows(
+                &mut commands,
+                event.board,
+                &mut board_blocks,
+                &mut piece_blocks,
+                &holes,
+            );
+            continue;
+        }
+
+        let mut attack = event.attack;
+        if let Ok((_, mut incoming, _, mut stats, _)) = main_board.get_mut(event.board) {
+            let cancelled = attack.min(incoming.0);
+            incoming.0 -= cancelled;
+            attack -= cancelled;
+            stats.garbage_sent += attack;
+        }
+        if attack == 0 {
+            continue;
+        }
+        if let Some(session) = session.as_mut() {
+            session.send(&NetMessage::Garbage(attack));
+            continue;
+        }
+        for (entity, mut incoming, ..) in &mut main_board {
+            if entity != event.board {
+                incoming.0 += attack;
+            }
+        }
+    }
+}
+
+// 在面板左侧生成垃圾行槽
+pub fn spawn_garbage_meter(parent: &mut ChildBuilder, player: Player, height: f32) {
+    parent
+        .spawn(NodeBundle {
+            style: Style {
+                width: Val::Px(8.),
+                height: Val::Px(height),
+                flex_direction: FlexDirection::Column,
+                justify_content: JustifyContent::FlexEnd,
+                ..default()
+            },
+            background_color: BackgroundColor(Color::srgb(0.15, 0.15, 0.15)),
+            ..default()
+        })
+        .with_children(|parent| {
+            parent.spawn((
+                NodeBundle {
+                    style: Style {
+                        width: Val::Percent(100.),
+                        height: Val::Percent(0.),
+                        ..default()
+                    },
+                    background_color: BackgroundColor(METER_COLOR),
+                    ..default()
+                },
+                GarbageMeter,
+                player,
+            ));
+        });
+}
+
+// 更新垃圾行槽高度
+pub fn update_garbage_meter(
+    main_board: Query<(&Player, &IncomingGarbage), With<MainBoard>>,
+    mut query: Query<(&Player, &mut Style), With<GarbageMeter>>,
+) {
+    for (meter_player, mut style) in &mut query {
+        for (player, incoming) in &main_board {
+            if player == meter_player {
+                let rows = incoming.0.min(ROW_COUNT as u32);
+                style.height = Val::Percent(rows as f32 / ROW_COUNT as f32 * 100.0);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clear(lines: u32, t_spin: bool) -> ClearResult {
+        ClearResult {
+            lines,
+            t_spin,
+            perfect_clear: false,
+        }
+    }
+
+    #[test]
+    fn attack_table() {
+        let cases = [
+            (clear(0, false), 0),
+            (clear(1, false), 0),
+            (clear(2, false), 1),
+            (clear(3, false), 2),
+            (clear(4, false), 4),
+            (clear(0, true), 0),
+            (clear(1, true), 2),
+            (clear(2, true), 4),
+            (clear(3, true), 6),
+        ];
+        for (result, lines) in cases {
+            assert_eq!(attack_lines(result, false, None), lines, "{:?}", result);
+        }
+    }
+
+    #[test]
+    fn attack_bonuses() {
+        // Back-to-Back 只对困难消除加 1 行
+        assert_eq!(attack_lines(clear(4, false), true, None), 5);
+        assert_eq!(attack_lines(clear(2, true), true, None), 5);
+        assert_eq!(attack_lines(clear(2, false), true, None), 1);
+        // 连击加成
+        let combos: Vec<u32> = [0, 1, 2, 3, 4, 5, 6, 7, 9, 10]
+            .into_iter()
+            .map(|combo| attack_lines(clear(1, false), false, Some(combo)))
+            .collect();
+        assert_eq!(combos, vec![0, 1, 1, 2, 2, 3, 3, 4, 4, 5]);
+        // 全消固定 10 行
+        let result = ClearResult {
+            lines: 1,
+            t_spin: false,
+            perfect_clear: true,
+        };
+        assert_eq!(attack_lines(result, true, Some(3)), 10);
+    }
+}